@@ -1,59 +1,151 @@
-use crate::{config::Config, size::SizeWorker, state::PersistentState, tree, ui};
+use crate::{
+    config::Config,
+    fileops, gc,
+    keymap::{Action, KeyChord, Lookup},
+    preview::{Preview, PreviewWorker},
+    search::{self, SearchIndexer},
+    size::{DirSize, SizeDisplay, SizeState, SizeWorker},
+    sizecache::SizeCache,
+    state::{self, PersistentState, SavedView, Session},
+    trash, tree, ui,
+    undo::{FileOp, UndoStack},
+    watcher::{ConfigWatcher, FsWatcher},
+};
 use color_eyre::Result;
+use crossbeam_channel::Receiver;
 use crossterm::event::{
-    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
-    MouseEventKind,
+    self, Event, KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEvent, MouseEventKind,
 };
 use ratatui::{layout::Rect, prelude::*};
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
 use std::io::BufWriter;
-use std::path::PathBuf;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tui_input::backend::crossterm::EventHandler;
 use tui_input::Input;
 use tui_tree_widget::{TreeItem, TreeState};
 
-fn fuzzy_score(haystack: &str, needle: &[char]) -> Option<u16> {
-    if needle.is_empty() {
-        return Some(0);
-    }
-    let mut score: u16 = 0;
-    let mut needle_idx = 0;
-    let mut prev_match = false;
-    let mut prev_was_separator = true;
-
-    for c in haystack.chars() {
-        let is_separator = c == '/' || c == '.' || c == '_' || c == '-' || c == ' ';
-        if needle_idx < needle.len() && c == needle[needle_idx] {
-            score += if prev_was_separator {
-                10
-            } else if prev_match {
-                5
-            } else {
-                1
-            };
-            needle_idx += 1;
-            prev_match = true;
-        } else {
-            prev_match = false;
-        }
-        prev_was_separator = is_separator;
+/// Returns the tree identifier for `path`: every ancestor below `root`, ending with `path`.
+fn tree_identifier(root: &Path, path: &Path) -> Vec<PathBuf> {
+    let mut identifier: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|p| p.starts_with(root) && *p != root)
+        .map(Path::to_path_buf)
+        .collect();
+    identifier.reverse();
+    identifier
+}
+
+fn open_expanded_dirs(
+    tree_state: &mut TreeState<PathBuf>,
+    root: &Path,
+    expanded_dirs: &HashSet<PathBuf>,
+) {
+    for expanded in expanded_dirs {
+        tree_state.open(tree_identifier(root, expanded));
     }
+}
+
+/// Search results shown at most.
+const MAX_SEARCH_MATCHES: usize = 50;
+
+/// The paths whose names fuzzily match `query`, with their scores.
+fn score_paths(paths: &[PathBuf], query: &str) -> Vec<(PathBuf, u16)> {
+    let query_chars: Vec<char> = query.to_lowercase().chars().collect();
+    paths
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().to_lowercase();
+            search::fuzzy_score(&name, &query_chars).map(|score| (path.clone(), score))
+        })
+        .collect()
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
 
-    if needle_idx == needle.len() {
-        Some(score)
-    } else {
-        None
+/// The banner for a config with problems: the first one, how many more there
+/// are, then `advice`.
+fn config_warning(config: &Config, advice: &str) -> Option<String> {
+    let first = config.errors.first()?;
+    let more = match config.errors.len() {
+        1 => String::new(),
+        n => format!(" (and {} more)", n - 1),
+    };
+    Some(format!(
+        "{}{}, {}",
+        first.in_file(Path::new("config.toml")),
+        more,
+        advice
+    ))
+}
+
+/// Which kinds of entries `Enter` accepts when treenav is used as a picker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PickMode {
+    Files,
+    Dirs,
+    Any,
+}
+
+impl PickMode {
+    fn accepts(self, path: &Path) -> bool {
+        match self {
+            PickMode::Files => !path.is_dir(),
+            PickMode::Dirs => path.is_dir(),
+            PickMode::Any => true,
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Picker settings from `--pick` and `--multi`.
+#[derive(Debug, Clone, Copy)]
+pub struct Picker {
+    pub mode: PickMode,
+    /// Print every marked path instead of only the entry under the cursor
+    pub multi: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum ViewMode {
+    #[default]
     Tree,
     Starred,
     Bookmarks,
     Recent,
+    Trash,
+    DiskUsage,
+}
+
+impl From<ViewMode> for SavedView {
+    fn from(mode: ViewMode) -> Self {
+        match mode {
+            ViewMode::Tree => SavedView::Tree,
+            ViewMode::Starred => SavedView::Starred,
+            ViewMode::Bookmarks => SavedView::Bookmarks,
+            ViewMode::Recent => SavedView::Recent,
+            ViewMode::Trash => SavedView::Trash,
+            ViewMode::DiskUsage => SavedView::DiskUsage,
+        }
+    }
+}
+
+impl From<SavedView> for ViewMode {
+    fn from(view: SavedView) -> Self {
+        match view {
+            SavedView::Tree => ViewMode::Tree,
+            SavedView::Starred => ViewMode::Starred,
+            SavedView::Bookmarks => ViewMode::Bookmarks,
+            SavedView::Recent => ViewMode::Recent,
+            SavedView::Trash => ViewMode::Trash,
+            SavedView::DiskUsage => ViewMode::DiskUsage,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -62,6 +154,18 @@ pub enum InputMode {
     Normal,
     Search,
     BookmarkLabel,
+    Visual,
+    CreateFile,
+    CreateDir,
+    Rename,
+    ConfirmDelete,
+}
+
+/// Paths yanked with copy or cut, waiting to be pasted.
+#[derive(Debug, Clone)]
+pub struct Clipboard {
+    pub paths: Vec<PathBuf>,
+    pub cut: bool,
 }
 
 pub struct App {
@@ -72,7 +176,8 @@ pub struct App {
     pub config: Config,
     pub should_quit: bool,
     pub visible_height: u16,
-    pub selected_dir: Option<PathBuf>,
+    pub selected_paths: Vec<PathBuf>,
+    pub picker: Option<Picker>,
     pub view_mode: ViewMode,
     pub input_mode: InputMode,
     pub show_help: bool,
@@ -81,44 +186,148 @@ pub struct App {
     pub search_matches: Vec<(PathBuf, u16)>,
     pub search_index: usize,
     search_paths_cache: Vec<PathBuf>,
+    search_paths_seen: HashSet<PathBuf>,
+    search_indexer: Option<SearchIndexer>,
     pub show_preview: bool,
+    /// The file preview shown, which may lag behind the selection
+    pub preview: Option<Preview>,
+    preview_worker: PreviewWorker,
     pub bookmark_input: Input,
     pub bookmark_path: Option<PathBuf>,
-    pub dir_sizes: HashMap<PathBuf, Option<u64>>,
+    pub file_op_input: Input,
+    pub file_op_path: Option<PathBuf>,
+    pub delete_paths: Vec<PathBuf>,
+    pub marked: HashSet<PathBuf>,
+    visual_anchor: Vec<PathBuf>,
+    visual_base: HashSet<PathBuf>,
+    pub clipboard: Option<Clipboard>,
+    pub status_message: Option<String>,
+    /// Problems in config.toml, shown above the tree until a key is pressed
+    pub config_warning: Option<String>,
+    /// Problems found while saving state and sizes on exit, printed once
+    /// the terminal is restored
+    pub exit_warnings: Vec<String>,
+    undo_stack: UndoStack,
+    /// Results of the startup pass over saved paths, until they arrive
+    gc_report: Option<Receiver<gc::GcReport>>,
+    pub dir_sizes: HashMap<PathBuf, SizeState>,
     size_worker: SizeWorker,
+    /// Sizes from earlier runs, and the ones measured in this one
+    size_cache: SizeCache,
+    /// `[sizes]`, with apparent sizes toggled at runtime
+    pub size_display: SizeDisplay,
+    /// The directory whose entries the disk usage view lists
+    pub du_dir: PathBuf,
+    /// Totals of `du_dir`, for the disk usage view's title
+    pub du_usage: tree::DiskUsage,
+    /// The entries of `du_dir` as last read
+    du_entries: Vec<tree::UsageEntry>,
+    fs_watcher: FsWatcher,
+    config_watcher: Option<ConfigWatcher>,
     saved_view_items: Option<Vec<TreeItem<'static, PathBuf>>>,
     saved_selection: Option<Vec<PathBuf>>,
+    /// Name given with `--session`, saved again on exit
+    session: Option<String>,
+    /// Scroll offset to restore once the first frame has been laid out
+    pending_offset: Option<usize>,
+    /// Keys typed so far towards a multi-key binding like `gg`
+    pub pending_keys: Vec<KeyChord>,
     last_click_time: std::time::Instant,
     last_click_row: u16,
 }
 
 impl App {
-    pub fn new(path: PathBuf) -> Result<Self> {
-        let persistent_state = PersistentState::load();
+    /// Opens `path`, or the root of `session` if no path is given, or else the
+    /// current directory. With `one_file_system`, directory sizes leave out
+    /// other filesystems mounted below it.
+    pub fn new(
+        path: Option<PathBuf>,
+        picker: Option<Picker>,
+        session: Option<String>,
+        one_file_system: bool,
+    ) -> Result<Self> {
+        let mut persistent_state = PersistentState::load();
+        let saved_session = session
+            .as_ref()
+            .and_then(|name| persistent_state.sessions.get(name))
+            .cloned();
+        let path = match path {
+            Some(path) => path,
+            None => match saved_session.as_ref().filter(|s| s.root.is_dir()) {
+                Some(saved) => saved.root.clone(),
+                None => std::env::current_dir()?.canonicalize()?,
+            },
+        };
+        if let Some(saved) = saved_session.as_ref().filter(|s| s.root == path) {
+            persistent_state.set_root(&path, saved.tree.clone());
+        }
+
+        let gc_report = Some(gc::spawn(persistent_state.clone()));
+        let status_message = persistent_state.load_error.clone();
+        let device = one_file_system
+            .then(|| fs::metadata(&path).map(|m| m.dev()))
+            .transpose()?;
+        let size_cache = SizeCache::load(device);
         let config = Config::load();
+        let config_warning = config_warning(&config, "run `treenav config check` for details");
         let items = tree::build_tree(
             &path,
-            &persistent_state.expanded_dirs,
-            &persistent_state.starred_dirs,
-            persistent_state.show_hidden,
-            None,
+            &tree::TreeOptions {
+                expanded_dirs: persistent_state.expanded_dirs(&path),
+                starred_dirs: &persistent_state.starred_dirs,
+                marked: &HashSet::new(),
+                show_hidden: persistent_state.show_hidden,
+                dir_sizes: None,
+                theme: &config.theme,
+                colors: &config.colors,
+                icons: &config.icons,
+                sizes: config.sizes,
+            },
         )?;
 
+        let root_state = persistent_state
+            .roots
+            .get(&path)
+            .cloned()
+            .unwrap_or_default();
         let mut tree_state = TreeState::default();
-        for expanded in &persistent_state.expanded_dirs {
-            tree_state.open(vec![expanded.clone()]);
+        open_expanded_dirs(&mut tree_state, &path, &root_state.expanded_dirs);
+        // The saved selection only counts if it is still visible in the tree
+        let selection = root_state
+            .selected
+            .as_ref()
+            .filter(|p| p.exists() && p.starts_with(&path) && **p != path)
+            .map(|p| tree_identifier(&path, p))
+            .filter(|id| {
+                id[..id.len() - 1]
+                    .iter()
+                    .all(|dir| root_state.expanded_dirs.contains(dir))
+            });
+        match selection {
+            Some(selection) => {
+                tree_state.select(selection);
+            }
+            None => {
+                if let Some(first) = items.first() {
+                    tree_state.select(vec![first.identifier().clone()]);
+                }
+            }
         }
-        tree_state.select_first();
 
-        Ok(Self {
+        let size_display = config.sizes;
+        let mut app = Self {
             tree_state,
             items,
+            du_dir: path.clone(),
+            du_usage: tree::DiskUsage::default(),
+            du_entries: Vec::new(),
             root_path: path,
             persistent_state,
             config,
             should_quit: false,
             visible_height: 20,
-            selected_dir: None,
+            selected_paths: Vec::new(),
+            picker,
             view_mode: ViewMode::Tree,
             input_mode: InputMode::Normal,
             show_help: false,
@@ -127,16 +336,48 @@ impl App {
             search_matches: Vec::new(),
             search_index: 0,
             search_paths_cache: Vec::new(),
+            search_paths_seen: HashSet::new(),
+            search_indexer: None,
             show_preview: false,
+            preview: None,
+            preview_worker: PreviewWorker::new(),
             bookmark_input: Input::default(),
             bookmark_path: None,
+            file_op_input: Input::default(),
+            file_op_path: None,
+            delete_paths: Vec::new(),
+            marked: HashSet::new(),
+            visual_anchor: Vec::new(),
+            visual_base: HashSet::new(),
+            clipboard: None,
+            status_message,
+            config_warning,
+            exit_warnings: Vec::new(),
+            undo_stack: UndoStack::default(),
+            gc_report,
             dir_sizes: HashMap::new(),
-            size_worker: SizeWorker::new(),
+            size_worker: SizeWorker::new(device, size_cache.records()),
+            size_cache,
+            size_display,
+            fs_watcher: FsWatcher::new(),
+            config_watcher: Config::config_file_path().and_then(|p| ConfigWatcher::new(&p)),
             saved_view_items: None,
             saved_selection: None,
+            session,
+            pending_offset: Some(root_state.offset),
+            pending_keys: Vec::new(),
             last_click_time: std::time::Instant::now(),
             last_click_row: 0,
-        })
+        };
+        app.sync_watches();
+        if let Some(saved) = saved_session {
+            app.show_preview = saved.show_preview;
+            let view_mode = ViewMode::from(saved.view_mode);
+            if view_mode != ViewMode::Tree {
+                app.enter_list_view(view_mode);
+            }
+        }
+        Ok(app)
     }
 
     pub fn run(
@@ -144,10 +385,18 @@ impl App {
         terminal: &mut Terminal<CrosstermBackend<BufWriter<File>>>,
     ) -> Result<()> {
         while !self.should_quit {
-            // Poll for size calculation results
-            self.size_worker.poll_results(&mut self.dir_sizes);
+            self.poll_sizes();
+            self.poll_search_index();
+            self.poll_fs_changes();
+            self.poll_config_changes();
+            self.poll_preview();
+            self.poll_gc();
 
             terminal.draw(|frame| ui::render(frame, self))?;
+            if let Some(offset) = self.pending_offset.take() {
+                self.restore_offset(offset);
+                terminal.draw(|frame| ui::render(frame, self))?;
+            }
 
             if event::poll(Duration::from_millis(50))? {
                 match event::read()? {
@@ -158,10 +407,71 @@ impl App {
             }
         }
 
-        self.persistent_state.save()?;
+        self.remember_position();
+        if let Err(e) = self.size_cache.save() {
+            self.exit_warnings
+                .push(format!("Could not save directory sizes: {}", e));
+        }
+        self.exit_warnings.extend(self.persistent_state.save()?);
         Ok(())
     }
 
+    /// Scrolls back to a saved offset. This needs the layout of a rendered
+    /// frame, as the tree widget clamps scrolling to what it last drew.
+    fn restore_offset(&mut self, offset: usize) {
+        self.tree_state.scroll_up(usize::MAX);
+        self.tree_state.scroll_down(offset);
+        // Keep the selection in view in case the window got smaller
+        let selected = self.tree_state.selected().to_vec();
+        self.tree_state.select(selected);
+    }
+
+    /// Records the tree position for this root, and the named session if any.
+    fn remember_position(&mut self) {
+        // In a list view, the tree position is the one saved on entering it
+        let (selection, offset) = match self.view_mode {
+            ViewMode::Tree => (
+                Some(self.tree_state.selected().to_vec()),
+                Some(self.tree_state.get_offset()),
+            ),
+            _ => (self.saved_selection.clone(), None),
+        };
+        let mut root_state = self
+            .persistent_state
+            .roots
+            .get(&self.root_path)
+            .cloned()
+            .unwrap_or_default();
+        if let Some(selection) = selection {
+            // The tree selects its first entry anyway, so that is not worth keeping
+            let first = self
+                .items
+                .first()
+                .filter(|_| self.view_mode == ViewMode::Tree)
+                .map(|item| item.identifier());
+            root_state.selected = selection
+                .last()
+                .filter(|p| selection.len() > 1 || Some(*p) != first)
+                .cloned();
+        }
+        if let Some(offset) = offset {
+            root_state.offset = offset;
+        }
+        self.persistent_state
+            .set_root(&self.root_path, root_state.clone());
+
+        if let Some(name) = &self.session {
+            let session = Session {
+                root: self.root_path.clone(),
+                tree: root_state,
+                show_preview: self.show_preview,
+                view_mode: self.view_mode.into(),
+                saved_at: state::now(),
+            };
+            self.persistent_state.sessions.insert(name.clone(), session);
+        }
+    }
+
     fn handle_key(&mut self, key: KeyEvent) {
         if key.kind != KeyEventKind::Press {
             return;
@@ -173,99 +483,182 @@ impl App {
             return;
         }
 
+        // Status messages last until the next key press
+        self.status_message = None;
+        self.config_warning = None;
+
         match self.input_mode {
             InputMode::Normal => self.handle_normal_key(key),
             InputMode::Search => self.handle_search_key(key),
             InputMode::BookmarkLabel => self.handle_bookmark_label_key(key),
+            InputMode::Visual => self.handle_visual_key(key),
+            InputMode::CreateFile | InputMode::CreateDir | InputMode::Rename => {
+                self.handle_file_op_input_key(key)
+            }
+            InputMode::ConfirmDelete => self.handle_confirm_delete_key(key),
         }
     }
 
-    fn handle_normal_key(&mut self, key: KeyEvent) {
-        match (key.modifiers, key.code) {
-            (KeyModifiers::CONTROL, KeyCode::Char('c')) => self.should_quit = true,
-            (_, KeyCode::Char('q')) => self.should_quit = true,
-            (_, KeyCode::Esc) => self.should_quit = true,
-
-            // Help
-            (KeyModifiers::SHIFT, KeyCode::Char('?')) | (_, KeyCode::Char('?')) => {
-                self.show_help = true;
+    /// Feeds a key to the keymap, returning the action once a binding is
+    /// complete. Keys that lead nowhere are dropped, as is Esc mid-sequence.
+    fn resolve_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.code == KeyCode::Esc && !self.pending_keys.is_empty() {
+            self.pending_keys.clear();
+            return None;
+        }
+        self.pending_keys.push(KeyChord::from(key));
+        match self.config.keys.lookup(&self.pending_keys) {
+            Lookup::Action(action) => {
+                self.pending_keys.clear();
+                Some(action)
             }
-
-            // Search
-            (_, KeyCode::Char('/')) => {
-                self.enter_search_mode();
+            Lookup::Pending => None,
+            Lookup::Unbound => {
+                self.pending_keys.clear();
+                None
             }
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: KeyEvent) {
+        if let Some(action) = self.resolve_key(key) {
+            self.run_action(action);
+        }
+    }
 
-            (_, KeyCode::Up) | (_, KeyCode::Char('k')) => {
+    fn run_action(&mut self, action: Action) {
+        let tree_view = self.view_mode == ViewMode::Tree;
+        let usage_view = self.view_mode == ViewMode::DiskUsage;
+        match action {
+            Action::Quit => self.should_quit = true,
+            Action::Help => self.show_help = true,
+            Action::Search => self.enter_search_mode(),
+
+            Action::Up => {
                 self.tree_state.key_up();
             }
-            (_, KeyCode::Down) | (_, KeyCode::Char('j')) => {
+            Action::Down => {
                 self.tree_state.key_down();
             }
-            (_, KeyCode::Left) | (_, KeyCode::Char('h')) => {
-                if self.view_mode == ViewMode::Tree {
-                    self.collapse_or_parent();
-                }
+            Action::Collapse if tree_view => self.collapse_or_parent(),
+            Action::Expand if tree_view => self.expand_selected(),
+            Action::Toggle if tree_view => self.toggle_selected(),
+            Action::Collapse if usage_view => self.leave_usage_dir(),
+            Action::Expand | Action::Toggle | Action::Open if usage_view => {
+                self.enter_usage_dir();
             }
-            (_, KeyCode::Right) | (_, KeyCode::Char('l')) => {
-                if self.view_mode == ViewMode::Tree {
-                    self.expand_selected();
-                }
-            }
-            (_, KeyCode::Char(' ')) => {
-                if self.view_mode == ViewMode::Tree {
-                    self.toggle_selected();
-                }
-            }
-            (_, KeyCode::Enter) => {
-                self.select_and_quit();
+            Action::Open if self.view_mode == ViewMode::Trash => {
+                self.restore_selected_from_trash();
             }
+            Action::Open => self.select_and_quit(),
 
-            // Star toggle
-            (_, KeyCode::Char('s')) => {
-                self.toggle_star();
-            }
-            // Switch view mode
-            (KeyModifiers::SHIFT, KeyCode::Char('S')) => {
-                self.toggle_view_mode();
-            }
-
-            (_, KeyCode::PageUp) => self.page_up(),
-            (_, KeyCode::PageDown) => self.page_down(),
-            (KeyModifiers::CONTROL, KeyCode::Char('u')) => self.half_page_up(),
-            (KeyModifiers::CONTROL, KeyCode::Char('d')) => self.half_page_down(),
+            Action::Star => self.toggle_star(),
+            Action::StarredView => self.toggle_view_mode(),
 
-            (_, KeyCode::Home) | (_, KeyCode::Char('g')) => {
+            Action::PageUp => self.page_up(),
+            Action::PageDown => self.page_down(),
+            Action::HalfPageUp => self.half_page_up(),
+            Action::HalfPageDown => self.half_page_down(),
+            Action::First => {
                 self.tree_state.select_first();
             }
-            (_, KeyCode::End) | (KeyModifiers::SHIFT, KeyCode::Char('G')) => {
+            Action::Last => {
                 self.tree_state.select_last();
             }
 
-            // Toggle hidden files
-            (_, KeyCode::Char('.')) => {
-                self.toggle_hidden();
-            }
-
-            // Toggle preview pane
-            (_, KeyCode::Char('p')) => {
-                self.show_preview = !self.show_preview;
+            Action::ToggleHidden => self.toggle_hidden(),
+            Action::TogglePreview => self.show_preview = !self.show_preview,
+
+            Action::Bookmark => self.add_or_edit_bookmark(),
+            Action::BookmarksView => self.toggle_list_view(ViewMode::Bookmarks),
+            Action::RecentView => self.toggle_list_view(ViewMode::Recent),
+
+            // File operations
+            Action::NewFile if tree_view => self.start_create(InputMode::CreateFile),
+            Action::NewDir if tree_view => self.start_create(InputMode::CreateDir),
+            Action::Rename if tree_view => self.start_rename(),
+            Action::Copy => self.yank_selected(false),
+            Action::Cut => self.yank_selected(true),
+            Action::Paste if tree_view => self.paste(),
+            Action::Trash if tree_view || usage_view => self.start_delete(),
+            Action::Undo => self.undo(),
+
+            // Marks
+            Action::Mark if tree_view || usage_view => {
+                self.toggle_mark();
+                self.tree_state.key_down();
             }
+            Action::ClearMarks => self.clear_marks(),
+            Action::Visual if tree_view => self.enter_visual_mode(),
+            Action::TrashView => self.toggle_list_view(ViewMode::Trash),
+            Action::DiskUsageView if usage_view => self.return_to_tree_view(),
+            Action::DiskUsageView => self.enter_usage_view(),
+            Action::ToggleApparentSize => self.toggle_apparent_size(),
+
+            // Tree-only actions in a list view
+            _ => {}
+        }
+    }
 
-            // Bookmarks
-            (_, KeyCode::Char('b')) => {
-                self.add_or_edit_bookmark();
+    fn handle_file_op_input_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => self.finish_file_op(),
+            KeyCode::Enter => {
+                let name = self.file_op_input.value().trim().to_string();
+                if let Some(path) = self.file_op_path.clone() {
+                    if !name.is_empty() {
+                        match self.input_mode {
+                            InputMode::CreateFile => self.create_entry(&path, &name, false),
+                            InputMode::CreateDir => self.create_entry(&path, &name, true),
+                            InputMode::Rename => self.rename_entry(&path, &name),
+                            _ => {}
+                        }
+                    }
+                }
+                self.finish_file_op();
             }
-            (KeyModifiers::SHIFT, KeyCode::Char('B')) => {
-                self.switch_to_bookmarks_view();
+            _ => {
+                let crossterm_event = crossterm::event::Event::Key(crossterm::event::KeyEvent {
+                    code: key.code,
+                    modifiers: key.modifiers,
+                    kind: crossterm::event::KeyEventKind::Press,
+                    state: crossterm::event::KeyEventState::NONE,
+                });
+                self.file_op_input.handle_event(&crossterm_event);
             }
+        }
+    }
 
-            // Recent directories
-            (_, KeyCode::Char('r')) => {
-                self.switch_to_recent_view();
+    fn handle_confirm_delete_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                for path in std::mem::take(&mut self.delete_paths) {
+                    self.delete_entry(&path);
+                }
+                if !self.marked.is_empty() {
+                    self.clear_marks();
+                }
+                self.finish_file_op();
             }
+            _ => self.finish_file_op(),
+        }
+    }
 
-            _ => {}
+    fn handle_visual_key(&mut self, key: KeyEvent) {
+        if key.code == KeyCode::Esc && self.pending_keys.is_empty() {
+            self.input_mode = InputMode::Normal;
+            return;
+        }
+        match self.resolve_key(key) {
+            Some(Action::Visual) => self.input_mode = InputMode::Normal,
+            // Quitting and other actions leave visual mode, keeping the marks
+            Some(action) => {
+                self.run_action(action);
+                if self.input_mode == InputMode::Visual {
+                    self.update_visual_marks();
+                }
+            }
+            None => {}
         }
     }
 
@@ -398,12 +791,22 @@ impl App {
     fn toggle_selected(&mut self) {
         if let Some(selected) = self.get_selected_path() {
             if selected.is_dir() {
-                if self.persistent_state.expanded_dirs.contains(&selected) {
-                    self.persistent_state.expanded_dirs.remove(&selected);
-                    self.tree_state.close(&[selected]);
+                let identifier = self.tree_state.selected().to_vec();
+                if self
+                    .persistent_state
+                    .expanded_dirs(&self.root_path)
+                    .contains(&selected)
+                {
+                    self.persistent_state
+                        .expanded_dirs_mut(&self.root_path)
+                        .remove(&selected);
+                    self.tree_state.close(&identifier);
+                    self.cancel_pending_sizes(|dir| dir.starts_with(&selected));
                 } else {
-                    self.persistent_state.expanded_dirs.insert(selected.clone());
-                    self.tree_state.open(vec![selected.clone()]);
+                    self.persistent_state
+                        .expanded_dirs_mut(&self.root_path)
+                        .insert(selected.clone());
+                    self.tree_state.open(identifier);
                     self.request_size_for_dir(&selected);
                 }
                 self.rebuild_tree();
@@ -412,6 +815,21 @@ impl App {
     }
 
     fn toggle_star(&mut self) {
+        if !self.marked.is_empty() {
+            let dirs: Vec<PathBuf> = self.marked.iter().filter(|p| p.is_dir()).cloned().collect();
+            let all_starred = dirs
+                .iter()
+                .all(|d| self.persistent_state.starred_dirs.contains(d));
+            for dir in dirs {
+                if all_starred {
+                    self.persistent_state.starred_dirs.remove(&dir);
+                } else {
+                    self.persistent_state.starred_dirs.insert(dir);
+                }
+            }
+            self.clear_marks();
+            return;
+        }
         if let Some(selected) = self.get_selected_path() {
             if selected.is_dir() {
                 if self.persistent_state.starred_dirs.contains(&selected) {
@@ -426,68 +844,252 @@ impl App {
 
     fn toggle_view_mode(&mut self) {
         match self.view_mode {
-            ViewMode::Tree => {
-                // Save current items before switching to starred
-                self.saved_view_items = Some(std::mem::take(&mut self.items));
-                self.saved_selection = Some(self.tree_state.selected().to_vec());
-                self.view_mode = ViewMode::Starred;
-                self.rebuild_tree();
-                self.tree_state = TreeState::default();
-                self.tree_state.select_first();
+            ViewMode::Tree => self.enter_list_view(ViewMode::Starred),
+            _ => self.return_to_tree_view(),
+        }
+    }
+
+    fn toggle_list_view(&mut self, mode: ViewMode) {
+        if self.view_mode == mode {
+            self.return_to_tree_view();
+        } else {
+            self.enter_list_view(mode);
+        }
+    }
+
+    fn enter_list_view(&mut self, mode: ViewMode) {
+        // Remember where we were in the tree, not in another list view
+        if self.view_mode == ViewMode::Tree {
+            self.saved_selection = Some(self.tree_state.selected().to_vec());
+        }
+        self.leave_usage_view();
+        self.view_mode = mode;
+        self.rebuild_tree();
+        self.tree_state = TreeState::default();
+        self.select_first_item();
+    }
+
+    /// Opens the disk usage view on the selected directory, the directory of
+    /// the selected file, or else the root.
+    fn enter_usage_view(&mut self) {
+        let selected = self.get_selected_path().filter(|p| p.exists());
+        self.du_dir = match selected {
+            Some(path) if path.is_dir() => path,
+            Some(path) if self.view_mode == ViewMode::Tree => path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| self.root_path.clone()),
+            _ => self.root_path.clone(),
+        };
+        self.enter_list_view(ViewMode::DiskUsage);
+    }
+
+    /// Drills down into the selected directory of the disk usage view.
+    fn enter_usage_dir(&mut self) {
+        let Some(selected) = self.get_selected_path() else {
+            return;
+        };
+        // Symlinked directories are listed by their own size, so don't follow them
+        if selected.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            self.du_dir = selected;
+            self.rebuild_tree();
+            self.tree_state = TreeState::default();
+            self.select_first_item();
+        }
+    }
+
+    /// Goes up to the parent directory in the disk usage view, with the
+    /// directory we came from selected.
+    fn leave_usage_dir(&mut self) {
+        let Some(parent) = self.du_dir.parent().map(Path::to_path_buf) else {
+            return;
+        };
+        let from = std::mem::replace(&mut self.du_dir, parent);
+        self.rebuild_tree();
+        self.tree_state = TreeState::default();
+        self.tree_state.select(vec![from]);
+    }
+
+    /// Stops measuring what only the disk usage view was waiting for.
+    fn leave_usage_view(&mut self) {
+        if self.view_mode != ViewMode::DiskUsage {
+            return;
+        }
+        let expanded_dirs = self.persistent_state.expanded_dirs(&self.root_path).clone();
+        self.cancel_pending_sizes(|dir| !expanded_dirs.contains(dir));
+    }
+
+    fn return_to_tree_view(&mut self) {
+        self.leave_usage_view();
+        self.view_mode = ViewMode::Tree;
+        self.rebuild_tree();
+        self.tree_state = TreeState::default();
+        open_expanded_dirs(
+            &mut self.tree_state,
+            &self.root_path,
+            self.persistent_state.expanded_dirs(&self.root_path),
+        );
+        match self.saved_selection.take() {
+            Some(sel) => {
+                self.tree_state.select(sel);
             }
-            ViewMode::Starred | ViewMode::Bookmarks | ViewMode::Recent => {
-                // Restore saved tree
-                self.view_mode = ViewMode::Tree;
-                if let Some(items) = self.saved_view_items.take() {
-                    self.items = items;
-                    self.rebuild_tree();
-                    self.tree_state = TreeState::default();
-                    if let Some(sel) = self.saved_selection.take() {
-                        self.tree_state.select(sel);
-                    } else {
-                        self.tree_state.select_first();
-                    }
-                } else {
-                    self.rebuild_tree();
-                    self.tree_state.select_first();
-                }
+            None => {
+                self.select_first_item();
             }
         }
     }
 
     fn select_and_quit(&mut self) {
+        if let Some(picker) = self.picker {
+            self.pick(picker);
+            return;
+        }
+        if !self.marked.is_empty() {
+            let mut marked: Vec<PathBuf> = self.marked.iter().cloned().collect();
+            marked.sort();
+            self.selected_paths = marked;
+            self.should_quit = true;
+            return;
+        }
         if let Some(selected) = self.get_selected_path() {
             if selected.is_dir() {
                 self.persistent_state.add_recent(selected.clone());
-                self.selected_dir = Some(selected);
+                self.selected_paths = vec![selected];
                 self.should_quit = true;
             }
         }
     }
 
+    fn pick(&mut self, picker: Picker) {
+        if picker.multi && !self.marked.is_empty() {
+            let picked: Vec<PathBuf> = self
+                .marked_paths()
+                .into_iter()
+                .filter(|p| picker.mode.accepts(p))
+                .collect();
+            if picked.is_empty() {
+                self.status_message = Some("No marked entry can be picked".to_string());
+            } else {
+                self.selected_paths = picked;
+                self.should_quit = true;
+            }
+            return;
+        }
+
+        let Some(selected) = self.get_selected_path() else {
+            return;
+        };
+        if picker.mode.accepts(&selected) {
+            if selected.is_dir() {
+                self.persistent_state.add_recent(selected.clone());
+            }
+            self.selected_paths = vec![selected];
+            self.should_quit = true;
+        } else if selected.is_dir() && self.view_mode == ViewMode::Tree {
+            // Picking files: Enter on a directory opens it instead
+            self.toggle_selected();
+        }
+    }
+
     fn expand_selected(&mut self) {
         if let Some(selected) = self.get_selected_path() {
-            if selected.is_dir() && !self.persistent_state.expanded_dirs.contains(&selected) {
-                self.persistent_state.expanded_dirs.insert(selected.clone());
-                self.tree_state.open(vec![selected.clone()]);
+            if selected.is_dir()
+                && !self
+                    .persistent_state
+                    .expanded_dirs(&self.root_path)
+                    .contains(&selected)
+            {
+                self.persistent_state
+                    .expanded_dirs_mut(&self.root_path)
+                    .insert(selected.clone());
+                self.tree_state.open(self.tree_state.selected().to_vec());
                 self.rebuild_tree();
                 self.request_size_for_dir(&selected);
             }
         }
     }
 
+    /// Measures `path` unless the cache has its size and nothing below it
+    /// changed, showing the cached size meanwhile if there is one.
     fn request_size_for_dir(&mut self, path: &PathBuf) {
-        if !self.dir_sizes.contains_key(path) {
-            self.dir_sizes.insert(path.clone(), None);
+        if self.dir_sizes.contains_key(path) {
+            return;
+        }
+        let state = self.size_cache.get(path).unwrap_or(SizeState::Pending);
+        self.dir_sizes.insert(path.clone(), state);
+        if state.is_pending() {
             self.size_worker.request_size(path.clone());
         }
     }
 
+    /// Takes in finished sizes, caching them, and refreshes the rows that
+    /// show sizes.
+    fn poll_sizes(&mut self) {
+        let results = self.size_worker.poll_results();
+        if results.is_empty() {
+            return;
+        }
+        for result in results {
+            self.dir_sizes.insert(result.path.clone(), result.state);
+            match result.state {
+                SizeState::Measured(_) => self.size_cache.insert(result.records),
+                _ => self.size_cache.forget(&result.path),
+            }
+        }
+        match self.view_mode {
+            ViewMode::Tree if self.input_mode != InputMode::Search => self.rebuild_tree(),
+            // The listing itself is unchanged, only the order and the sizes
+            ViewMode::DiskUsage => self.refresh_usage_list(),
+            _ => {}
+        }
+    }
+
+    /// Stops measuring the directories `cancel` returns true for, so other
+    /// sizes come in sooner.
+    fn cancel_pending_sizes(&mut self, cancel: impl Fn(&Path) -> bool) {
+        let size_worker = &mut self.size_worker;
+        self.dir_sizes.retain(|dir, size| {
+            if size.is_pending() && cancel(dir) {
+                size_worker.cancel(dir);
+                return false;
+            }
+            true
+        });
+    }
+
+    /// Measures the known directories `stale` returns true for again, showing
+    /// their old size as stale meanwhile.
+    fn remeasure_sizes(&mut self, stale: impl Fn(&Path) -> bool) {
+        let dirs: Vec<PathBuf> = self
+            .dir_sizes
+            .keys()
+            .filter(|dir| stale(dir))
+            .cloned()
+            .collect();
+        for dir in &dirs {
+            let state = match self.dir_sizes[dir].size() {
+                Some(size) => SizeState::Stale(size),
+                None => SizeState::Pending,
+            };
+            self.dir_sizes.insert(dir.clone(), state);
+            self.size_worker.request_size(dir.clone());
+        }
+    }
+
     fn collapse_or_parent(&mut self) {
         if let Some(selected) = self.get_selected_path() {
-            if selected.is_dir() && self.persistent_state.expanded_dirs.contains(&selected) {
-                self.persistent_state.expanded_dirs.remove(&selected);
-                self.tree_state.close(&[selected]);
+            if selected.is_dir()
+                && self
+                    .persistent_state
+                    .expanded_dirs(&self.root_path)
+                    .contains(&selected)
+            {
+                self.persistent_state
+                    .expanded_dirs_mut(&self.root_path)
+                    .remove(&selected);
+                let identifier = self.tree_state.selected().to_vec();
+                self.tree_state.close(&identifier);
+                self.cancel_pending_sizes(|dir| dir.starts_with(&selected));
                 self.rebuild_tree();
             } else {
                 self.tree_state.key_left();
@@ -497,27 +1099,238 @@ impl App {
         }
     }
 
+    fn toggle_apparent_size(&mut self) {
+        self.size_display.apparent = !self.size_display.apparent;
+        self.status_message = Some(
+            if self.size_display.apparent {
+                "Showing apparent sizes"
+            } else {
+                "Showing disk usage"
+            }
+            .to_string(),
+        );
+        self.rebuild_tree();
+    }
+
     fn toggle_hidden(&mut self) {
         self.persistent_state.show_hidden = !self.persistent_state.show_hidden;
         self.rebuild_tree();
     }
 
+    fn tree_options(&self) -> tree::TreeOptions<'_> {
+        tree::TreeOptions {
+            expanded_dirs: self.persistent_state.expanded_dirs(&self.root_path),
+            starred_dirs: &self.persistent_state.starred_dirs,
+            marked: &self.marked,
+            show_hidden: self.persistent_state.show_hidden,
+            dir_sizes: Some(&self.dir_sizes),
+            theme: &self.config.theme,
+            colors: &self.config.colors,
+            icons: &self.config.icons,
+            sizes: self.size_display,
+        }
+    }
+
     fn rebuild_tree(&mut self) {
         let items = match self.view_mode {
-            ViewMode::Tree => tree::build_tree(
-                &self.root_path,
-                &self.persistent_state.expanded_dirs,
-                &self.persistent_state.starred_dirs,
-                self.persistent_state.show_hidden,
-                Some(&self.dir_sizes),
-            ),
+            ViewMode::Tree => tree::build_tree(&self.root_path, &self.tree_options()),
             ViewMode::Starred => tree::build_starred_list(&self.persistent_state.starred_dirs),
             ViewMode::Bookmarks => tree::build_bookmarks_list(&self.persistent_state.bookmarks),
-            ViewMode::Recent => tree::build_recent_list(&self.persistent_state.recent_dirs),
+            ViewMode::Recent => tree::build_recent_list(&self.persistent_state.ranked_recent()),
+            ViewMode::Trash => tree::build_trash_list(&trash::list()),
+            ViewMode::DiskUsage => {
+                self.rebuild_usage_list();
+                return;
+            }
         };
         if let Ok(items) = items {
             self.items = items;
         }
+        if self.view_mode == ViewMode::Tree {
+            self.sync_watches();
+        }
+    }
+
+    /// Lists `du_dir` by size, asking for the size of any subdirectory not
+    /// measured yet.
+    fn rebuild_usage_list(&mut self) {
+        match tree::read_usage_entries(&self.du_dir) {
+            Ok(entries) => self.du_entries = entries,
+            Err(e) => {
+                self.du_entries.clear();
+                self.status_message = Some(format!("Can't read {}: {}", self.du_dir.display(), e));
+            }
+        }
+        let subdirs: Vec<PathBuf> = self
+            .du_entries
+            .iter()
+            .filter(|entry| entry.is_dir)
+            .map(|entry| entry.path.clone())
+            .collect();
+        for dir in &subdirs {
+            self.request_size_for_dir(dir);
+        }
+        self.refresh_usage_list();
+    }
+
+    /// Sorts the listing of `du_dir` again with the sizes known now.
+    fn refresh_usage_list(&mut self) {
+        let (items, usage) = tree::build_disk_usage_list(&self.du_entries, &self.tree_options());
+        self.items = items;
+        self.du_usage = usage;
+    }
+
+    /// Watches the root plus every expanded directory that is visible in the tree.
+    fn sync_watches(&mut self) {
+        let expanded_dirs = self.persistent_state.expanded_dirs(&self.root_path);
+        let mut dirs: HashSet<PathBuf> = expanded_dirs
+            .iter()
+            .filter(|dir| dir.starts_with(&self.root_path) && dir.is_dir())
+            .filter(|dir| {
+                tree_identifier(&self.root_path, dir)
+                    .iter()
+                    .all(|ancestor| expanded_dirs.contains(ancestor))
+            })
+            .cloned()
+            .collect();
+        dirs.insert(self.root_path.clone());
+        self.fs_watcher.sync(dirs);
+    }
+
+    fn poll_fs_changes(&mut self) {
+        // Leave events queued while the tree is replaced by search results or a list view
+        if self.view_mode != ViewMode::Tree || self.input_mode == InputMode::Search {
+            return;
+        }
+        let changes = self.fs_watcher.poll_changes();
+        if changes.is_empty() {
+            return;
+        }
+
+        // Read the previewed file again if it may have changed
+        if self
+            .preview
+            .as_ref()
+            .and_then(|preview| preview.path.parent())
+            .is_some_and(|dir| changes.touched.contains(dir))
+        {
+            self.preview = None;
+        }
+
+        // Any directory containing a change has a stale size
+        self.remeasure_sizes(|dir| changes.touched.iter().any(|t| t.starts_with(dir)));
+
+        // New sizes redraw the tree as they come in, so file contents
+        // changing alone leaves the listings as they are
+        if changes.listings.contains(&self.root_path) {
+            self.rebuild_tree();
+        } else if !changes.listings.is_empty() {
+            let mut dirs: Vec<&PathBuf> = changes.listings.iter().collect();
+            dirs.sort_by_key(|dir| dir.components().count());
+            let mut rebuilt: Vec<&PathBuf> = Vec::new();
+            for dir in dirs {
+                if rebuilt.iter().any(|r| dir.starts_with(r)) {
+                    continue;
+                }
+                let options = tree::TreeOptions {
+                    expanded_dirs: self.persistent_state.expanded_dirs(&self.root_path),
+                    starred_dirs: &self.persistent_state.starred_dirs,
+                    marked: &self.marked,
+                    show_hidden: self.persistent_state.show_hidden,
+                    dir_sizes: Some(&self.dir_sizes),
+                    theme: &self.config.theme,
+                    colors: &self.config.colors,
+                    icons: &self.config.icons,
+                    sizes: self.size_display,
+                };
+                tree::rebuild_subtree(&mut self.items, dir, &options);
+                rebuilt.push(dir);
+            }
+            self.sync_watches();
+        }
+
+        // If the selected entry was removed, fall back to its closest surviving ancestor
+        if self
+            .tree_state
+            .selected()
+            .last()
+            .is_some_and(|p| !p.exists())
+        {
+            self.select_closest_existing(self.tree_state.selected().to_vec());
+        }
+    }
+
+    /// Reloads config.toml after it changes.
+    fn poll_config_changes(&mut self) {
+        if !self
+            .config_watcher
+            .as_mut()
+            .is_some_and(|watcher| watcher.poll_changed())
+        {
+            return;
+        }
+        self.reload_config(Config::load());
+    }
+
+    /// Applies a reloaded config. A config with problems is not applied, so
+    /// a half-finished edit never undoes settings that worked.
+    fn reload_config(&mut self, config: Config) {
+        if !config.errors.is_empty() {
+            self.config_warning = config_warning(&config, "keeping the previous config");
+            return;
+        }
+        // Keep a toggled size display unless [sizes] itself changed
+        if config.sizes != self.config.sizes {
+            self.size_display = config.sizes;
+        }
+        self.config_warning = config_warning(&config, "run `treenav config check` for details");
+        self.config = config;
+        self.pending_keys.clear();
+        self.status_message = Some("Reloaded config.toml".to_string());
+        let shows_files = matches!(self.view_mode, ViewMode::Tree | ViewMode::DiskUsage);
+        if shows_files && self.input_mode != InputMode::Search {
+            self.rebuild_tree();
+        }
+    }
+
+    /// Drops the saved entries the startup pass found missing, once it is done.
+    fn poll_gc(&mut self) {
+        let Some(report) = self.gc_report.as_ref().and_then(|rx| rx.try_recv().ok()) else {
+            return;
+        };
+        self.gc_report = None;
+        gc::apply(&mut self.persistent_state, &report);
+        if report.removed() > 0 && self.status_message.is_none() {
+            self.status_message = Some(format!("Pruned from saved state: {}", report.summary()));
+        }
+    }
+
+    /// Asks for a preview of the selected file once the shown one is for
+    /// another file, or highlighted another way.
+    fn poll_preview(&mut self) {
+        if let Some(preview) = self.preview_worker.poll() {
+            self.preview = Some(preview);
+        }
+        if !self.show_preview {
+            return;
+        }
+        let highlight = self.config.preview.highlight;
+        let Some(selected) = self.tree_state.selected().last() else {
+            return;
+        };
+        if self
+            .preview
+            .as_ref()
+            .is_some_and(|p| p.path == *selected && p.highlight == highlight)
+        {
+            return;
+        }
+        if selected.is_file() {
+            self.preview_worker.request(selected, highlight);
+        } else {
+            // Directories are listed as they are drawn
+            self.preview = None;
+        }
     }
 
     fn page_up(&mut self) {
@@ -552,8 +1365,61 @@ impl App {
         // Save current selection and items
         self.saved_selection = Some(self.tree_state.selected().to_vec());
         self.saved_view_items = Some(self.items.clone());
-        // Cache paths from already-loaded tree items (no disk I/O)
+        // Start with paths from already-loaded tree items (no disk I/O),
+        // then let the indexer stream in everything below the root
         self.search_paths_cache = self.collect_paths_from_items();
+        self.search_paths_seen = self.search_paths_cache.iter().cloned().collect();
+        self.search_indexer = (self.view_mode == ViewMode::Tree)
+            .then(|| SearchIndexer::new(self.root_path.clone(), self.persistent_state.show_hidden));
+    }
+
+    pub fn is_indexing(&self) -> bool {
+        self.search_indexer
+            .as_ref()
+            .is_some_and(|indexer| !indexer.is_done())
+    }
+
+    fn poll_search_index(&mut self) {
+        let Some(indexer) = self.search_indexer.as_mut() else {
+            return;
+        };
+        let mut new_paths = Vec::new();
+        if !indexer.poll_results(&mut new_paths) {
+            return;
+        }
+
+        let before = self.search_paths_cache.len();
+        for path in new_paths {
+            if self.search_paths_seen.insert(path.clone()) {
+                self.search_paths_cache.push(path);
+            }
+        }
+        if self.search_paths_cache.len() > before && !self.search_input.value().is_empty() {
+            self.add_search_matches(before);
+        }
+    }
+
+    /// Adds matches among the paths indexed from `start` on, keeping the
+    /// highlighted match if it is still shown. Only the new paths are scored,
+    /// so a large tree streaming in never re-runs the query over all of it.
+    fn add_search_matches(&mut self, start: usize) {
+        let new_matches = score_paths(&self.search_paths_cache[start..], self.search_input.value());
+        if new_matches.is_empty() {
+            return;
+        }
+        let current = self
+            .search_matches
+            .get(self.search_index)
+            .map(|(path, _)| path.clone());
+        let mut matches = std::mem::take(&mut self.search_matches);
+        matches.extend(new_matches);
+        self.show_search_matches(matches);
+        if let Some(index) =
+            current.and_then(|current| self.search_matches.iter().position(|(p, _)| *p == current))
+        {
+            self.search_index = index;
+            self.select_search_match();
+        }
     }
 
     fn collect_paths_from_items(&self) -> Vec<PathBuf> {
@@ -573,7 +1439,7 @@ impl App {
         self.search_input = Input::default();
         self.search_matches.clear();
         self.search_index = 0;
-        self.search_paths_cache.clear();
+        self.clear_search_index();
         // Restore original tree
         if let Some(items) = self.saved_view_items.take() {
             self.items = items;
@@ -581,7 +1447,7 @@ impl App {
             if let Some(sel) = self.saved_selection.take() {
                 self.tree_state.select(sel);
             } else {
-                self.tree_state.select_first();
+                self.select_first_item();
             }
         }
     }
@@ -596,24 +1462,19 @@ impl App {
                 self.items = items.clone();
             }
             self.tree_state = TreeState::default();
-            self.tree_state.select_first();
+            self.select_first_item();
             return;
         }
 
-        let query_lower = query.to_lowercase();
-        let query_chars: Vec<char> = query_lower.chars().collect();
-
-        let mut matches: Vec<(PathBuf, u16)> = self
-            .search_paths_cache
-            .iter()
-            .filter_map(|path| {
-                let name = path.file_name()?.to_string_lossy().to_lowercase();
-                fuzzy_score(&name, &query_chars).map(|score| (path.clone(), score))
-            })
-            .collect();
+        let matches = score_paths(&self.search_paths_cache, query);
+        self.show_search_matches(matches);
+    }
 
-        matches.sort_by(|a, b| b.1.cmp(&a.1));
-        matches.truncate(50);
+    /// Shows the best of `matches` as a flat list, in place of the tree.
+    fn show_search_matches(&mut self, mut matches: Vec<(PathBuf, u16)>) {
+        // Stable, so equal scores stay in the order they were indexed
+        matches.sort_by_key(|m| std::cmp::Reverse(m.1));
+        matches.truncate(MAX_SEARCH_MATCHES);
 
         // Build filtered tree from matches
         self.items = matches
@@ -623,8 +1484,7 @@ impl App {
                     .file_name()
                     .map(|n| n.to_string_lossy().to_string())
                     .unwrap_or_else(|| path.display().to_string());
-                let icon = crate::icons::get_icon(path, false);
-                let display = format!("{} {}", icon, name);
+                let display = self.config.icons.label(path, false, &name);
                 TreeItem::new_leaf(path.clone(), display)
             })
             .collect();
@@ -632,7 +1492,7 @@ impl App {
         self.search_matches = matches;
         self.search_index = 0;
         self.tree_state = TreeState::default();
-        self.tree_state.select_first();
+        self.select_first_item();
     }
 
     fn jump_to_search_result(&mut self) {
@@ -651,30 +1511,28 @@ impl App {
         self.saved_selection = None;
 
         // Build full selection path from root to target
-        let mut selection_path: Vec<PathBuf> = Vec::new();
-        let mut current = Some(path.as_path());
-
-        while let Some(p) = current {
-            if p.starts_with(&self.root_path) && p != self.root_path {
-                selection_path.push(p.to_path_buf());
-            }
-            current = p.parent();
-        }
-        selection_path.reverse();
+        let selection_path = tree_identifier(&self.root_path, &path);
 
         // Expand all parent directories
         for ancestor in &selection_path[..selection_path.len().saturating_sub(1)] {
-            if !self.persistent_state.expanded_dirs.contains(ancestor) {
-                self.persistent_state.expanded_dirs.insert(ancestor.clone());
+            if !self
+                .persistent_state
+                .expanded_dirs(&self.root_path)
+                .contains(ancestor)
+            {
+                self.persistent_state
+                    .expanded_dirs_mut(&self.root_path)
+                    .insert(ancestor.clone());
             }
         }
 
         self.rebuild_tree();
         self.tree_state = TreeState::default();
-        // Open expanded dirs in tree state
-        for expanded in &self.persistent_state.expanded_dirs {
-            self.tree_state.open(vec![expanded.clone()]);
-        }
+        open_expanded_dirs(
+            &mut self.tree_state,
+            &self.root_path,
+            self.persistent_state.expanded_dirs(&self.root_path),
+        );
         self.tree_state.select(selection_path);
 
         // Clear search state
@@ -682,10 +1540,30 @@ impl App {
         self.search_input = Input::default();
         self.search_matches.clear();
         self.search_index = 0;
+        self.clear_search_index();
+    }
+
+    fn clear_search_index(&mut self) {
+        // Dropping the indexer disconnects its channel, which stops the walk
+        self.search_indexer = None;
         self.search_paths_cache.clear();
+        self.search_paths_seen.clear();
     }
 
     fn add_or_edit_bookmark(&mut self) {
+        if !self.marked.is_empty() {
+            let mut dirs: Vec<PathBuf> =
+                self.marked.iter().filter(|p| p.is_dir()).cloned().collect();
+            dirs.sort();
+            for dir in dirs {
+                // Keep labels of directories that are already bookmarked
+                if self.persistent_state.get_bookmark(&dir).is_none() {
+                    self.persistent_state.add_bookmark(dir, String::new());
+                }
+            }
+            self.clear_marks();
+            return;
+        }
         if let Some(selected) = self.get_selected_path() {
             if selected.is_dir() {
                 let existing_label = self
@@ -701,65 +1579,415 @@ impl App {
         }
     }
 
-    fn switch_to_bookmarks_view(&mut self) {
-        match self.view_mode {
-            ViewMode::Bookmarks => {
-                // Return to tree view
-                self.view_mode = ViewMode::Tree;
-                if let Some(items) = self.saved_view_items.take() {
-                    self.items = items;
-                    self.rebuild_tree();
-                    self.tree_state = TreeState::default();
-                    if let Some(sel) = self.saved_selection.take() {
-                        self.tree_state.select(sel);
-                    } else {
-                        self.tree_state.select_first();
+    /// Directory that new or pasted entries go into: the selected directory, or the
+    /// parent of the selected file.
+    fn target_dir(&self) -> PathBuf {
+        match self.get_selected_path() {
+            Some(selected) if selected.is_dir() => selected,
+            Some(selected) => selected
+                .parent()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| self.root_path.clone()),
+            None => self.root_path.clone(),
+        }
+    }
+
+    fn start_create(&mut self, mode: InputMode) {
+        self.file_op_path = Some(self.target_dir());
+        self.file_op_input = Input::default();
+        self.input_mode = mode;
+    }
+
+    fn start_rename(&mut self) {
+        if let Some(selected) = self.get_selected_path() {
+            let name = selected
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            self.file_op_input = Input::default().with_value(name);
+            self.file_op_path = Some(selected);
+            self.input_mode = InputMode::Rename;
+        }
+    }
+
+    fn start_delete(&mut self) {
+        self.delete_paths = if self.marked.is_empty() {
+            self.get_selected_path().into_iter().collect()
+        } else {
+            self.marked_paths()
+        };
+        if !self.delete_paths.is_empty() {
+            self.input_mode = InputMode::ConfirmDelete;
+        }
+    }
+
+    fn finish_file_op(&mut self) {
+        self.input_mode = InputMode::Normal;
+        self.file_op_input = Input::default();
+        self.file_op_path = None;
+        self.delete_paths.clear();
+    }
+
+    /// Marked paths, outermost first, without paths nested inside other marked ones.
+    fn marked_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .marked
+            .iter()
+            .filter(|p| {
+                !p.ancestors()
+                    .skip(1)
+                    .any(|ancestor| self.marked.contains(ancestor))
+            })
+            .cloned()
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn toggle_mark(&mut self) {
+        if let Some(selected) = self.get_selected_path() {
+            if !self.marked.remove(&selected) {
+                self.marked.insert(selected);
+            }
+            self.rebuild_tree();
+        }
+    }
+
+    fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.rebuild_tree();
+    }
+
+    fn enter_visual_mode(&mut self) {
+        self.visual_anchor = self.tree_state.selected().to_vec();
+        if self.visual_anchor.is_empty() {
+            return;
+        }
+        self.visual_base = self.marked.clone();
+        self.input_mode = InputMode::Visual;
+        self.update_visual_marks();
+    }
+
+    /// Marks every visible row between the visual anchor and the cursor.
+    fn update_visual_marks(&mut self) {
+        let rows: Vec<Vec<PathBuf>> = self
+            .tree_state
+            .flatten(&self.items)
+            .into_iter()
+            .map(|row| row.identifier)
+            .collect();
+        let anchor = rows.iter().position(|id| *id == self.visual_anchor);
+        let cursor = rows.iter().position(|id| id == self.tree_state.selected());
+
+        let mut marked = self.visual_base.clone();
+        if let (Some(anchor), Some(cursor)) = (anchor, cursor) {
+            let range = anchor.min(cursor)..=anchor.max(cursor);
+            marked.extend(rows[range].iter().filter_map(|id| id.last().cloned()));
+        }
+        if marked != self.marked {
+            self.marked = marked;
+            self.rebuild_tree();
+        }
+    }
+
+    fn create_entry(&mut self, dir: &Path, name: &str, is_dir: bool) {
+        let path = dir.join(name);
+        let result = fileops::check_name(name).and_then(|()| {
+            if is_dir {
+                fileops::create_dir(&path)
+            } else {
+                fileops::create_file(&path)
+            }
+        });
+        match result {
+            Ok(()) => {
+                self.undo_stack.push(FileOp::Created(path.clone()));
+                self.status_message = Some(format!("Created {}", name));
+                if dir != self.root_path {
+                    self.persistent_state
+                        .expanded_dirs_mut(&self.root_path)
+                        .insert(dir.to_path_buf());
+                }
+                self.refresh_after_file_op(Some(path));
+            }
+            Err(e) => self.status_message = Some(format!("Create failed: {}", e)),
+        }
+    }
+
+    fn rename_entry(&mut self, from: &Path, name: &str) {
+        let Some(parent) = from.parent() else {
+            return;
+        };
+        let to = parent.join(name);
+        if to == from {
+            return;
+        }
+        match fileops::check_name(name).and_then(|()| fileops::rename(from, &to)) {
+            Ok(()) => {
+                self.persistent_state.rename_path(from, &to);
+                self.undo_stack.push(FileOp::Moved {
+                    from: from.to_path_buf(),
+                    to: to.clone(),
+                });
+                self.status_message = Some(format!("Renamed to {}", name));
+                self.refresh_after_file_op(Some(to));
+            }
+            Err(e) => self.status_message = Some(format!("Rename failed: {}", e)),
+        }
+    }
+
+    fn delete_entry(&mut self, path: &Path) {
+        // A file with other links left frees nothing, and the sizes above
+        // may have counted it through another of them
+        let removed = match path.symlink_metadata() {
+            Ok(m) if m.is_dir() => self.dir_sizes.get(path).and_then(|s| s.size()),
+            Ok(m) if m.is_file() && m.nlink() == 1 => Some(DirSize::of_file(&m)),
+            _ => None,
+        };
+        match trash::move_to_trash(path) {
+            Ok(entry) => {
+                let forgotten = self.persistent_state.forget_path(path);
+                self.cancel_pending_sizes(|dir| dir.starts_with(path));
+                let relinked = self.size_worker.forget(path);
+                self.dir_sizes.retain(|p, _| !p.starts_with(path));
+                self.remeasure_sizes(|dir| relinked.contains(dir));
+                match removed {
+                    // Take what was freed off the sizes above, instead of
+                    // measuring them again
+                    Some(removed) => {
+                        for ancestor in path.ancestors().skip(1) {
+                            match self.dir_sizes.get_mut(ancestor) {
+                                Some(SizeState::Measured(size)) => {
+                                    *size = size.saturating_sub(removed);
+                                }
+                                // It may have been counted already, so start over
+                                Some(state) if state.is_pending() => {
+                                    self.size_worker.request_size(ancestor.to_path_buf());
+                                }
+                                _ => {}
+                            }
+                        }
                     }
-                } else {
-                    self.rebuild_tree();
-                    self.tree_state.select_first();
+                    None => self.remeasure_sizes(|dir| path.starts_with(dir)),
                 }
+                self.undo_stack.push(FileOp::Trashed { entry, forgotten });
+                self.status_message =
+                    Some(format!("Moved {} to trash (u to undo)", display_name(path)));
+                self.refresh_after_file_op(None);
             }
-            _ => {
-                // Save current state and switch to bookmarks
-                self.saved_selection = Some(self.tree_state.selected().to_vec());
-                self.saved_view_items = Some(std::mem::take(&mut self.items));
-                self.view_mode = ViewMode::Bookmarks;
-                self.rebuild_tree();
-                self.tree_state = TreeState::default();
-                self.tree_state.select_first();
+            Err(e) => self.status_message = Some(format!("Delete failed: {}", e)),
+        }
+    }
+
+    fn undo(&mut self) {
+        let Some(op) = self.undo_stack.pop() else {
+            self.status_message = Some("Nothing to undo".to_string());
+            return;
+        };
+
+        let result = match &op {
+            FileOp::Created(path) | FileOp::Copied(path) => trash::move_to_trash(path).map(|_| {
+                self.persistent_state.forget_path(path);
+                (format!("Moved {} to trash", display_name(path)), None)
+            }),
+            FileOp::Moved { from, to } => fileops::move_path(to, from).map(|()| {
+                self.persistent_state.rename_path(to, from);
+                (
+                    format!("Moved {} back", display_name(from)),
+                    Some(from.clone()),
+                )
+            }),
+            FileOp::Trashed { entry, forgotten } => trash::restore(entry).map(|path: PathBuf| {
+                self.persistent_state.restore_forgotten(forgotten.clone());
+                // Measure the directories it is back in again
+                self.remeasure_sizes(|dir| path.starts_with(dir));
+                (format!("Restored {}", display_name(&path)), Some(path))
+            }),
+        };
+
+        match result {
+            Ok((message, select)) => {
+                self.status_message = Some(format!("Undo: {}", message));
+                self.refresh_after_file_op(select);
             }
+            Err(e) => self.status_message = Some(format!("Undo failed: {}", e)),
         }
     }
 
-    fn switch_to_recent_view(&mut self) {
-        match self.view_mode {
-            ViewMode::Recent => {
-                // Return to tree view
-                self.view_mode = ViewMode::Tree;
-                if let Some(items) = self.saved_view_items.take() {
-                    self.items = items;
-                    self.rebuild_tree();
-                    self.tree_state = TreeState::default();
-                    if let Some(sel) = self.saved_selection.take() {
-                        self.tree_state.select(sel);
+    fn restore_selected_from_trash(&mut self) {
+        let Some(entry) = self
+            .get_selected_path()
+            .and_then(|selected| trash::entry_for(&selected))
+        else {
+            return;
+        };
+        match trash::restore(&entry) {
+            Ok(path) => {
+                self.status_message = Some(format!("Restored {}", path.display()));
+                self.refresh_after_file_op(None);
+            }
+            Err(e) => self.status_message = Some(format!("Restore failed: {}", e)),
+        }
+    }
+
+    fn yank_selected(&mut self, cut: bool) {
+        if !self.marked.is_empty() {
+            let paths = self.marked_paths();
+            self.status_message = Some(format!(
+                "{} {} items",
+                if cut { "Cut" } else { "Copied" },
+                paths.len()
+            ));
+            self.clipboard = Some(Clipboard { paths, cut });
+            self.clear_marks();
+            return;
+        }
+        if let Some(selected) = self.get_selected_path() {
+            self.status_message = Some(format!(
+                "{} {}",
+                if cut { "Cut" } else { "Copied" },
+                display_name(&selected)
+            ));
+            self.clipboard = Some(Clipboard {
+                paths: vec![selected],
+                cut,
+            });
+        }
+    }
+
+    fn paste(&mut self) {
+        let Some(clipboard) = self.clipboard.clone() else {
+            self.status_message = Some("Nothing to paste".to_string());
+            return;
+        };
+        let dir = self.target_dir();
+
+        let mut last = None;
+        let mut pasted = 0;
+        for from in &clipboard.paths {
+            let Some(name) = from.file_name() else {
+                continue;
+            };
+            let to = fileops::unique_destination(&dir, name);
+            let result = if clipboard.cut {
+                fileops::move_path(from, &to)
+            } else {
+                fileops::copy(from, &to)
+            };
+            match result {
+                Ok(()) => {
+                    if clipboard.cut {
+                        self.persistent_state.rename_path(from, &to);
+                        self.undo_stack.push(FileOp::Moved {
+                            from: from.clone(),
+                            to: to.clone(),
+                        });
                     } else {
-                        self.tree_state.select_first();
+                        self.undo_stack.push(FileOp::Copied(to.clone()));
                     }
-                } else {
-                    self.rebuild_tree();
-                    self.tree_state.select_first();
+                    pasted += 1;
+                    last = Some(to);
+                }
+                Err(e) => {
+                    self.status_message =
+                        Some(format!("Paste of {} failed: {}", display_name(from), e));
                 }
             }
-            _ => {
-                // Save current state and switch to recent
-                self.saved_selection = Some(self.tree_state.selected().to_vec());
-                self.saved_view_items = Some(std::mem::take(&mut self.items));
-                self.view_mode = ViewMode::Recent;
-                self.rebuild_tree();
-                self.tree_state = TreeState::default();
-                self.tree_state.select_first();
+        }
+
+        if clipboard.cut {
+            self.clipboard = None;
+        }
+        if pasted > 0 {
+            if dir != self.root_path {
+                self.persistent_state
+                    .expanded_dirs_mut(&self.root_path)
+                    .insert(dir);
+            }
+            if self.status_message.is_none() {
+                self.status_message = Some(format!("Pasted {} item(s)", pasted));
             }
+            self.refresh_after_file_op(last);
         }
     }
+
+    /// Rebuilds the tree after paths changed on disk, selecting `select` if given.
+    fn refresh_after_file_op(&mut self, select: Option<PathBuf>) {
+        let selection = self.tree_state.selected().to_vec();
+        self.marked.retain(|p| p.symlink_metadata().is_ok());
+        self.rebuild_tree();
+        if self.view_mode != ViewMode::Tree {
+            self.select_closest_existing(selection);
+            return;
+        }
+
+        self.tree_state = TreeState::default();
+        open_expanded_dirs(
+            &mut self.tree_state,
+            &self.root_path,
+            self.persistent_state.expanded_dirs(&self.root_path),
+        );
+
+        match select {
+            Some(path) => {
+                self.tree_state
+                    .select(tree_identifier(&self.root_path, &path));
+            }
+            None => self.select_closest_existing(selection),
+        }
+    }
+
+    /// Selects the first row. `TreeState::select_first` only knows the rows of the
+    /// last render, so it selects nothing right after the items were replaced.
+    fn select_first_item(&mut self) {
+        let first = self
+            .items
+            .first()
+            .map(|item| vec![item.identifier().clone()])
+            .unwrap_or_default();
+        self.tree_state.select(first);
+    }
+
+    /// Selects `selection`, or its closest ancestor that still exists on disk.
+    fn select_closest_existing(&mut self, mut selection: Vec<PathBuf>) {
+        while selection.last().is_some_and(|p| !p.exists()) {
+            selection.pop();
+        }
+        if selection.is_empty() {
+            self.select_first_item();
+        } else {
+            self.tree_state.select(selection);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reloaded_configs_with_mistakes_are_not_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.toml");
+        let mut app = App::new(Some(dir.path().to_path_buf()), None, None, false).unwrap();
+
+        fs::write(&file, "[preview]\nline_numbers = false\n").unwrap();
+        app.reload_config(Config::load_file(&file));
+        assert!(!app.config.preview.line_numbers);
+        assert_eq!(app.config_warning, None);
+
+        fs::write(
+            &file,
+            "[preview]\nline_numbers = true\n[theme]\nborder = \"not a color\"\n",
+        )
+        .unwrap();
+        app.reload_config(Config::load_file(&file));
+        assert!(!app.config.preview.line_numbers);
+        let warning = app.config_warning.as_deref().unwrap_or_default();
+        assert!(
+            warning.ends_with("keeping the previous config"),
+            "{}",
+            warning
+        );
+    }
 }
@@ -0,0 +1,468 @@
+use crate::config::Config;
+use crate::gc;
+use crate::import::{self, Source};
+use crate::search;
+use crate::shell::{self, KeyBinding, Shell};
+use crate::sizecache::SizeCache;
+use crate::state::{self, PersistentState};
+use clap::Subcommand;
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Commands that work on the saved state without opening the terminal UI.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Print shell integration, e.g. `eval "$(treenav init bash)"`
+    Init {
+        shell: Shell,
+
+        /// Name of the function that runs treenav and changes directory
+        #[arg(long, default_value = "tn", value_parser = shell::parse_command_name)]
+        cmd: String,
+
+        /// Also bind a key (like ctrl-o or alt-t) that opens treenav and cds
+        #[arg(long, value_name = "KEY")]
+        bind: Option<KeyBinding>,
+    },
+    /// Manage bookmarks
+    #[command(subcommand)]
+    Bookmark(BookmarkCommand),
+    /// Manage starred directories
+    #[command(subcommand)]
+    Star(StarCommand),
+    /// List recently visited directories, highest frecency first
+    Recent {
+        /// Show each directory's frecency score
+        #[arg(short, long)]
+        scores: bool,
+    },
+    /// Print the directory matching a bookmark label or fuzzy query
+    ///
+    /// An exact label wins; otherwise the best fuzzy match among bookmarks,
+    /// starred and recent directories is printed and recorded as a visit.
+    Jump {
+        #[arg(required = true, num_args = 1..)]
+        query: Vec<String>,
+    },
+    /// Merge directory history from another jumper into recent directories
+    Import {
+        #[arg(long, value_enum)]
+        from: Source,
+
+        /// The jumper's database, e.g. ~/.local/share/zoxide/db.zo,
+        /// ~/.local/share/autojump/autojump.txt, ~/.z or ~/.fasd
+        file: PathBuf,
+
+        /// Show what would be imported without saving
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage sessions saved with `treenav --session <NAME>`
+    #[command(subcommand)]
+    Session(SessionCommand),
+    /// Check or print the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Remove saved entries and cached sizes for deleted paths, and cap
+    /// expanded directories and roots
+    Gc {
+        /// Keep entries on volumes under /mnt, /media, /run/media or /Volumes
+        /// that look unmounted rather than deleted
+        #[arg(long)]
+        keep_unmounted: bool,
+
+        /// Report what would be removed without saving
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BookmarkCommand {
+    /// Bookmark a path, replacing any existing bookmark for it
+    Add {
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        #[arg(short, long, default_value = "")]
+        label: String,
+    },
+    /// Remove a bookmark by label or path
+    Rm { bookmark: String },
+    /// List bookmarks as `label<TAB>path`
+    Ls,
+    /// Change the label of a bookmark, found by label or path
+    Rename { bookmark: String, label: String },
+}
+
+#[derive(Subcommand)]
+pub enum StarCommand {
+    /// Star a directory
+    Add {
+        #[arg(default_value = ".")]
+        path: PathBuf,
+    },
+    /// Unstar a directory
+    Rm { path: PathBuf },
+    /// List starred directories
+    Ls,
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommand {
+    /// List sessions as `name<TAB>root`
+    Ls,
+    /// Forget a session
+    Rm { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Report problems in config.toml by line and column
+    Check,
+    /// Print the configuration in effect, defaults included
+    Dump,
+}
+
+pub fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Init { shell, cmd, bind } => {
+            print!("{}", shell::init_script(shell, &cmd, bind));
+        }
+        Command::Bookmark(command) => bookmark(command)?,
+        Command::Star(command) => star(command)?,
+        Command::Session(command) => session(command)?,
+        Command::Config(command) => config(command)?,
+        Command::Recent { scores } => {
+            let state = load_state();
+            let now = state::now();
+            for recent in state.ranked_recent() {
+                if scores {
+                    println!("{:>8.2} {}", recent.frecency(now), recent.path.display());
+                } else {
+                    println!("{}", recent.path.display());
+                }
+            }
+        }
+        Command::Import {
+            from,
+            file,
+            dry_run,
+        } => import_history(from, &file, dry_run)?,
+        Command::Gc {
+            keep_unmounted,
+            dry_run,
+        } => {
+            let mut state = load_state();
+            let report = gc::collect(&mut state, keep_unmounted);
+            for (kind, path) in &report.missing {
+                println!("{:<9} {}", kind.label(), path.display());
+            }
+            for path in &report.capped {
+                println!(
+                    "{:<9} {} (over the per-root limit)",
+                    "expanded",
+                    path.display()
+                );
+            }
+            for path in &report.old_roots {
+                println!("{:<9} {} (least recently used)", "root", path.display());
+            }
+            println!(
+                "{} {} entries ({} missing, {} expanded over the limit, {} roots over the limit), \
+                 kept {} on unmounted volumes",
+                if dry_run { "Would remove" } else { "Removed" },
+                report.removed(),
+                report.missing.len(),
+                report.capped.len(),
+                report.old_roots.len(),
+                report.kept_unmounted
+            );
+            if !dry_run {
+                save_state(&mut state)?;
+            }
+
+            let sizes = SizeCache::load(None).prune(
+                |dir| dir.exists() || (keep_unmounted && gc::on_unmounted_volume(dir)),
+                dry_run,
+            )?;
+            // Directories below one that is gone go without saying
+            let gone: HashSet<&Path> = sizes.iter().map(PathBuf::as_path).collect();
+            for dir in &sizes {
+                if !dir.parent().is_some_and(|parent| gone.contains(parent)) {
+                    println!("{:<9} {}", "size", dir.display());
+                }
+            }
+            println!(
+                "{} {} cached directories",
+                if dry_run { "Would remove" } else { "Removed" },
+                sizes.len()
+            );
+        }
+        Command::Jump { query } => {
+            let mut state = load_state();
+            let query = query.join(" ");
+            let Some(path) = resolve_jump(&state, &query) else {
+                eprintln!("treenav: nothing matches '{}'", query);
+                std::process::exit(1);
+            };
+            println!("{}", path.display());
+            state.add_recent(path);
+            save_state(&mut state)?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the state, reporting a corrupt state file on stderr.
+fn load_state() -> PersistentState {
+    let state = PersistentState::load();
+    if let Some(error) = &state.load_error {
+        eprintln!("treenav: {}", error);
+    }
+    state
+}
+
+/// Saves the state, reporting on stderr if the file had become corrupt or
+/// can't be written, unless `load_state` already did.
+fn save_state(state: &mut PersistentState) -> Result<()> {
+    if let Some(error) = state.save()? {
+        if state.load_error.as_ref() != Some(&error) {
+            eprintln!("treenav: {}", error);
+        }
+    }
+    Ok(())
+}
+
+fn bookmark(command: BookmarkCommand) -> Result<()> {
+    let mut state = load_state();
+    match command {
+        BookmarkCommand::Add { path, label } => {
+            let path = existing_path(&path)?;
+            state.add_bookmark(path, label);
+        }
+        BookmarkCommand::Rm { bookmark } => {
+            let index = find_bookmark(&state, &bookmark)?;
+            state.bookmarks.remove(index);
+        }
+        BookmarkCommand::Ls => {
+            for bookmark in &state.bookmarks {
+                println!("{}\t{}", bookmark.label, bookmark.path.display());
+            }
+            return Ok(());
+        }
+        BookmarkCommand::Rename { bookmark, label } => {
+            let index = find_bookmark(&state, &bookmark)?;
+            state.bookmarks[index].label = label;
+        }
+    }
+    save_state(&mut state)?;
+    Ok(())
+}
+
+fn star(command: StarCommand) -> Result<()> {
+    let mut state = load_state();
+    match command {
+        StarCommand::Add { path } => {
+            let path = existing_path(&path)?;
+            if !path.is_dir() {
+                bail!("{} is not a directory", path.display());
+            }
+            state.starred_dirs.insert(path);
+        }
+        StarCommand::Rm { path } => {
+            // The directory may be gone already, so fall back to the path as given
+            let path = path
+                .canonicalize()
+                .or_else(|_| std::path::absolute(&path))?;
+            if !state.starred_dirs.remove(&path) {
+                bail!("{} is not starred", path.display());
+            }
+        }
+        StarCommand::Ls => {
+            let mut dirs: Vec<&PathBuf> = state.starred_dirs.iter().collect();
+            dirs.sort();
+            for dir in dirs {
+                println!("{}", dir.display());
+            }
+            return Ok(());
+        }
+    }
+    save_state(&mut state)?;
+    Ok(())
+}
+
+fn session(command: SessionCommand) -> Result<()> {
+    let mut state = load_state();
+    match command {
+        SessionCommand::Ls => {
+            for (name, session) in &state.sessions {
+                println!("{}\t{}", name, session.root.display());
+            }
+            return Ok(());
+        }
+        SessionCommand::Rm { name } => {
+            if state.sessions.remove(&name).is_none() {
+                bail!("no session '{}'", name);
+            }
+        }
+    }
+    save_state(&mut state)?;
+    Ok(())
+}
+
+fn config(command: ConfigCommand) -> Result<()> {
+    let path = Config::config_file_path().ok_or_else(|| eyre!("no config directory"))?;
+    let config = Config::load_file(&path);
+    match command {
+        ConfigCommand::Check => {
+            if !path.exists() {
+                println!("{}: not found, using defaults", path.display());
+                return Ok(());
+            }
+            if config.errors.is_empty() {
+                println!("{}: OK", path.display());
+                return Ok(());
+            }
+            for error in &config.errors {
+                println!("{}", error.in_file(&path));
+            }
+            std::process::exit(1);
+        }
+        ConfigCommand::Dump => {
+            for error in &config.errors {
+                eprintln!("treenav: {}", error.in_file(&path));
+            }
+            print!("{}", config.dump());
+        }
+    }
+    Ok(())
+}
+
+fn import_history(from: Source, file: &Path, dry_run: bool) -> Result<()> {
+    let entries = import::read(from, file).map_err(|e| eyre!("{}: {}", file.display(), e))?;
+    let total = entries.len();
+    let mut state = load_state();
+    let (mut added, mut merged) = (0, 0);
+
+    // fasd also tracks files, and every jumper keeps deleted directories
+    let (mut entries, mut skipped): (Vec<_>, Vec<_>) =
+        entries.into_iter().partition(|entry| entry.path.is_dir());
+    skipped.extend(PersistentState::scale_imported(&mut entries));
+    if dry_run {
+        for entry in &skipped {
+            println!("skip   {}", entry.path.display());
+        }
+    }
+
+    for entry in entries {
+        let path = entry.path.clone();
+        if state.merge_recent(entry) {
+            added += 1;
+            if dry_run {
+                println!("new    {}", path.display());
+            }
+        } else {
+            merged += 1;
+            if dry_run {
+                println!("merge  {}", path.display());
+            }
+        }
+    }
+
+    println!(
+        "{} {} entries from {}: {} new, {} merged, {} skipped (missing, not a directory \
+         or without a usable rank)",
+        if dry_run { "Would import" } else { "Imported" },
+        total,
+        file.display(),
+        added,
+        merged,
+        skipped.len()
+    );
+    if !dry_run {
+        state.age_recent();
+        save_state(&mut state)?;
+    }
+    Ok(())
+}
+
+fn existing_path(path: &Path) -> Result<PathBuf> {
+    path.canonicalize()
+        .map_err(|e| eyre!("{}: {}", path.display(), e))
+}
+
+/// Finds a bookmark by exact label first, then by path.
+fn find_bookmark(state: &PersistentState, bookmark: &str) -> Result<usize> {
+    let by_label = state
+        .bookmarks
+        .iter()
+        .position(|b| !b.label.is_empty() && b.label == bookmark);
+    let by_path = || {
+        let path = Path::new(bookmark);
+        let path = path
+            .canonicalize()
+            .or_else(|_| std::path::absolute(path))
+            .ok()?;
+        state.bookmarks.iter().position(|b| b.path == path)
+    };
+    by_label
+        .or_else(by_path)
+        .ok_or_else(|| eyre!("no bookmark '{}'", bookmark))
+}
+
+/// Resolves a `jump` query. An exact bookmark label wins; otherwise the best
+/// fuzzy match on labels and directory names, weighted by frecency, with
+/// earlier candidates (bookmarks, then starred, then recent) winning ties.
+pub fn resolve_jump(state: &PersistentState, query: &str) -> Option<PathBuf> {
+    if let Some(bookmark) = state
+        .bookmarks
+        .iter()
+        .filter(|b| !b.label.is_empty() && b.label.eq_ignore_ascii_case(query))
+        .find(|b| b.path.is_dir())
+    {
+        return Some(bookmark.path.clone());
+    }
+
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let mut starred: Vec<&PathBuf> = state.starred_dirs.iter().collect();
+    starred.sort();
+    let candidates = state
+        .bookmarks
+        .iter()
+        .map(|b| (b.path.as_path(), b.label.as_str()))
+        .chain(starred.into_iter().map(|p| (p.as_path(), "")))
+        .chain(
+            state
+                .ranked_recent()
+                .into_iter()
+                .map(|r| (r.path.as_path(), "")),
+        );
+
+    let mut best: Option<(&Path, f64)> = None;
+    for (path, label) in candidates {
+        let Some(score) = jump_score(path, label, &query) else {
+            continue;
+        };
+        // Frecency breaks near-ties in match quality without drowning them
+        let score = f64::from(score) * (1.0 + state.frecency(path).ln_1p());
+        if best.is_none_or(|(_, best_score)| score > best_score) && path.is_dir() {
+            best = Some((path, score));
+        }
+    }
+    best.map(|(path, _)| path.to_path_buf())
+}
+
+/// Labels and the final path component count double, so `proj` prefers
+/// `~/projects` over `~/src/old-projects-archive/x`.
+fn jump_score(path: &Path, label: &str, query: &[char]) -> Option<u16> {
+    let label = search::fuzzy_score(&label.to_lowercase(), query).map(|s| s.saturating_mul(2));
+    let name = path
+        .file_name()
+        .and_then(|n| search::fuzzy_score(&n.to_string_lossy().to_lowercase(), query))
+        .map(|s| s.saturating_mul(2));
+    let full = search::fuzzy_score(&path.to_string_lossy().to_lowercase(), query);
+    label.max(name).max(full)
+}
@@ -1,138 +1,220 @@
-use ratatui::style::Color;
+use crate::icons::{self, Icons, IconsSetting};
+use crate::keymap::Keymap;
+use crate::lscolors::LsColors;
+use crate::preview::{self, PreviewDisplay, PreviewTable};
+use crate::size::{self, SizeDisplay, SizesTable};
+use crate::theme::{self, Theme, ThemeSetting, ThemeTable};
+use serde::de::IgnoredAny;
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
 
+/// A problem in the config file, with its 1-based line and column when it
+/// can be pinned to a spot in the file.
 #[derive(Debug, Clone)]
-pub struct Theme {
-    pub border: Color,
-    pub highlight_bg: Color,
-    pub starred: Color,
-    pub dim: Color,
-    pub text: Color,
+pub struct Diagnostic {
+    /// Set when the problem is in a theme file rather than config.toml
+    pub file: Option<PathBuf>,
+    pub location: Option<(usize, usize)>,
+    pub message: String,
 }
 
-impl Default for Theme {
-    fn default() -> Self {
+impl Diagnostic {
+    pub fn at(contents: &str, offset: usize, message: impl Into<String>) -> Self {
+        let before = &contents[..offset.min(contents.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        Self {
+            file: None,
+            location: Some((line, column)),
+            message: message.into(),
+        }
+    }
+
+    pub fn in_theme_file(self, file: &Path) -> Self {
         Self {
-            border: Color::Rgb(80, 200, 220),
-            highlight_bg: Color::Rgb(40, 80, 100),
-            starred: Color::Rgb(250, 200, 50),
-            dim: Color::Rgb(100, 100, 100),
-            text: Color::White,
+            file: Some(file.to_path_buf()),
+            ..self
+        }
+    }
+
+    /// Prefixes the diagnostic with its file, `config` unless it is in a theme
+    /// file, like `config.toml:3:9: ...`.
+    pub fn in_file(&self, config: &Path) -> String {
+        let file = self.file.as_deref().unwrap_or(config);
+        match self.location {
+            Some(_) => format!("{}:{}", file.display(), self),
+            None => format!("{}: {}", file.display(), self),
         }
     }
 }
 
-#[derive(Debug, Clone, Default)]
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => write!(f, "{}:{}: {}", line, column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Config {
     pub theme: Theme,
+    pub keys: Keymap,
+    /// File name colors: `LS_COLORS`, then the `[colors]` table
+    pub colors: LsColors,
+    pub icons: Icons,
+    pub sizes: SizeDisplay,
+    pub preview: PreviewDisplay,
+    /// Problems found in the config file. Whatever could not be read falls
+    /// back to the default.
+    pub errors: Vec<Diagnostic>,
 }
 
+/// The top-level keys of config.toml. Others are reported but do not stop
+/// the rest from being read.
+const SECTIONS: &[&str] = &["theme", "colors", "icons", "sizes", "preview", "keys"];
+
 #[derive(Debug, Deserialize, Default)]
 struct TomlConfig {
+    theme: Option<ThemeSetting>,
+    #[serde(default)]
+    colors: ThemeTable,
+    icons: Option<IconsSetting>,
     #[serde(default)]
-    theme: TomlTheme,
+    sizes: SizesTable,
+    #[serde(default)]
+    preview: PreviewTable,
+    #[serde(default)]
+    keys: BTreeMap<Spanned<String>, Spanned<String>>,
 }
 
-#[derive(Debug, Deserialize, Default)]
-struct TomlTheme {
-    border: Option<String>,
-    highlight_bg: Option<String>,
-    starred: Option<String>,
-    dim: Option<String>,
-    text: Option<String>,
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            keys: Keymap::default(),
+            colors: LsColors::from_env(),
+            icons: Icons::default(),
+            sizes: SizeDisplay::default(),
+            preview: PreviewDisplay::default(),
+            errors: Vec::new(),
+        }
+    }
 }
 
 impl Config {
     pub fn load() -> Self {
-        let path = Self::config_file_path();
-        if let Some(path) = path {
-            if let Ok(contents) = fs::read_to_string(&path) {
-                if let Ok(toml_config) = toml::from_str::<TomlConfig>(&contents) {
-                    return Self::from_toml(toml_config);
-                }
-            }
+        match Self::config_file_path() {
+            Some(path) => Self::load_file(&path),
+            None => Self::default(),
         }
-        Self::default()
     }
 
-    fn config_file_path() -> Option<PathBuf> {
+    /// Reads `path`, which need not exist.
+    pub fn load_file(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents, path.parent()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => Self {
+                errors: vec![Diagnostic {
+                    file: None,
+                    location: None,
+                    message: e.to_string(),
+                }],
+                ..Self::default()
+            },
+        }
+    }
+
+    pub fn config_file_path() -> Option<PathBuf> {
         dirs::config_dir().map(|p| p.join("treenav").join("config.toml"))
     }
 
-    fn from_toml(toml: TomlConfig) -> Self {
-        let default = Theme::default();
-        Self {
-            theme: Theme {
-                border: toml
-                    .theme
-                    .border
-                    .as_deref()
-                    .and_then(parse_color)
-                    .unwrap_or(default.border),
-                highlight_bg: toml
-                    .theme
-                    .highlight_bg
-                    .as_deref()
-                    .and_then(parse_color)
-                    .unwrap_or(default.highlight_bg),
-                starred: toml
-                    .theme
-                    .starred
-                    .as_deref()
-                    .and_then(parse_color)
-                    .unwrap_or(default.starred),
-                dim: toml
-                    .theme
-                    .dim
-                    .as_deref()
-                    .and_then(parse_color)
-                    .unwrap_or(default.dim),
-                text: toml
-                    .theme
-                    .text
-                    .as_deref()
-                    .and_then(parse_color)
-                    .unwrap_or(default.text),
+    /// Parses config.toml, whose theme files are in `dir`/themes.
+    fn parse(contents: &str, dir: Option<&Path>) -> Self {
+        match toml::from_str::<TomlConfig>(contents) {
+            Ok(toml) => Self::from_toml(toml, contents, dir.map(|d| d.join("themes")).as_deref()),
+            Err(e) => Self {
+                errors: vec![Diagnostic::at(
+                    contents,
+                    e.span().map_or(0, |span| span.start),
+                    e.message().to_string(),
+                )],
+                ..Self::default()
             },
         }
     }
-}
 
-fn parse_color(s: &str) -> Option<Color> {
-    let s = s.trim();
-
-    // Handle hex colors like "#50C8DC" or "50C8DC"
-    let hex = s.strip_prefix('#').unwrap_or(s);
-    if hex.len() == 6 {
-        if let (Ok(r), Ok(g), Ok(b)) = (
-            u8::from_str_radix(&hex[0..2], 16),
-            u8::from_str_radix(&hex[2..4], 16),
-            u8::from_str_radix(&hex[4..6], 16),
-        ) {
-            return Some(Color::Rgb(r, g, b));
+    fn from_toml(toml: TomlConfig, contents: &str, themes_dir: Option<&Path>) -> Self {
+        let (theme, mut errors) = theme::resolve(toml.theme.as_ref(), contents, themes_dir);
+        report_unknown_sections(contents, &mut errors);
+
+        let mut colors = LsColors::from_env();
+        colors.apply(&toml.colors, contents, &mut errors);
+        let icons = icons::resolve(toml.icons.as_ref(), contents, &mut errors);
+        let sizes = size::resolve(&toml.sizes, contents, &mut errors);
+        let preview = preview::resolve(&toml.preview, theme.highlight, contents, &mut errors);
+
+        let (keys, key_errors) = Keymap::with_config(&toml.keys);
+        errors.extend(
+            key_errors
+                .into_iter()
+                .map(|(span, message)| Diagnostic::at(contents, span.start, message)),
+        );
+        // config.toml first, then any theme file
+        errors.sort_by(|a, b| (&a.file, a.location).cmp(&(&b.file, b.location)));
+        Self {
+            theme,
+            keys,
+            colors,
+            icons,
+            sizes,
+            preview,
+            errors,
         }
     }
 
-    // Handle named colors
-    match s.to_lowercase().as_str() {
-        "black" => Some(Color::Black),
-        "red" => Some(Color::Red),
-        "green" => Some(Color::Green),
-        "yellow" => Some(Color::Yellow),
-        "blue" => Some(Color::Blue),
-        "magenta" => Some(Color::Magenta),
-        "cyan" => Some(Color::Cyan),
-        "gray" | "grey" => Some(Color::Gray),
-        "darkgray" | "darkgrey" => Some(Color::DarkGray),
-        "lightred" => Some(Color::LightRed),
-        "lightgreen" => Some(Color::LightGreen),
-        "lightyellow" => Some(Color::LightYellow),
-        "lightblue" => Some(Color::LightBlue),
-        "lightmagenta" => Some(Color::LightMagenta),
-        "lightcyan" => Some(Color::LightCyan),
-        "white" => Some(Color::White),
-        _ => None,
+    /// The configuration in effect, as a complete `config.toml`.
+    pub fn dump(&self) -> String {
+        let mut keys = toml::Table::new();
+        for (key, action) in self.keys.bindings() {
+            let action = action.map_or("none", |a| a.name());
+            keys.insert(key.config_name(), action.into());
+        }
+
+        let mut config = toml::Table::new();
+        config.insert("theme".to_string(), self.theme.to_toml().into());
+        config.insert("colors".to_string(), self.colors.to_toml().into());
+        config.insert("icons".to_string(), self.icons.to_toml().into());
+        config.insert("sizes".to_string(), self.sizes.to_toml().into());
+        config.insert("preview".to_string(), self.preview.to_toml().into());
+        config.insert("keys".to_string(), keys.into());
+        toml::to_string(&config).expect("config serializes")
+    }
+}
+
+/// Reports the top-level keys of a config that parsed, other than `SECTIONS`.
+fn report_unknown_sections(contents: &str, errors: &mut Vec<Diagnostic>) {
+    let Ok(keys) = toml::from_str::<BTreeMap<Spanned<String>, IgnoredAny>>(contents) else {
+        return;
+    };
+    for key in keys.keys() {
+        if !SECTIONS.contains(&key.get_ref().as_str()) {
+            errors.push(Diagnostic::at(
+                contents,
+                key.span().start,
+                format!(
+                    "unknown setting '{}', expected {}",
+                    key.get_ref(),
+                    SECTIONS.join(", ")
+                ),
+            ));
+        }
     }
 }
@@ -0,0 +1,250 @@
+use crate::state::PersistentState;
+use crossbeam_channel::{bounded, Receiver};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Expanded directories remembered per tree root. Past this, the deepest are
+/// collapsed first so no remaining entry loses its expanded parent.
+const MAX_EXPANDED_PER_ROOT: usize = 256;
+
+/// Tree roots remembered. Past this, the least recently used are dropped.
+const MAX_ROOTS: usize = 100;
+
+/// Where removable and network volumes get mounted.
+const MOUNT_ROOTS: &[&str] = &["/mnt", "/media", "/run/media", "/Volumes"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Root,
+    Session,
+    Expanded,
+    Starred,
+    Bookmark,
+    Recent,
+}
+
+impl EntryKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            EntryKind::Root => "root",
+            EntryKind::Session => "session",
+            EntryKind::Expanded => "expanded",
+            EntryKind::Starred => "star",
+            EntryKind::Bookmark => "bookmark",
+            EntryKind::Recent => "recent",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GcReport {
+    /// Entries dropped because their path no longer exists
+    pub missing: Vec<(EntryKind, PathBuf)>,
+    /// Expanded directories collapsed to stay under the per-root cap
+    pub capped: Vec<PathBuf>,
+    /// Least recently used roots dropped to stay under the cap
+    pub old_roots: Vec<PathBuf>,
+    /// Missing paths kept because their volume looks unmounted
+    pub kept_unmounted: usize,
+}
+
+impl GcReport {
+    pub fn removed(&self) -> usize {
+        self.missing.len() + self.capped.len() + self.old_roots.len()
+    }
+
+    /// What was removed, like "bookmark /src/old" for a single entry or
+    /// "2 recent, 1 star" for several.
+    pub fn summary(&self) -> String {
+        if let ([(kind, path)], 1) = (self.missing.as_slice(), self.removed()) {
+            return format!("{} {}", kind.label(), path.display());
+        }
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        let kinds = self.missing.iter().map(|(kind, _)| kind.label());
+        let capped = std::iter::repeat_n(EntryKind::Expanded.label(), self.capped.len());
+        let old_roots = std::iter::repeat_n(EntryKind::Root.label(), self.old_roots.len());
+        for label in kinds.chain(capped).chain(old_roots) {
+            match counts.iter_mut().find(|(l, _)| *l == label) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((label, 1)),
+            }
+        }
+        counts
+            .iter()
+            .map(|(label, count)| format!("{} {}", count, label))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Runs the startup pass on a copy of `state` on a thread of its own, as a
+/// path on a hung network mount can block for a long time. Apply the report
+/// with `apply` once it arrives.
+pub fn spawn(mut state: PersistentState) -> Receiver<GcReport> {
+    let (report_tx, report_rx) = bounded(1);
+    thread::spawn(move || {
+        // Unplugged drives are likely to come back, so only prune deleted paths here
+        let _ = report_tx.send(collect(&mut state, true));
+    });
+    report_rx
+}
+
+/// Drops the entries `report` found missing, and caps expanded directories
+/// again, without touching the disk.
+pub fn apply(state: &mut PersistentState, report: &GcReport) {
+    let missing = |kind: EntryKind| -> HashSet<&Path> {
+        report
+            .missing
+            .iter()
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, path)| path.as_path())
+            .collect()
+    };
+
+    let roots = missing(EntryKind::Root);
+    state
+        .roots
+        .retain(|root, _| !roots.contains(root.as_path()));
+    let sessions = missing(EntryKind::Session);
+    state
+        .sessions
+        .retain(|_, session| !sessions.contains(session.root.as_path()));
+    let expanded = missing(EntryKind::Expanded);
+    let trees = state
+        .roots
+        .values_mut()
+        .chain(state.sessions.values_mut().map(|session| &mut session.tree));
+    for tree in trees {
+        tree.expanded_dirs
+            .retain(|p| !expanded.contains(p.as_path()));
+    }
+    let starred = missing(EntryKind::Starred);
+    state
+        .starred_dirs
+        .retain(|p| !starred.contains(p.as_path()));
+    let bookmarks = missing(EntryKind::Bookmark);
+    state
+        .bookmarks
+        .retain(|b| !bookmarks.contains(b.path.as_path()));
+    let recent = missing(EntryKind::Recent);
+    state
+        .recent_dirs
+        .retain(|r| !recent.contains(r.path.as_path()));
+
+    for root in state.roots.values_mut() {
+        cap_expanded(&mut root.expanded_dirs);
+    }
+    cap_roots(state);
+}
+
+/// Drops entries for deleted paths, caps expanded directories per root and
+/// drops the least recently used roots past the cap. With
+/// `keep_unmounted`, paths on a volume that is merely unmounted are kept.
+pub fn collect(state: &mut PersistentState, keep_unmounted: bool) -> GcReport {
+    let mut report = GcReport::default();
+    let mut keep = |kind: EntryKind, path: &Path| {
+        if path.exists() {
+            true
+        } else if keep_unmounted && on_unmounted_volume(path) {
+            report.kept_unmounted += 1;
+            true
+        } else {
+            report.missing.push((kind, path.to_path_buf()));
+            false
+        }
+    };
+
+    state.roots.retain(|root, _| keep(EntryKind::Root, root));
+    for root in state.roots.values_mut() {
+        root.expanded_dirs.retain(|p| keep(EntryKind::Expanded, p));
+        root.selected = root.selected.take().filter(|p| p.exists());
+    }
+    state
+        .sessions
+        .retain(|_, session| keep(EntryKind::Session, &session.root));
+    for session in state.sessions.values_mut() {
+        session
+            .tree
+            .expanded_dirs
+            .retain(|p| keep(EntryKind::Expanded, p));
+    }
+    state.starred_dirs.retain(|p| keep(EntryKind::Starred, p));
+    state
+        .bookmarks
+        .retain(|b| keep(EntryKind::Bookmark, &b.path));
+    state
+        .recent_dirs
+        .retain(|r| keep(EntryKind::Recent, &r.path));
+
+    for root in state.roots.values_mut() {
+        report.capped.extend(cap_expanded(&mut root.expanded_dirs));
+    }
+    report.capped.sort();
+    report.old_roots = cap_roots(state);
+    report.missing.sort_by(|a, b| a.1.cmp(&b.1));
+    report
+}
+
+/// Drops the least recently used roots past the cap, returning them.
+fn cap_roots(state: &mut PersistentState) -> Vec<PathBuf> {
+    if state.roots.len() <= MAX_ROOTS {
+        return Vec::new();
+    }
+    let mut roots: Vec<(u64, PathBuf)> = state
+        .roots
+        .iter()
+        .map(|(path, root)| (root.used_at, path.clone()))
+        .collect();
+    roots.sort();
+    let dropped: Vec<PathBuf> = roots
+        .drain(..roots.len() - MAX_ROOTS)
+        .map(|(_, path)| path)
+        .collect();
+    for root in &dropped {
+        state.roots.remove(root);
+    }
+    dropped
+}
+
+/// Trims a root's expanded directories to the cap, deepest first.
+fn cap_expanded(expanded: &mut HashSet<PathBuf>) -> Vec<PathBuf> {
+    if expanded.len() <= MAX_EXPANDED_PER_ROOT {
+        return Vec::new();
+    }
+    let mut dirs: Vec<PathBuf> = expanded.iter().cloned().collect();
+    dirs.sort_by_key(|d| (std::cmp::Reverse(d.components().count()), d.clone()));
+    let capped: Vec<PathBuf> = dirs.drain(..dirs.len() - MAX_EXPANDED_PER_ROOT).collect();
+    for dir in &capped {
+        expanded.remove(dir);
+    }
+    capped
+}
+
+/// Guesses whether a missing `path` lives on a volume that is not mounted right
+/// now: it sits under a usual mount location and its nearest existing ancestor
+/// is that location, a per-user directory in it, or an empty mount point.
+pub fn on_unmounted_volume(path: &Path) -> bool {
+    let Some(mount_root) = MOUNT_ROOTS
+        .iter()
+        .map(Path::new)
+        .find(|root| path.starts_with(root))
+    else {
+        return false;
+    };
+    let Some(existing) = path.ancestors().find(|a| a.exists()) else {
+        return false;
+    };
+
+    if !existing.starts_with(mount_root) {
+        return false;
+    }
+
+    // /media/<user> and /run/media/<user> hold one directory per volume
+    let user_dir = matches!(mount_root.to_str(), Some("/media" | "/run/media"))
+        && existing.parent() == Some(mount_root);
+    let empty_mount_point = existing
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_none());
+    existing == mount_root || user_dir || empty_mount_point
+}
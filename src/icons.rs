@@ -1,39 +1,525 @@
-use nerd_font_symbols::{cod, dev, fa, md};
+use crate::config::Diagnostic;
+use nerd_font_symbols::{cod, dev, fa, md, seti};
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
+use toml::Spanned;
 
-pub fn get_icon(path: &Path, is_expanded: bool) -> &'static str {
-    if path.is_dir() {
-        return get_dir_icon(is_expanded);
-    }
-
-    match path.extension().and_then(|e| e.to_str()) {
-        Some("rs") => dev::DEV_RUST,
-        Some("toml") => fa::FA_FILE_CODE,
-        Some("json") => fa::FA_FILE_CODE,
-        Some("md") => md::MD_LANGUAGE_MARKDOWN,
-        Some("txt") => fa::FA_FILE_TEXT_O,
-        Some("py") => dev::DEV_PYTHON,
-        Some("js") => dev::DEV_JAVASCRIPT,
-        Some("ts") => dev::DEV_JAVASCRIPT,
-        Some("html") => dev::DEV_HTML5,
-        Some("css") => dev::DEV_CSS3,
-        Some("yml" | "yaml") => fa::FA_FILE_CODE,
-        Some("sh" | "bash" | "zsh") => cod::COD_TERMINAL,
-        Some("png" | "jpg" | "jpeg" | "gif" | "svg" | "ico") => fa::FA_FILE_IMAGE,
-        Some("zip" | "tar" | "gz" | "rar" | "7z") => fa::FA_FILE_ZIPPER,
-        Some("pdf") => fa::FA_FILE_PDF,
-        Some("mp3" | "wav" | "flac" | "ogg") => fa::FA_FILE_AUDIO,
-        Some("mp4" | "avi" | "mkv" | "mov") => fa::FA_FILE_VIDEO,
-        Some("lock") => fa::FA_LOCK,
-        Some("git" | "gitignore") => dev::DEV_GIT,
-        _ => fa::FA_FILE_O,
-    }
-}
-
-pub fn get_dir_icon(is_expanded: bool) -> &'static str {
-    if is_expanded {
-        fa::FA_FOLDER_OPEN
-    } else {
-        fa::FA_FOLDER
+/// Which glyphs tree entries get in front of their names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconMode {
+    /// Nerd Font glyphs, for a patched terminal font
+    Nerd,
+    /// Emoji and symbols most fonts have
+    Unicode,
+    /// The file type letter `ls -l` shows
+    Ascii,
+    None,
+}
+
+impl IconMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            IconMode::Nerd => "nerd",
+            IconMode::Unicode => "unicode",
+            IconMode::Ascii => "ascii",
+            IconMode::None => "none",
+        }
+    }
+}
+
+impl FromStr for IconMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nerd" => Ok(IconMode::Nerd),
+            "unicode" => Ok(IconMode::Unicode),
+            "ascii" => Ok(IconMode::Ascii),
+            "none" => Ok(IconMode::None),
+            _ => Err(format!(
+                "unknown icon mode '{}', expected nerd, unicode, ascii or none",
+                s
+            )),
+        }
+    }
+}
+
+/// Nerd Font glyphs by extension, lowercased, without the dot.
+const NERD_EXTENSIONS: &[(&str, &str)] = &[
+    ("7z", fa::FA_FILE_ZIPPER),
+    ("aac", fa::FA_FILE_AUDIO),
+    ("avi", fa::FA_FILE_VIDEO),
+    ("bash", cod::COD_TERMINAL),
+    ("bmp", fa::FA_FILE_IMAGE),
+    ("bz2", fa::FA_FILE_ZIPPER),
+    ("c", dev::DEV_C_LANG),
+    ("cc", dev::DEV_CPLUSPLUS),
+    ("cfg", seti::SETI_CONFIG),
+    ("clj", dev::DEV_CLOJURE),
+    ("cljs", dev::DEV_CLOJURE),
+    ("cmake", dev::DEV_CMAKE),
+    ("conf", seti::SETI_CONFIG),
+    ("cpp", dev::DEV_CPLUSPLUS),
+    ("crt", md::MD_CERTIFICATE),
+    ("cs", dev::DEV_CSHARP),
+    ("css", dev::DEV_CSS3),
+    ("csv", md::MD_FILE_TABLE),
+    ("cxx", dev::DEV_CPLUSPLUS),
+    ("d", dev::DEV_DLANG),
+    ("dart", dev::DEV_DART),
+    ("db", fa::FA_DATABASE),
+    ("deb", fa::FA_FILE_ZIPPER),
+    ("diff", dev::DEV_GIT_COMPARE),
+    ("doc", fa::FA_FILE_WORD),
+    ("docx", fa::FA_FILE_WORD),
+    ("el", dev::DEV_EMACS),
+    ("elm", dev::DEV_ELM),
+    ("env", seti::SETI_CONFIG),
+    ("epub", fa::FA_BOOK),
+    ("erl", dev::DEV_ERLANG),
+    ("ex", dev::DEV_ELIXIR),
+    ("exs", dev::DEV_ELIXIR),
+    ("f90", dev::DEV_FORTRAN),
+    ("fish", cod::COD_TERMINAL),
+    ("flac", fa::FA_FILE_AUDIO),
+    ("fs", dev::DEV_FSHARP),
+    ("gif", fa::FA_FILE_IMAGE),
+    ("go", dev::DEV_GO),
+    ("gradle", dev::DEV_GRADLE),
+    ("graphql", dev::DEV_GRAPHQL),
+    ("groovy", dev::DEV_GROOVY),
+    ("gz", fa::FA_FILE_ZIPPER),
+    ("h", dev::DEV_C_LANG),
+    ("hpp", dev::DEV_CPLUSPLUS),
+    ("hs", dev::DEV_HASKELL),
+    ("htm", dev::DEV_HTML5),
+    ("html", dev::DEV_HTML5),
+    ("ico", fa::FA_FILE_IMAGE),
+    ("ini", seti::SETI_CONFIG),
+    ("ipynb", dev::DEV_JUPYTER),
+    ("iso", md::MD_ZIP_BOX),
+    ("java", dev::DEV_JAVA),
+    ("jl", dev::DEV_JULIA),
+    ("jpeg", fa::FA_FILE_IMAGE),
+    ("jpg", fa::FA_FILE_IMAGE),
+    ("js", dev::DEV_JAVASCRIPT),
+    ("json", fa::FA_FILE_CODE),
+    ("jsx", dev::DEV_REACT),
+    ("key", fa::FA_KEY),
+    ("kt", dev::DEV_KOTLIN),
+    ("kts", dev::DEV_KOTLIN),
+    ("less", dev::DEV_LESS),
+    ("lock", fa::FA_LOCK),
+    ("log", fa::FA_FILE_TEXT_O),
+    ("lua", dev::DEV_LUA),
+    ("m4a", fa::FA_FILE_AUDIO),
+    ("md", md::MD_LANGUAGE_MARKDOWN),
+    ("mkv", fa::FA_FILE_VIDEO),
+    ("ml", dev::DEV_OCAML),
+    ("mov", fa::FA_FILE_VIDEO),
+    ("mp3", fa::FA_FILE_AUDIO),
+    ("mp4", fa::FA_FILE_VIDEO),
+    ("nim", dev::DEV_NIM),
+    ("nix", dev::DEV_NIXOS),
+    ("odp", fa::FA_FILE_POWERPOINT),
+    ("ods", fa::FA_FILE_EXCEL),
+    ("odt", fa::FA_FILE_WORD),
+    ("ogg", fa::FA_FILE_AUDIO),
+    ("otf", fa::FA_FONT),
+    ("patch", dev::DEV_GIT_COMPARE),
+    ("pdf", fa::FA_FILE_PDF),
+    ("pem", fa::FA_KEY),
+    ("php", dev::DEV_PHP),
+    ("pl", dev::DEV_PERL),
+    ("png", fa::FA_FILE_IMAGE),
+    ("ppt", fa::FA_FILE_POWERPOINT),
+    ("pptx", fa::FA_FILE_POWERPOINT),
+    ("ps1", cod::COD_TERMINAL),
+    ("py", dev::DEV_PYTHON),
+    ("r", dev::DEV_R),
+    ("rar", fa::FA_FILE_ZIPPER),
+    ("rb", dev::DEV_RUBY),
+    ("rpm", fa::FA_FILE_ZIPPER),
+    ("rs", dev::DEV_RUST),
+    ("sass", dev::DEV_SASS),
+    ("scala", dev::DEV_SCALA),
+    ("scss", dev::DEV_SASS),
+    ("sh", cod::COD_TERMINAL),
+    ("sol", dev::DEV_SOLIDITY),
+    ("sql", fa::FA_DATABASE),
+    ("sqlite", dev::DEV_SQLITE),
+    ("svelte", dev::DEV_SVELTE),
+    ("svg", md::MD_SVG),
+    ("swift", dev::DEV_SWIFT),
+    ("tar", fa::FA_FILE_ZIPPER),
+    ("tex", dev::DEV_LATEX),
+    ("tf", dev::DEV_TERRAFORM),
+    ("tgz", fa::FA_FILE_ZIPPER),
+    ("toml", fa::FA_FILE_CODE),
+    ("ts", dev::DEV_TYPESCRIPT),
+    ("tsx", dev::DEV_REACT),
+    ("ttf", fa::FA_FONT),
+    ("txt", fa::FA_FILE_TEXT_O),
+    ("vim", dev::DEV_VIM),
+    ("vue", dev::DEV_VUEJS),
+    ("wasm", seti::SETI_WASM),
+    ("wav", fa::FA_FILE_AUDIO),
+    ("webm", fa::FA_FILE_VIDEO),
+    ("webp", fa::FA_FILE_IMAGE),
+    ("woff", fa::FA_FONT),
+    ("woff2", fa::FA_FONT),
+    ("xls", fa::FA_FILE_EXCEL),
+    ("xlsx", fa::FA_FILE_EXCEL),
+    ("xml", seti::SETI_XML),
+    ("xz", fa::FA_FILE_ZIPPER),
+    ("yaml", fa::FA_FILE_CODE),
+    ("yml", fa::FA_FILE_CODE),
+    ("zig", dev::DEV_ZIG),
+    ("zip", fa::FA_FILE_ZIPPER),
+    ("zsh", cod::COD_TERMINAL),
+    ("zst", fa::FA_FILE_ZIPPER),
+];
+
+/// Nerd Font glyphs by exact file name, which win over the extension.
+const NERD_FILES: &[(&str, &str)] = &[
+    (".bashrc", cod::COD_TERMINAL),
+    (".editorconfig", seti::SETI_EDITORCONFIG),
+    (".env", seti::SETI_CONFIG),
+    (".gitattributes", dev::DEV_GIT),
+    (".gitignore", dev::DEV_GIT),
+    (".gitmodules", dev::DEV_GIT),
+    (".npmrc", dev::DEV_NPM),
+    (".vimrc", dev::DEV_VIM),
+    (".zshrc", cod::COD_TERMINAL),
+    ("CHANGELOG.md", fa::FA_BOOK),
+    ("CMakeLists.txt", dev::DEV_CMAKE),
+    ("Cargo.lock", dev::DEV_RUST),
+    ("Cargo.toml", dev::DEV_RUST),
+    ("Dockerfile", dev::DEV_DOCKER),
+    ("Gemfile", dev::DEV_RUBY),
+    ("Jenkinsfile", dev::DEV_JENKINS),
+    ("LICENSE", md::MD_LICENSE),
+    ("Makefile", seti::SETI_MAKEFILE),
+    ("README.md", fa::FA_BOOK),
+    ("Rakefile", dev::DEV_RUBY),
+    ("build.gradle", dev::DEV_GRADLE),
+    ("docker-compose.yml", dev::DEV_DOCKER),
+    ("flake.nix", dev::DEV_NIXOS),
+    ("go.mod", dev::DEV_GO),
+    ("go.sum", dev::DEV_GO),
+    ("package-lock.json", dev::DEV_NPM),
+    ("package.json", dev::DEV_NPM),
+    ("pom.xml", dev::DEV_MAVEN),
+    ("pyproject.toml", dev::DEV_PYTHON),
+    ("requirements.txt", dev::DEV_PYTHON),
+    ("tsconfig.json", seti::SETI_TSCONFIG),
+    ("yarn.lock", dev::DEV_YARN),
+];
+
+/// Nerd Font glyphs by directory name.
+const NERD_DIRECTORIES: &[(&str, &str)] = &[
+    (".cargo", dev::DEV_RUST),
+    (".config", md::MD_FOLDER_COG),
+    (".git", dev::DEV_GIT),
+    (".github", dev::DEV_GITHUB),
+    (".ssh", md::MD_FOLDER_KEY),
+    (".vscode", dev::DEV_VSCODE),
+    ("Desktop", md::MD_FOLDER_ACCOUNT),
+    ("Documents", md::MD_FOLDER_TEXT),
+    ("Downloads", md::MD_FOLDER_DOWNLOAD),
+    ("Music", md::MD_FOLDER_MUSIC),
+    ("Pictures", md::MD_FOLDER_IMAGE),
+    ("Videos", fa::FA_FILM),
+    ("bin", md::MD_FOLDER_COG),
+    ("build", md::MD_FOLDER_WRENCH),
+    ("config", md::MD_FOLDER_COG),
+    ("docs", md::MD_FOLDER_TEXT),
+    ("node_modules", dev::DEV_NPM),
+    ("src", md::MD_FOLDER_STAR),
+    ("target", md::MD_FOLDER_WRENCH),
+    ("test", md::MD_TEST_TUBE),
+    ("tests", md::MD_TEST_TUBE),
+];
+
+/// Emoji by extension, for terminals without a Nerd Font.
+const UNICODE_EXTENSIONS: &[(&str, &str)] = &[
+    ("7z", "📦"),
+    ("avi", "🎞"),
+    ("bz2", "📦"),
+    ("csv", "📊"),
+    ("db", "🗄"),
+    ("deb", "📦"),
+    ("doc", "📝"),
+    ("docx", "📝"),
+    ("epub", "📚"),
+    ("flac", "🎵"),
+    ("gif", "🖼"),
+    ("gz", "📦"),
+    ("ico", "🖼"),
+    ("iso", "💿"),
+    ("jpeg", "🖼"),
+    ("jpg", "🖼"),
+    ("key", "🔑"),
+    ("lock", "🔒"),
+    ("md", "📝"),
+    ("mkv", "🎞"),
+    ("mov", "🎞"),
+    ("mp3", "🎵"),
+    ("mp4", "🎞"),
+    ("ogg", "🎵"),
+    ("pdf", "📕"),
+    ("pem", "🔑"),
+    ("png", "🖼"),
+    ("rar", "📦"),
+    ("sh", "📜"),
+    ("sql", "🗄"),
+    ("sqlite", "🗄"),
+    ("svg", "🖼"),
+    ("tar", "📦"),
+    ("tgz", "📦"),
+    ("txt", "📝"),
+    ("wav", "🎵"),
+    ("webm", "🎞"),
+    ("webp", "🖼"),
+    ("xls", "📊"),
+    ("xlsx", "📊"),
+    ("xz", "📦"),
+    ("zip", "📦"),
+    ("zsh", "📜"),
+    ("zst", "📦"),
+];
+
+const UNICODE_FILES: &[(&str, &str)] = &[
+    ("Dockerfile", "🐳"),
+    ("LICENSE", "📜"),
+    ("Makefile", "🛠"),
+    ("README.md", "📖"),
+];
+
+const UNICODE_DIRECTORIES: &[(&str, &str)] = &[(".git", "🌱"), ("node_modules", "📦")];
+
+/// The glyphs for tree entries: the mode's built-in tables with the
+/// `[icons]` config on top.
+#[derive(Debug, Clone)]
+pub struct Icons {
+    pub mode: IconMode,
+    extensions: BTreeMap<String, String>,
+    files: BTreeMap<String, String>,
+    directories: BTreeMap<String, String>,
+}
+
+impl Default for Icons {
+    fn default() -> Self {
+        Self::new(IconMode::Nerd)
+    }
+}
+
+impl Icons {
+    pub fn new(mode: IconMode) -> Self {
+        let (extensions, files, directories): (&[_], &[_], &[_]) = match mode {
+            IconMode::Nerd => (NERD_EXTENSIONS, NERD_FILES, NERD_DIRECTORIES),
+            IconMode::Unicode => (UNICODE_EXTENSIONS, UNICODE_FILES, UNICODE_DIRECTORIES),
+            IconMode::Ascii | IconMode::None => (&[], &[], &[]),
+        };
+        let table = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(name, icon)| (name.to_string(), icon.to_string()))
+                .collect()
+        };
+        Self {
+            mode,
+            extensions: table(extensions),
+            files: table(files),
+            directories: table(directories),
+        }
+    }
+
+    /// The glyph for `path`, or None when icons are off.
+    pub fn get(&self, path: &Path, is_expanded: bool) -> Option<&str> {
+        if self.mode == IconMode::None {
+            return None;
+        }
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+
+        if path.is_dir() {
+            if let Some(icon) = self.directories.get(name.as_ref()) {
+                return Some(icon);
+            }
+            return Some(self.dir_icon(is_expanded));
+        }
+        if let Some(icon) = self.files.get(name.as_ref()) {
+            return Some(icon);
+        }
+        if path.is_symlink() {
+            return Some(match self.mode {
+                IconMode::Unicode => "🔗",
+                IconMode::Ascii => "l",
+                _ => cod::COD_FILE_SYMLINK_FILE,
+            });
+        }
+        // Longest extension first, so `tar.gz` can differ from `gz`
+        let lower = name.to_lowercase();
+        let extension = lower
+            .char_indices()
+            .skip(1)
+            .filter(|&(_, c)| c == '.')
+            .find_map(|(i, _)| self.extensions.get(&lower[i + 1..]));
+        if let Some(icon) = extension {
+            return Some(icon);
+        }
+        Some(match self.mode {
+            IconMode::Unicode => "📄",
+            IconMode::Ascii => "-",
+            _ => fa::FA_FILE_O,
+        })
+    }
+
+    fn dir_icon(&self, is_expanded: bool) -> &'static str {
+        match (self.mode, is_expanded) {
+            (IconMode::Unicode, false) => "📁",
+            (IconMode::Unicode, true) => "📂",
+            (IconMode::Ascii, _) => "d",
+            (IconMode::None, _) => "",
+            (IconMode::Nerd, false) => fa::FA_FOLDER,
+            (IconMode::Nerd, true) => fa::FA_FOLDER_OPEN,
+        }
+    }
+
+    /// `name` with the icon for `path` in front, if icons are on.
+    pub fn label(&self, path: &Path, is_expanded: bool, name: &str) -> String {
+        match self.get(path, is_expanded) {
+            Some(icon) => format!("{} {}", icon, name),
+            None => name.to_string(),
+        }
+    }
+
+    fn apply(&mut self, table: &IconsTable) {
+        for (extension, icon) in &table.extensions {
+            let extension = extension.trim_start_matches('.').to_lowercase();
+            self.extensions.insert(extension, icon.clone());
+        }
+        for (name, icon) in &table.files {
+            self.files.insert(name.clone(), icon.clone());
+        }
+        for (name, icon) in &table.directories {
+            self.directories.insert(name.clone(), icon.clone());
+        }
+    }
+
+    /// The icons in effect, as an `[icons]` table.
+    pub fn to_toml(&self) -> toml::Table {
+        let table = |entries: &BTreeMap<String, String>| -> toml::Value {
+            entries
+                .iter()
+                .map(|(name, icon)| (name.clone(), toml::Value::from(icon.as_str())))
+                .collect::<toml::Table>()
+                .into()
+        };
+        let mut icons = toml::Table::new();
+        icons.insert("mode".to_string(), self.mode.name().into());
+        icons.insert("extensions".to_string(), table(&self.extensions));
+        icons.insert("files".to_string(), table(&self.files));
+        icons.insert("directories".to_string(), table(&self.directories));
+        icons
+    }
+}
+
+/// `icons = "mode"`, or an `[icons]` table of a mode and glyph mappings.
+#[derive(Debug)]
+pub enum IconsSetting {
+    Mode(Spanned<String>),
+    Table(IconsTable),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IconsTable {
+    mode: Option<Spanned<String>>,
+    /// By extension, like `rs` or `tar.gz`
+    #[serde(default)]
+    extensions: BTreeMap<String, String>,
+    /// By exact file name, like `Makefile`
+    #[serde(default)]
+    files: BTreeMap<String, String>,
+    /// By directory name, like `node_modules`
+    #[serde(default)]
+    directories: BTreeMap<String, String>,
+}
+
+impl<'de> Deserialize<'de> for IconsSetting {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        enum ModeOrTable {
+            Mode(String),
+            Table(IconsTable),
+        }
+
+        struct ModeOrTableVisitor;
+
+        impl<'de> Visitor<'de> for ModeOrTableVisitor {
+            type Value = ModeOrTable;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an icon mode or a table")
+            }
+
+            fn visit_str<E: de::Error>(self, mode: &str) -> Result<Self::Value, E> {
+                Ok(ModeOrTable::Mode(mode.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                IconsTable::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(ModeOrTable::Table)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for ModeOrTable {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_any(ModeOrTableVisitor)
+            }
+        }
+
+        // Spanned so an unknown mode can be pointed at
+        let value = Spanned::<ModeOrTable>::deserialize(deserializer)?;
+        let span = value.span();
+        Ok(match value.into_inner() {
+            ModeOrTable::Mode(mode) => IconsSetting::Mode(Spanned::new(span, mode)),
+            ModeOrTable::Table(table) => IconsSetting::Table(table),
+        })
+    }
+}
+
+/// Builds the icons from the `icons` setting.
+pub fn resolve(
+    setting: Option<&IconsSetting>,
+    contents: &str,
+    errors: &mut Vec<Diagnostic>,
+) -> Icons {
+    let (mode, table) = match setting {
+        None => return Icons::default(),
+        Some(IconsSetting::Mode(mode)) => (Some(mode), None),
+        Some(IconsSetting::Table(table)) => (table.mode.as_ref(), Some(table)),
+    };
+    let mode = match mode.map(|mode| mode.get_ref().parse::<IconMode>()) {
+        Some(Ok(mode)) => mode,
+        Some(Err(message)) => {
+            let span = mode.expect("mode was given").span();
+            errors.push(Diagnostic::at(contents, span.start, message));
+            IconMode::Nerd
+        }
+        None => IconMode::Nerd,
+    };
+
+    let mut icons = Icons::new(mode);
+    if let Some(table) = table {
+        icons.apply(table);
     }
+    icons
 }
@@ -0,0 +1,141 @@
+use crate::state::{self, RecentDir};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory jumpers whose databases can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Source {
+    Zoxide,
+    Autojump,
+    Z,
+    Fasd,
+}
+
+/// Reads every entry from a jumper database. Entries are returned as found;
+/// callers decide which paths are worth keeping.
+pub fn read(source: Source, file: &Path) -> io::Result<Vec<RecentDir>> {
+    match source {
+        Source::Zoxide => parse_zoxide(&fs::read(file)?),
+        Source::Autojump => {
+            // autojump keeps no access times, so the database's mtime stands in
+            let modified = fs::metadata(file)?
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or_else(state::now, |d| d.as_secs());
+            parse_autojump(&fs::read_to_string(file)?, modified)
+        }
+        Source::Z | Source::Fasd => parse_z(&fs::read_to_string(file)?),
+    }
+}
+
+fn invalid(line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+/// `weight<TAB>path` per line.
+fn parse_autojump(contents: &str, last_access: u64) -> io::Result<Vec<RecentDir>> {
+    let mut entries = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (weight, path) = line
+            .split_once('\t')
+            .ok_or_else(|| invalid(i + 1, "expected weight<TAB>path"))?;
+        let rank = weight
+            .trim()
+            .parse()
+            .map_err(|_| invalid(i + 1, "weight is not a number"))?;
+        entries.push(RecentDir {
+            path: PathBuf::from(path),
+            rank,
+            last_access,
+        });
+    }
+    Ok(entries)
+}
+
+/// `path|rank|time` per line, shared by z and fasd. Paths may contain `|`,
+/// so the two numeric fields are split off the end.
+fn parse_z(contents: &str) -> io::Result<Vec<RecentDir>> {
+    let mut entries = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.rsplitn(3, '|');
+        let (Some(time), Some(rank), Some(path)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid(i + 1, "expected path|rank|time"));
+        };
+        entries.push(RecentDir {
+            path: PathBuf::from(path),
+            rank: rank
+                .trim()
+                .parse()
+                .map_err(|_| invalid(i + 1, "rank is not a number"))?,
+            last_access: time
+                .trim()
+                .parse()
+                .map_err(|_| invalid(i + 1, "time is not a number"))?,
+        });
+    }
+    Ok(entries)
+}
+
+/// zoxide's `db.zo`: a little-endian u32 format version (3), then a
+/// bincode-encoded list of `{ path: String, rank: f64, last_accessed: u64 }`.
+fn parse_zoxide(bytes: &[u8]) -> io::Result<Vec<RecentDir>> {
+    let mut reader = ByteReader { bytes };
+    let version = u32::from_le_bytes(reader.take()?);
+    if version != 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported zoxide database version {}", version),
+        ));
+    }
+
+    let count = u64::from_le_bytes(reader.take()?);
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let len = u64::from_le_bytes(reader.take()?) as usize;
+        let path = std::str::from_utf8(reader.take_slice(len)?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "path is not UTF-8"))?;
+        let rank = f64::from_le_bytes(reader.take()?);
+        let last_access = u64::from_le_bytes(reader.take()?);
+        entries.push(RecentDir {
+            path: PathBuf::from(path),
+            rank,
+            last_access,
+        });
+    }
+    Ok(entries)
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take_slice(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "zoxide database is truncated",
+            ));
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let slice = self.take_slice(N)?;
+        Ok(slice.try_into().expect("slice has the requested length"))
+    }
+}
@@ -0,0 +1,679 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use toml::Spanned;
+
+/// Everything a key can be bound to in normal mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Help,
+    Search,
+    Up,
+    Down,
+    Collapse,
+    Expand,
+    Toggle,
+    Open,
+    Star,
+    StarredView,
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    First,
+    Last,
+    ToggleHidden,
+    TogglePreview,
+    Bookmark,
+    BookmarksView,
+    RecentView,
+    NewFile,
+    NewDir,
+    Rename,
+    Copy,
+    Cut,
+    Paste,
+    Trash,
+    Undo,
+    Mark,
+    ClearMarks,
+    Visual,
+    TrashView,
+    DiskUsageView,
+    ToggleApparentSize,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::Quit,
+        Action::Help,
+        Action::Search,
+        Action::Up,
+        Action::Down,
+        Action::Collapse,
+        Action::Expand,
+        Action::Toggle,
+        Action::Open,
+        Action::Star,
+        Action::StarredView,
+        Action::PageUp,
+        Action::PageDown,
+        Action::HalfPageUp,
+        Action::HalfPageDown,
+        Action::First,
+        Action::Last,
+        Action::ToggleHidden,
+        Action::TogglePreview,
+        Action::Bookmark,
+        Action::BookmarksView,
+        Action::RecentView,
+        Action::NewFile,
+        Action::NewDir,
+        Action::Rename,
+        Action::Copy,
+        Action::Cut,
+        Action::Paste,
+        Action::Trash,
+        Action::Undo,
+        Action::Mark,
+        Action::ClearMarks,
+        Action::Visual,
+        Action::TrashView,
+        Action::DiskUsageView,
+        Action::ToggleApparentSize,
+    ];
+
+    /// The name used for this action in `[keys]`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Help => "help",
+            Action::Search => "search",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Collapse => "collapse",
+            Action::Expand => "expand",
+            Action::Toggle => "toggle",
+            Action::Open => "open",
+            Action::Star => "star",
+            Action::StarredView => "starred-view",
+            Action::PageUp => "page-up",
+            Action::PageDown => "page-down",
+            Action::HalfPageUp => "half-page-up",
+            Action::HalfPageDown => "half-page-down",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::ToggleHidden => "toggle-hidden",
+            Action::TogglePreview => "toggle-preview",
+            Action::Bookmark => "bookmark",
+            Action::BookmarksView => "bookmarks-view",
+            Action::RecentView => "recent-view",
+            Action::NewFile => "new-file",
+            Action::NewDir => "new-dir",
+            Action::Rename => "rename",
+            Action::Copy => "copy",
+            Action::Cut => "cut",
+            Action::Paste => "paste",
+            Action::Trash => "trash",
+            Action::Undo => "undo",
+            Action::Mark => "mark",
+            Action::ClearMarks => "clear-marks",
+            Action::Visual => "visual",
+            Action::TrashView => "trash-view",
+            Action::DiskUsageView => "disk-usage-view",
+            Action::ToggleApparentSize => "toggle-apparent-size",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|a| a.name() == s)
+            .ok_or_else(|| format!("unknown action '{}'", s))
+    }
+}
+
+/// A single key press with its modifiers. Shift is folded into the character,
+/// so `G` and `shift-g` are the same chord.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        match code {
+            // Terminals send ctrl with a letter the same with or without shift
+            KeyCode::Char(c)
+                if modifiers.contains(KeyModifiers::CONTROL) && c.is_ascii_alphabetic() =>
+            {
+                Self {
+                    code: KeyCode::Char(c.to_ascii_lowercase()),
+                    modifiers: modifiers - KeyModifiers::SHIFT,
+                }
+            }
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => Self {
+                code: KeyCode::Char(c.to_ascii_uppercase()),
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            // Terminals disagree on whether shift-tab carries the shift flag
+            KeyCode::BackTab => Self {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => Self { code, modifiers },
+        }
+    }
+
+    fn plain(c: char) -> Self {
+        Self::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    fn is_plain_char(self) -> bool {
+        matches!(self.code, KeyCode::Char(c) if c != ' ') && self.modifiers.is_empty()
+    }
+
+    /// The chord as written in `[keys]`, like `ctrl-d` or `pgdn`.
+    fn config_name(self) -> String {
+        let mut name = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            name.push_str("ctrl-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            name.push_str("alt-");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            name.push_str("shift-");
+        }
+        match self.code {
+            KeyCode::Char(' ') => name.push_str("space"),
+            KeyCode::Char(c) => name.push(c),
+            KeyCode::PageUp => name.push_str("pgup"),
+            KeyCode::PageDown => name.push_str("pgdn"),
+            KeyCode::F(n) => name.push_str(&format!("f{}", n)),
+            code => name.push_str(&format!("{:?}", code).to_ascii_lowercase()),
+        }
+        name
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    /// Parses `j`, `G`, `space`, `pgdn`, `ctrl-d`, `alt-shift-x` and the like.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        // A lone "-" is a key, not an empty modifier list
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, s)),
+            };
+            rest = key;
+        }
+
+        let mut chars = rest.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(Self::new(KeyCode::Char(c), modifiers));
+        }
+        let code = match rest.to_ascii_lowercase().as_str() {
+            "space" => KeyCode::Char(' '),
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n @ 1..=24) => KeyCode::F(n),
+                _ => return Err(format!("unknown key '{}'", rest)),
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            KeyCode::Delete => write!(f, "Del"),
+            KeyCode::Insert => write!(f, "Ins"),
+            KeyCode::BackTab => write!(f, "Shift+Tab"),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// One or more chords pressed in order, like `gg` or `ctrl-w l`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeySequence(Vec<KeyChord>);
+
+impl FromStr for KeySequence {
+    type Err = String;
+
+    /// Chords are separated by spaces. A word that is not a key name or chord
+    /// is read as one key per character, so `gg` and `zc` need no spaces.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // " " on its own would otherwise split into nothing
+        if s == " " {
+            return Ok(Self(vec![KeyChord::plain(' ')]));
+        }
+        let mut chords = Vec::new();
+        for word in s.split_whitespace() {
+            match word.parse::<KeyChord>() {
+                Ok(chord) => chords.push(chord),
+                Err(_) if !word.contains('-') && word.chars().count() > 1 => {
+                    chords.extend(word.chars().map(KeyChord::plain));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if chords.is_empty() {
+            return Err("empty key".to_string());
+        }
+        Ok(Self(chords))
+    }
+}
+
+impl fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Runs of plain characters read best without separators
+        for (i, chord) in self.0.iter().enumerate() {
+            if i > 0 && !(chord.is_plain_char() && self.0[i - 1].is_plain_char()) {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", chord)?;
+        }
+        Ok(())
+    }
+}
+
+impl KeySequence {
+    fn is_prefix_of(&self, other: &KeySequence) -> bool {
+        other.0.starts_with(&self.0)
+    }
+
+    /// The sequence as written in `[keys]`. Plain characters run together
+    /// unless that would spell a key name, as in `e n d`.
+    pub fn config_name(&self) -> String {
+        let run = self.0.iter().all(|c| c.is_plain_char())
+            && self.0.len() > 1
+            && !self.0.iter().any(|c| c.code == KeyCode::Char('-'));
+        let names: Vec<String> = self.0.iter().map(|c| c.config_name()).collect();
+        let joined = names.concat();
+        if run && joined.parse::<KeyChord>().is_err() {
+            joined
+        } else {
+            names.join(" ")
+        }
+    }
+}
+
+/// Result of feeding a key press to the keymap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    Action(Action),
+    /// The keys so far start a longer binding
+    Pending,
+    Unbound,
+}
+
+const DEFAULT_BINDINGS: &[(&str, Action)] = &[
+    ("q", Action::Quit),
+    ("esc", Action::Quit),
+    ("ctrl-c", Action::Quit),
+    ("?", Action::Help),
+    ("/", Action::Search),
+    ("k", Action::Up),
+    ("up", Action::Up),
+    ("j", Action::Down),
+    ("down", Action::Down),
+    ("h", Action::Collapse),
+    ("left", Action::Collapse),
+    ("l", Action::Expand),
+    ("right", Action::Expand),
+    ("space", Action::Toggle),
+    ("enter", Action::Open),
+    ("s", Action::Star),
+    ("S", Action::StarredView),
+    ("pgup", Action::PageUp),
+    ("pgdn", Action::PageDown),
+    ("ctrl-u", Action::HalfPageUp),
+    ("ctrl-d", Action::HalfPageDown),
+    ("g", Action::First),
+    ("home", Action::First),
+    ("G", Action::Last),
+    ("end", Action::Last),
+    (".", Action::ToggleHidden),
+    ("p", Action::TogglePreview),
+    ("b", Action::Bookmark),
+    ("B", Action::BookmarksView),
+    ("r", Action::RecentView),
+    ("a", Action::NewFile),
+    ("A", Action::NewDir),
+    ("R", Action::Rename),
+    ("y", Action::Copy),
+    ("x", Action::Cut),
+    ("P", Action::Paste),
+    ("D", Action::Trash),
+    ("u", Action::Undo),
+    ("m", Action::Mark),
+    ("M", Action::ClearMarks),
+    ("V", Action::Visual),
+    ("T", Action::TrashView),
+    ("U", Action::DiskUsageView),
+    ("z", Action::ToggleApparentSize),
+];
+
+/// Key sequences bound to actions: the defaults, overridden by `[keys]`.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// In order of preference for display: configured keys first
+    bindings: Vec<(KeySequence, Action)>,
+    /// Keys configured as `none`
+    unbound: Vec<KeySequence>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: DEFAULT_BINDINGS
+                .iter()
+                .map(|(key, action)| (key.parse().expect("default key is valid"), *action))
+                .collect(),
+            unbound: Vec::new(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Applies a `[keys]` table of key to action name, where `none` unbinds
+    /// the key. Defaults that clash with a configured key are dropped; bad
+    /// entries and clashes between configured keys are skipped and reported
+    /// with the span of the offending key or action.
+    pub fn with_config(
+        table: &BTreeMap<Spanned<String>, Spanned<String>>,
+    ) -> (Self, Vec<(Range<usize>, String)>) {
+        let mut errors = Vec::new();
+        let mut configured: Vec<(KeySequence, Option<Action>, &str)> = Vec::new();
+        for (key, action) in table {
+            let sequence = match key.get_ref().parse::<KeySequence>() {
+                Ok(sequence) => sequence,
+                Err(e) => {
+                    errors.push((key.span(), e));
+                    continue;
+                }
+            };
+            let action = match action.get_ref().as_str() {
+                "none" => None,
+                name => match name.parse() {
+                    Ok(action) => Some(action),
+                    Err(e) => {
+                        errors.push((action.span(), e));
+                        continue;
+                    }
+                },
+            };
+            let clash = configured.iter().find(|(other, _, _)| {
+                other.is_prefix_of(&sequence) || sequence.is_prefix_of(other)
+            });
+            if let Some((other, _, other_key)) = clash {
+                errors.push((
+                    key.span(),
+                    if *other == sequence {
+                        format!("'{}' is the same key as '{}'", key.get_ref(), other_key)
+                    } else {
+                        format!("'{}' conflicts with '{}'", key.get_ref(), other_key)
+                    },
+                ));
+                continue;
+            }
+            configured.push((sequence, action, key.get_ref()));
+        }
+
+        let defaults: Vec<(KeySequence, Action)> = Self::default()
+            .bindings
+            .into_iter()
+            .filter(|(default, _)| {
+                !configured
+                    .iter()
+                    .any(|(key, _, _)| key.is_prefix_of(default) || default.is_prefix_of(key))
+            })
+            .collect();
+        let mut bindings = Vec::new();
+        let mut unbound = Vec::new();
+        for (key, action, _) in configured {
+            match action {
+                Some(action) => bindings.push((key, action)),
+                None => unbound.push(key),
+            }
+        }
+        bindings.extend(defaults);
+        (Self { bindings, unbound }, errors)
+    }
+
+    /// Every binding in effect, configured keys first, then the keys
+    /// configured as `none`.
+    pub fn bindings(&self) -> impl Iterator<Item = (&KeySequence, Option<Action>)> {
+        self.bindings
+            .iter()
+            .map(|(key, action)| (key, Some(*action)))
+            .chain(self.unbound.iter().map(|key| (key, None)))
+    }
+
+    /// Looks up the keys pressed so far, the last one included.
+    pub fn lookup(&self, pressed: &[KeyChord]) -> Lookup {
+        let mut lookup = Lookup::Unbound;
+        for (sequence, action) in &self.bindings {
+            if sequence.0 == pressed {
+                return Lookup::Action(*action);
+            }
+            if sequence.0.starts_with(pressed) {
+                lookup = Lookup::Pending;
+            }
+        }
+        lookup
+    }
+
+    /// The keys bound to `action`, preferred first.
+    pub fn keys(&self, action: Action) -> impl Iterator<Item = &KeySequence> {
+        self.bindings
+            .iter()
+            .filter(move |(_, a)| *a == action)
+            .map(|(key, _)| key)
+    }
+
+    /// The preferred key for each of `actions`, joined with `/` for the footer.
+    /// None if any of them is unbound.
+    pub fn short(&self, actions: &[Action]) -> Option<String> {
+        let keys: Option<Vec<String>> = actions
+            .iter()
+            .map(|a| self.keys(*a).next().map(|k| k.to_string()))
+            .collect();
+        Some(keys?.join("/"))
+    }
+
+    /// Every key bound to `action`, for the help overlay.
+    pub fn describe(&self, action: Action) -> String {
+        let keys: Vec<String> = self.keys(action).map(|k| k.to_string()).collect();
+        if keys.is_empty() {
+            "(unbound)".to_string()
+        } else {
+            keys.join(" / ")
+        }
+    }
+}
+
+/// Shows keys pressed so far as part of an unfinished sequence.
+pub fn pending_display(pressed: &[KeyChord]) -> String {
+    KeySequence(pressed.to_vec()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        KeyChord { code, modifiers }
+    }
+
+    fn keys(s: &str) -> Vec<KeyChord> {
+        s.parse::<KeySequence>().unwrap().0
+    }
+
+    fn keymap(toml: &str) -> (Keymap, Vec<String>) {
+        let table = toml::from_str(toml).unwrap();
+        let (keymap, errors) = Keymap::with_config(&table);
+        (keymap, errors.into_iter().map(|(_, e)| e).collect())
+    }
+
+    #[test]
+    fn chord_parses_names_modifiers_and_shifted_letters() {
+        let parse = |s: &str| s.parse::<KeyChord>().unwrap();
+        assert_eq!(parse("j"), chord(KeyCode::Char('j'), KeyModifiers::NONE));
+        assert_eq!(parse("G"), parse("shift-g"));
+        assert_eq!(parse("-"), chord(KeyCode::Char('-'), KeyModifiers::NONE));
+        assert_eq!(
+            parse("space"),
+            chord(KeyCode::Char(' '), KeyModifiers::NONE)
+        );
+        assert_eq!(parse("PgDn"), chord(KeyCode::PageDown, KeyModifiers::NONE));
+        assert_eq!(parse("f12"), chord(KeyCode::F(12), KeyModifiers::NONE));
+        assert_eq!(
+            parse("ctrl-d"),
+            chord(KeyCode::Char('d'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            parse("alt-shift-x"),
+            chord(KeyCode::Char('X'), KeyModifiers::ALT)
+        );
+        assert_eq!(parse("c-left"), chord(KeyCode::Left, KeyModifiers::CONTROL));
+        assert!("hyper-x".parse::<KeyChord>().is_err());
+        assert!("f25".parse::<KeyChord>().is_err());
+        assert!("nokey".parse::<KeyChord>().is_err());
+    }
+
+    #[test]
+    fn ctrl_letters_are_lowercase_however_written_or_sent() {
+        let ctrl_d = chord(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        assert_eq!("ctrl-D".parse::<KeyChord>().unwrap(), ctrl_d);
+        assert_eq!("ctrl-shift-d".parse::<KeyChord>().unwrap(), ctrl_d);
+        let pressed = KeyEvent::new(
+            KeyCode::Char('D'),
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        );
+        assert_eq!(KeyChord::from(pressed), ctrl_d);
+    }
+
+    #[test]
+    fn sequences_split_words_that_are_not_key_names() {
+        assert_eq!(keys("gg"), vec![KeyChord::plain('g'); 2]);
+        assert_eq!(keys("up"), vec![chord(KeyCode::Up, KeyModifiers::NONE)]);
+        assert_eq!(
+            keys("ctrl-x k"),
+            vec![
+                chord(KeyCode::Char('x'), KeyModifiers::CONTROL),
+                KeyChord::plain('k')
+            ]
+        );
+        assert!("ctrl-x nokey-k".parse::<KeySequence>().is_err());
+        assert!("".parse::<KeySequence>().is_err());
+    }
+
+    #[test]
+    fn lookup_waits_for_the_rest_of_a_sequence() {
+        let (keymap, errors) = keymap(
+            r#"gg = "first"
+"ctrl-x k" = "trash""#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(keymap.lookup(&keys("g")), Lookup::Pending);
+        assert_eq!(keymap.lookup(&keys("gg")), Lookup::Action(Action::First));
+        assert_eq!(keymap.lookup(&keys("ctrl-x")), Lookup::Pending);
+        assert_eq!(
+            keymap.lookup(&keys("ctrl-x k")),
+            Lookup::Action(Action::Trash)
+        );
+        assert_eq!(keymap.lookup(&keys("ctrl-x j")), Lookup::Unbound);
+        // The default g is dropped, as it would fire before gg could be typed
+        let first: Vec<String> = keymap.keys(Action::First).map(|k| k.to_string()).collect();
+        assert_eq!(first, ["gg", "Home"]);
+        assert_eq!(keymap.lookup(&keys("j")), Lookup::Action(Action::Down));
+    }
+
+    #[test]
+    fn conflicting_configured_keys_are_reported_and_skipped() {
+        let (keymap, errors) = keymap(
+            r#"g = "first"
+gg = "last"
+G = "quit"
+shift-g = "help""#,
+        );
+        // Tables are read in key order: G, g, gg, shift-g
+        assert_eq!(
+            errors,
+            vec![
+                "'gg' conflicts with 'g'".to_string(),
+                "'shift-g' is the same key as 'G'".to_string(),
+            ]
+        );
+        assert_eq!(keymap.lookup(&keys("g")), Lookup::Action(Action::First));
+        assert_eq!(keymap.lookup(&keys("G")), Lookup::Action(Action::Quit));
+    }
+
+    #[test]
+    fn none_unbinds_a_default_key() {
+        let (keymap, errors) = keymap(r#"q = "none""#);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(keymap.lookup(&keys("q")), Lookup::Unbound);
+        assert_eq!(keymap.lookup(&keys("esc")), Lookup::Action(Action::Quit));
+        assert!(keymap
+            .bindings()
+            .any(|(key, action)| key.0 == keys("q") && action.is_none()));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::config::Diagnostic;
+use crate::theme::{self, ColorValue, StyleValue, ThemeTable};
+use ratatui::style::{Color, Modifier, Style};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+
+/// What `ls` uses when `LS_COLORS` is not set.
+const DEFAULT_LS_COLORS: &str = "di=01;34:ln=01;36:pi=40;33:so=01;35:do=01;35:bd=40;33;01:\
+    cd=40;33;01:or=40;31;01:su=37;41:sg=30;43:tw=30;42:ow=34;42:st=37;44:ex=01;32";
+
+/// The `LS_COLORS` file kinds, with the names `[colors]` also accepts.
+const KINDS: &[(&str, &str)] = &[
+    ("fi", "file"),
+    ("di", "directory"),
+    ("ln", "symlink"),
+    ("or", "broken_symlink"),
+    ("mi", "missing"),
+    ("pi", "pipe"),
+    ("so", "socket"),
+    ("do", "door"),
+    ("bd", "block_device"),
+    ("cd", "char_device"),
+    ("ex", "executable"),
+    ("su", "setuid"),
+    ("sg", "setgid"),
+    ("st", "sticky"),
+    ("ow", "other_writable"),
+    ("tw", "sticky_other_writable"),
+];
+
+/// How tree entries are colored by file kind and name, like `ls --color`.
+#[derive(Debug, Clone)]
+pub struct LsColors {
+    /// By `LS_COLORS` code, like `di`
+    kinds: BTreeMap<&'static str, Style>,
+    /// Name suffixes like `.rs` from `*.rs`, lowercased, longest first
+    suffixes: Vec<(String, Style)>,
+    /// `ln=target`: color symlinks like the file they point to
+    link_as_target: bool,
+}
+
+impl Default for LsColors {
+    fn default() -> Self {
+        Self::parse(DEFAULT_LS_COLORS)
+    }
+}
+
+impl LsColors {
+    /// Reads `LS_COLORS`, falling back to the colors `ls` uses without it.
+    pub fn from_env() -> Self {
+        match env::var("LS_COLORS") {
+            Ok(value) if !value.is_empty() => Self::parse(&value),
+            _ => Self::default(),
+        }
+    }
+
+    /// Parses an `LS_COLORS` value. Entries that can't be read are skipped,
+    /// as `ls` does.
+    pub fn parse(value: &str) -> Self {
+        let mut colors = Self {
+            kinds: BTreeMap::new(),
+            suffixes: Vec::new(),
+            link_as_target: false,
+        };
+        for entry in value.split(':') {
+            let Some((key, sgr)) = entry.split_once('=') else {
+                continue;
+            };
+            if key == "ln" && sgr == "target" {
+                colors.link_as_target = true;
+            } else if let Ok(style) = parse_sgr(sgr) {
+                colors.set(key, style);
+            }
+        }
+        colors
+    }
+
+    /// Applies a `[colors]` table, whose keys are `LS_COLORS` codes, kind names
+    /// or `*` patterns, and whose values are colors, style tables or SGR codes.
+    pub fn apply(&mut self, table: &ThemeTable, contents: &str, errors: &mut Vec<Diagnostic>) {
+        for (key, value) in table.entries() {
+            let key_name = key.get_ref().as_str();
+            if !key_name.starts_with('*') && kind_code(key_name).is_none() {
+                errors.push(Diagnostic::at(
+                    contents,
+                    key.span().start,
+                    format!(
+                        "unknown file kind '{}', expected an LS_COLORS code, a kind name or a *pattern",
+                        key_name
+                    ),
+                ));
+                continue;
+            }
+            let style = match value.get_ref() {
+                StyleValue::Color(ColorValue::Name(name)) if name == "target" => {
+                    if kind_code(key_name) == Some("ln") {
+                        self.link_as_target = true;
+                        continue;
+                    }
+                    Err("only symlinks can be colored as their 'target'".to_string())
+                }
+                StyleValue::Color(ColorValue::Name(sgr))
+                    if sgr.chars().all(|c| c.is_ascii_digit() || c == ';') =>
+                {
+                    parse_sgr(sgr)
+                }
+                value => {
+                    let mut style = Style::default();
+                    value.apply_to(&mut style).map(|()| style)
+                }
+            };
+            match style {
+                Ok(style) => self.set(key_name, style),
+                Err(message) => errors.push(Diagnostic::at(contents, value.span().start, message)),
+            }
+        }
+    }
+
+    /// Sets the style of an `LS_COLORS` key. Unknown keys are ignored.
+    fn set(&mut self, key: &str, style: Style) {
+        if let Some(pattern) = key.strip_prefix('*') {
+            let pattern = pattern.to_lowercase();
+            self.suffixes.retain(|(suffix, _)| *suffix != pattern);
+            // Longest first, so `*.tar.gz` wins over `*.gz`
+            let index = self
+                .suffixes
+                .iter()
+                .position(|(suffix, _)| suffix.len() < pattern.len())
+                .unwrap_or(self.suffixes.len());
+            self.suffixes.insert(index, (pattern, style));
+        } else if let Some(code) = kind_code(key) {
+            if code == "ln" {
+                self.link_as_target = false;
+            }
+            self.kinds.insert(code, style);
+        }
+    }
+
+    /// The style for the entry at `path`.
+    pub fn style(&self, path: &Path) -> Style {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return self.kind("mi").unwrap_or_default();
+        };
+        if metadata.file_type().is_symlink() {
+            return match fs::metadata(path) {
+                Err(_) => self.kind("or").or(self.kind("ln")).unwrap_or_default(),
+                Ok(target) if self.link_as_target => self.style_for(path, &target),
+                Ok(_) => self.kind("ln").unwrap_or_default(),
+            };
+        }
+        self.style_for(path, &metadata)
+    }
+
+    fn style_for(&self, path: &Path, metadata: &Metadata) -> Style {
+        let file_type = metadata.file_type();
+        let mode = metadata.permissions().mode();
+        let code = if file_type.is_dir() {
+            match (mode & 0o1000 != 0, mode & 0o002 != 0) {
+                (true, true) => "tw",
+                (false, true) => "ow",
+                (true, false) => "st",
+                (false, false) => "di",
+            }
+        } else if file_type.is_fifo() {
+            "pi"
+        } else if file_type.is_socket() {
+            "so"
+        } else if file_type.is_block_device() {
+            "bd"
+        } else if file_type.is_char_device() {
+            "cd"
+        } else if mode & 0o4000 != 0 {
+            "su"
+        } else if mode & 0o2000 != 0 {
+            "sg"
+        } else if mode & 0o111 != 0 {
+            "ex"
+        } else {
+            return self.file_style(path);
+        };
+        // Kinds without a color of their own fall back to the plainer kind
+        let fallback = match code {
+            "tw" | "ow" | "st" => self.kind("di"),
+            "su" | "sg" => self.kind("ex").or(self.kind("fi")),
+            _ => self.kind("fi"),
+        };
+        self.kind(code).or(fallback).unwrap_or_default()
+    }
+
+    fn file_style(&self, path: &Path) -> Style {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        self.suffixes
+            .iter()
+            .find(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .map(|(_, style)| *style)
+            .or(self.kind("fi"))
+            .unwrap_or_default()
+    }
+
+    fn kind(&self, code: &str) -> Option<Style> {
+        self.kinds.get(code).copied()
+    }
+
+    /// The colors in effect, as a `[colors]` table.
+    pub fn to_toml(&self) -> toml::Table {
+        let mut table = toml::Table::new();
+        for (code, name) in KINDS {
+            if let Some(style) = self.kind(code) {
+                table.insert(name.to_string(), theme::style_value(style));
+            }
+        }
+        if self.link_as_target {
+            table.insert("symlink".to_string(), "target".into());
+        }
+        for (suffix, style) in &self.suffixes {
+            table.insert(format!("*{}", suffix), theme::style_value(*style));
+        }
+        table
+    }
+}
+
+fn kind_code(key: &str) -> Option<&'static str> {
+    KINDS
+        .iter()
+        .find(|(code, name)| key == *code || key == *name)
+        .map(|(code, _)| *code)
+}
+
+/// The 16 basic terminal colors, in SGR order.
+const ANSI_COLORS: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Gray,
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::White,
+];
+
+/// Reads the SGR codes of an `LS_COLORS` entry, like `01;38;5;208`.
+fn parse_sgr(sgr: &str) -> Result<Style, String> {
+    let invalid = || format!("invalid color code '{}'", sgr);
+    let mut style = Style::default();
+    let mut codes = sgr
+        .split(';')
+        .filter(|code| !code.is_empty())
+        .map(|code| code.parse::<u8>().map_err(|_| invalid()));
+    while let Some(code) = codes.next() {
+        let code = code?;
+        match code {
+            0 => style = Style::default(),
+            1 => style = style.add_modifier(Modifier::BOLD),
+            2 => style = style.add_modifier(Modifier::DIM),
+            3 => style = style.add_modifier(Modifier::ITALIC),
+            4 => style = style.add_modifier(Modifier::UNDERLINED),
+            5 => style = style.add_modifier(Modifier::SLOW_BLINK),
+            6 => style = style.add_modifier(Modifier::RAPID_BLINK),
+            7 => style = style.add_modifier(Modifier::REVERSED),
+            8 => style = style.add_modifier(Modifier::HIDDEN),
+            9 => style = style.add_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style = style.fg(ANSI_COLORS[usize::from(code - 30)]),
+            39 => style.fg = None,
+            40..=47 => style = style.bg(ANSI_COLORS[usize::from(code - 40)]),
+            49 => style.bg = None,
+            90..=97 => style = style.fg(ANSI_COLORS[usize::from(code - 90 + 8)]),
+            100..=107 => style = style.bg(ANSI_COLORS[usize::from(code - 100 + 8)]),
+            38 | 48 => {
+                let color = match codes.next().transpose()? {
+                    Some(5) => Color::Indexed(codes.next().transpose()?.ok_or_else(invalid)?),
+                    Some(2) => {
+                        let mut channel = || codes.next().transpose()?.ok_or_else(invalid);
+                        Color::Rgb(channel()?, channel()?, channel()?)
+                    }
+                    _ => return Err(invalid()),
+                };
+                style = if code == 38 {
+                    style.fg(color)
+                } else {
+                    style.bg(color)
+                };
+            }
+            _ => {}
+        }
+    }
+    Ok(style)
+}
@@ -1,12 +1,26 @@
 mod app;
+mod cli;
 mod config;
+mod fileops;
+mod gc;
 mod icons;
+mod import;
+mod keymap;
+mod lscolors;
+mod preview;
+mod search;
+mod shell;
 mod size;
+mod sizecache;
 mod state;
+mod theme;
+mod trash;
 mod tree;
 mod ui;
+mod undo;
+mod watcher;
 
-use app::App;
+use app::{App, PickMode, Picker};
 use clap::Parser;
 use color_eyre::Result;
 use crossterm::{
@@ -16,22 +30,50 @@ use crossterm::{
 };
 use ratatui::prelude::*;
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
+use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "treenav")]
 #[command(about = "A terminal-based directory tree navigator with persistent state")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    #[arg(default_value = ".")]
-    path: PathBuf,
+    #[command(subcommand)]
+    command: Option<cli::Command>,
+
+    /// Directory to open [default: the session's root, or .]
+    path: Option<PathBuf>,
+
+    /// Restore the named session and save it again on exit
+    #[arg(long, value_name = "NAME")]
+    session: Option<String>,
+
+    /// Use as a chooser: Enter prints the selected entry if it is of this kind
+    #[arg(long, value_enum, value_name = "KIND")]
+    pick: Option<PickMode>,
+
+    /// With --pick, print every marked entry
+    #[arg(long, requires = "pick")]
+    multi: bool,
+
+    /// Separate printed paths with NUL instead of newline
+    #[arg(long)]
+    print0: bool,
+
+    /// Leave other filesystems out of directory sizes, like `du -x`
+    #[arg(long)]
+    one_file_system: bool,
 }
 
 fn main() -> Result<()> {
     color_eyre::install()?;
 
     let args = Args::parse();
-    let path = args.path.canonicalize()?;
+    if let Some(command) = args.command {
+        return cli::run(command);
+    }
+    let path = args.path.map(|p| p.canonicalize()).transpose()?;
 
     // Open /dev/tty directly for terminal I/O (allows stdout to be captured)
     let tty = File::options().read(true).write(true).open("/dev/tty")?;
@@ -43,7 +85,11 @@ fn main() -> Result<()> {
     let backend = CrosstermBackend::new(tty_writer);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut app = App::new(path)?;
+    let picker = args.pick.map(|mode| Picker {
+        mode,
+        multi: args.multi,
+    });
+    let mut app = App::new(path, picker, args.session, args.one_file_system)?;
     let result = app.run(&mut terminal);
 
     // Restore terminal
@@ -54,9 +100,22 @@ fn main() -> Result<()> {
         DisableMouseCapture
     )?;
 
-    // Print selected directory to stdout (can be captured by shell)
-    if let Some(selected_dir) = app.selected_dir {
-        println!("{}", selected_dir.display());
+    for warning in &app.exit_warnings {
+        eprintln!("treenav: {}", warning);
+    }
+
+    // Print selected paths to stdout (can be captured by shell)
+    let separator = if args.print0 { b'\0' } else { b'\n' };
+    let mut stdout = std::io::stdout().lock();
+    for path in &app.selected_paths {
+        stdout.write_all(path.as_os_str().as_bytes())?;
+        stdout.write_all(&[separator])?;
+    }
+    stdout.flush()?;
+
+    // Let scripts tell a cancelled pick apart from an empty result
+    if result.is_ok() && picker.is_some() && app.selected_paths.is_empty() {
+        std::process::exit(1);
     }
 
     result
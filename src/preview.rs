@@ -0,0 +1,342 @@
+use crate::config::Diagnostic;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{self, FontStyle, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use toml::Spanned;
+
+/// Lines of a file the preview shows.
+const PREVIEW_LINES: usize = 100;
+
+/// Bytes read at most, so a huge file on one line is cut short too.
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+/// Highlight themes that come with syntect, as named in `[preview]`.
+pub const HIGHLIGHT_THEMES: &[&str] = &[
+    "base16-ocean.dark",
+    "base16-eighties.dark",
+    "base16-mocha.dark",
+    "base16-ocean.light",
+    "InspiredGitHub",
+    "Solarized (dark)",
+    "Solarized (light)",
+];
+
+/// The first lines of a file, highlighted with `highlight` if set.
+pub struct Preview {
+    pub path: PathBuf,
+    pub highlight: Option<&'static str>,
+    pub contents: PreviewContents,
+}
+
+pub enum PreviewContents {
+    Lines(Vec<Line<'static>>),
+    /// The file has NUL bytes, so it is not shown as text
+    Binary,
+    Unreadable,
+}
+
+struct Request {
+    path: PathBuf,
+    highlight: Option<&'static str>,
+}
+
+/// Reads and highlights file previews on a thread of its own. Only the
+/// latest request counts; older ones are skipped or their result dropped.
+pub struct PreviewWorker {
+    request_tx: Sender<Request>,
+    result_rx: Receiver<Preview>,
+    /// The request whose result is still to come
+    requested: Option<(PathBuf, Option<&'static str>)>,
+}
+
+impl PreviewWorker {
+    pub fn new() -> Self {
+        let (request_tx, request_rx) = unbounded::<Request>();
+        let (result_tx, result_rx) = unbounded();
+
+        thread::spawn(move || {
+            // Loading the syntaxes takes a moment, so wait until they are needed
+            let mut highlighter: Option<Highlighter> = None;
+            while let Ok(mut request) = request_rx.recv() {
+                // Skip to the latest request, the selection has moved on
+                while let Ok(next) = request_rx.try_recv() {
+                    request = next;
+                }
+                let contents = match read_lines(&request.path) {
+                    Ok(Some(lines)) => PreviewContents::Lines(match request.highlight {
+                        Some(theme) => {
+                            let highlighter = highlighter.get_or_insert_with(Highlighter::new);
+                            highlighter.highlight(&request.path, lines, theme)
+                        }
+                        None => lines.into_iter().map(Line::from).collect(),
+                    }),
+                    Ok(None) => PreviewContents::Binary,
+                    Err(_) => PreviewContents::Unreadable,
+                };
+                let preview = Preview {
+                    path: request.path,
+                    highlight: request.highlight,
+                    contents,
+                };
+                if result_tx.send(preview).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            request_tx,
+            result_rx,
+            requested: None,
+        }
+    }
+
+    /// Previews `path`, unless that is already under way.
+    pub fn request(&mut self, path: &Path, highlight: Option<&'static str>) {
+        if self
+            .requested
+            .as_ref()
+            .is_some_and(|(p, h)| p == path && *h == highlight)
+        {
+            return;
+        }
+        self.requested = Some((path.to_path_buf(), highlight));
+        let _ = self.request_tx.send(Request {
+            path: path.to_path_buf(),
+            highlight,
+        });
+    }
+
+    /// The preview for the latest request, once it is done.
+    pub fn poll(&mut self) -> Option<Preview> {
+        let mut latest = None;
+        while let Ok(preview) = self.result_rx.try_recv() {
+            if self
+                .requested
+                .as_ref()
+                .is_some_and(|(p, h)| *p == preview.path && *h == preview.highlight)
+            {
+                self.requested = None;
+                latest = Some(preview);
+            }
+        }
+        latest
+    }
+}
+
+struct Highlighter {
+    syntaxes: SyntaxSet,
+    themes: ThemeSet,
+}
+
+impl Highlighter {
+    fn new() -> Self {
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            themes: ThemeSet::load_defaults(),
+        }
+    }
+
+    fn highlight(&self, path: &Path, lines: Vec<String>, theme: &str) -> Vec<Line<'static>> {
+        let Some(theme) = self.themes.themes.get(theme) else {
+            return lines.into_iter().map(Line::from).collect();
+        };
+        let syntax = self.syntax_for(path, lines.first().map(String::as_str));
+        let mut highlighter = HighlightLines::new(syntax, theme);
+
+        let mut highlighted = Vec::with_capacity(lines.len());
+        for line in lines {
+            // The syntaxes expect each line to end in a newline
+            let line = line + "\n";
+            let spans = match highlighter.highlight_line(&line, &self.syntaxes) {
+                Ok(regions) => regions
+                    .into_iter()
+                    .map(|(style, text)| {
+                        Span::styled(text.trim_end_matches('\n').to_string(), convert(style))
+                    })
+                    .collect(),
+                // Leave the rest plain rather than guess at the state
+                Err(_) => vec![Span::raw(line.trim_end_matches('\n').to_string())],
+            };
+            highlighted.push(Line::from(spans));
+        }
+        highlighted
+    }
+
+    /// The syntax by file name, then extension, then a shebang or modeline
+    /// on the first line.
+    fn syntax_for(&self, path: &Path, first_line: Option<&str>) -> &SyntaxReference {
+        let name = path.file_name().and_then(|n| n.to_str());
+        let extension = path.extension().and_then(|e| e.to_str());
+        name.and_then(|name| self.syntaxes.find_syntax_by_extension(name))
+            .or_else(|| extension.and_then(|ext| self.syntaxes.find_syntax_by_extension(ext)))
+            .or_else(|| first_line.and_then(|line| self.syntaxes.find_syntax_by_first_line(line)))
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+    }
+}
+
+/// The first lines of `path`, with tabs expanded as the preview cannot
+/// align them, or `None` if they have a NUL byte and so look binary.
+fn read_lines(path: &Path) -> io::Result<Option<Vec<String>>> {
+    let reader = BufReader::new(File::open(path)?.take(PREVIEW_BYTES));
+    let mut lines = Vec::new();
+    for line in reader.split(b'\n').take(PREVIEW_LINES) {
+        let line = line?;
+        if line.contains(&0) {
+            return Ok(None);
+        }
+        let line = String::from_utf8_lossy(&line);
+        lines.push(line.trim_end_matches('\r').replace('\t', "    "));
+    }
+    Ok(Some(lines))
+}
+
+/// A syntect style as a ratatui one. The background is left to the theme's
+/// `preview` element, so the pane matches the rest of treenav.
+fn convert(style: highlighting::Style) -> Style {
+    let fg = style.foreground;
+    let mut converted = Style::new().fg(Color::Rgb(fg.r, fg.g, fg.b));
+    for (font_style, modifier) in [
+        (FontStyle::BOLD, Modifier::BOLD),
+        (FontStyle::ITALIC, Modifier::ITALIC),
+        (FontStyle::UNDERLINE, Modifier::UNDERLINED),
+    ] {
+        if style.font_style.contains(font_style) {
+            converted = converted.add_modifier(modifier);
+        }
+    }
+    converted
+}
+
+/// How file previews are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreviewDisplay {
+    /// The highlight theme, or `None` for plain text
+    pub highlight: Option<&'static str>,
+    pub line_numbers: bool,
+}
+
+impl Default for PreviewDisplay {
+    fn default() -> Self {
+        Self {
+            highlight: Some(HIGHLIGHT_THEMES[0]),
+            line_numbers: true,
+        }
+    }
+}
+
+impl PreviewDisplay {
+    /// The display in effect, as a `[preview]` table.
+    pub fn to_toml(self) -> toml::Table {
+        let mut table = toml::Table::new();
+        table.insert(
+            "highlight".to_string(),
+            self.highlight.unwrap_or("none").into(),
+        );
+        table.insert("line_numbers".to_string(), self.line_numbers.into());
+        table
+    }
+}
+
+/// The `[preview]` table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PreviewTable {
+    highlight: Option<Spanned<String>>,
+    line_numbers: Option<bool>,
+}
+
+/// Builds the preview display from the `[preview]` table. Without a
+/// `highlight` setting, the theme's `default_highlight` is used.
+pub fn resolve(
+    table: &PreviewTable,
+    default_highlight: &'static str,
+    contents: &str,
+    errors: &mut Vec<Diagnostic>,
+) -> PreviewDisplay {
+    let default = PreviewDisplay {
+        highlight: Some(default_highlight),
+        ..PreviewDisplay::default()
+    };
+    let highlight = match table.highlight.as_ref() {
+        Some(highlight) if highlight.get_ref() == "none" => None,
+        Some(highlight) => match HIGHLIGHT_THEMES.iter().find(|t| *t == highlight.get_ref()) {
+            Some(theme) => Some(*theme),
+            None => {
+                errors.push(Diagnostic::at(
+                    contents,
+                    highlight.span().start,
+                    format!(
+                        "unknown highlight theme '{}', expected {} or none",
+                        highlight.get_ref(),
+                        HIGHLIGHT_THEMES.join(", ")
+                    ),
+                ));
+                default.highlight
+            }
+        },
+        None => default.highlight,
+    };
+    PreviewDisplay {
+        highlight,
+        line_numbers: table.line_numbers.unwrap_or(default.line_numbers),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syntax_comes_from_the_name_then_the_extension_then_the_first_line() {
+        let highlighter = Highlighter::new();
+        let syntax = |path: &str, first_line: Option<&str>| {
+            highlighter
+                .syntax_for(Path::new(path), first_line)
+                .name
+                .clone()
+        };
+        assert_eq!(syntax("src/main.rs", None), "Rust");
+        assert_eq!(syntax("Makefile", None), "Makefile");
+        assert_eq!(
+            syntax("build.sh", Some("#!/usr/bin/env python3")),
+            "Bourne Again Shell (bash)"
+        );
+        assert_eq!(syntax("bin/tool", Some("#!/usr/bin/env python3")), "Python");
+        assert_eq!(syntax("notes", Some("hello")), "Plain Text");
+    }
+
+    #[test]
+    fn highlight_themes_come_with_syntect() {
+        let highlighter = Highlighter::new();
+        for theme in HIGHLIGHT_THEMES {
+            assert!(highlighter.themes.themes.contains_key(*theme), "{}", theme);
+        }
+        // An unknown one leaves the lines plain rather than failing
+        let lines = highlighter.highlight(Path::new("main.rs"), vec!["fn".to_string()], "nope");
+        assert_eq!(lines, vec![Line::from("fn")]);
+    }
+
+    #[test]
+    fn files_with_nul_bytes_are_not_read_as_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("text");
+        let binary = dir.path().join("binary");
+        std::fs::write(&text, "one\n\ttwo\r\n").unwrap();
+        std::fs::write(&binary, b"\x7fELF\x02\x01\x00\x00").unwrap();
+
+        assert_eq!(
+            read_lines(&text).unwrap(),
+            Some(vec!["one".to_string(), "    two".to_string()])
+        );
+        assert_eq!(read_lines(&binary).unwrap(), None);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::tree;
+use crossbeam_channel::{bounded, Receiver, TryRecvError};
+use std::path::PathBuf;
+use std::thread;
+use walkdir::WalkDir;
+
+const BATCH_SIZE: usize = 512;
+const MAX_INDEXED_PATHS: usize = 200_000;
+
+/// Walks the root directory in the background and streams every path it
+/// finds, so search can match entries inside collapsed directories.
+pub struct SearchIndexer {
+    result_rx: Receiver<Vec<PathBuf>>,
+    done: bool,
+}
+
+impl SearchIndexer {
+    pub fn new(root: PathBuf, show_hidden: bool) -> Self {
+        let (result_tx, result_rx) = bounded::<Vec<PathBuf>>(16);
+
+        thread::spawn(move || {
+            let walker = WalkDir::new(&root)
+                .min_depth(1)
+                .into_iter()
+                .filter_entry(|e| show_hidden || !tree::is_hidden(e.path()))
+                .filter_map(|e| e.ok())
+                .take(MAX_INDEXED_PATHS);
+
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            for entry in walker {
+                batch.push(entry.into_path());
+                // A failed send means search mode was left; stop walking.
+                if batch.len() >= BATCH_SIZE && result_tx.send(std::mem::take(&mut batch)).is_err()
+                {
+                    return;
+                }
+            }
+            let _ = result_tx.send(batch);
+        });
+
+        Self {
+            result_rx,
+            done: false,
+        }
+    }
+
+    /// Appends newly indexed paths to `paths`. Returns true if any arrived.
+    pub fn poll_results(&mut self, paths: &mut Vec<PathBuf>) -> bool {
+        let mut received = false;
+        loop {
+            match self.result_rx.try_recv() {
+                Ok(batch) => {
+                    received |= !batch.is_empty();
+                    paths.extend(batch);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+        received
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+/// Scores `needle` as a subsequence of `haystack`, favouring matches at word starts
+/// and consecutive runs. Returns `None` if not every character matches.
+pub fn fuzzy_score(haystack: &str, needle: &[char]) -> Option<u16> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    let mut score: u16 = 0;
+    let mut needle_idx = 0;
+    let mut prev_match = false;
+    let mut prev_was_separator = true;
+
+    for c in haystack.chars() {
+        let is_separator = c == '/' || c == '.' || c == '_' || c == '-' || c == ' ';
+        if needle_idx < needle.len() && c == needle[needle_idx] {
+            score += if prev_was_separator {
+                10
+            } else if prev_match {
+                5
+            } else {
+                1
+            };
+            needle_idx += 1;
+            prev_match = true;
+        } else {
+            prev_match = false;
+        }
+        prev_was_separator = is_separator;
+    }
+
+    if needle_idx == needle.len() {
+        Some(score)
+    } else {
+        None
+    }
+}
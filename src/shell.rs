@@ -0,0 +1,256 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Modifier {
+    Ctrl,
+    Alt,
+}
+
+/// A key chord like `ctrl-o` or `alt-t`, translated into each shell's own syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    modifier: Modifier,
+    key: char,
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let (modifier, key) = lower
+            .split_once('-')
+            .ok_or_else(|| format!("expected ctrl-<key> or alt-<key>, got '{}'", s))?;
+        let modifier = match modifier {
+            "ctrl" | "c" => Modifier::Ctrl,
+            "alt" | "meta" | "m" => Modifier::Alt,
+            _ => return Err(format!("unknown modifier '{}', use ctrl or alt", modifier)),
+        };
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(key), None) if key.is_ascii_lowercase() => Ok(Self { modifier, key }),
+            _ => Err(format!("key must be a single letter, got '{}'", key)),
+        }
+    }
+}
+
+impl KeyBinding {
+    fn bash(self) -> String {
+        match self.modifier {
+            Modifier::Ctrl => format!("\\C-{}", self.key),
+            Modifier::Alt => format!("\\e{}", self.key),
+        }
+    }
+
+    fn zsh(self) -> String {
+        match self.modifier {
+            Modifier::Ctrl => format!("^{}", self.key.to_ascii_uppercase()),
+            Modifier::Alt => format!("^[{}", self.key),
+        }
+    }
+
+    fn fish(self) -> String {
+        match self.modifier {
+            Modifier::Ctrl => format!("\\c{}", self.key),
+            Modifier::Alt => format!("\\e{}", self.key),
+        }
+    }
+
+    fn nushell_modifier(self) -> &'static str {
+        match self.modifier {
+            Modifier::Ctrl => "control",
+            Modifier::Alt => "alt",
+        }
+    }
+}
+
+/// Validates a wrapper command name, which ends up unquoted in shell code.
+pub fn parse_command_name(name: &str) -> Result<String, String> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(format!(
+            "'{}' is not a valid command name (letters, digits, '_' and '-')",
+            name
+        ))
+    }
+}
+
+/// Returns the integration script for `shell`: a `cmd` function that runs
+/// treenav and changes into the printed directory, plus an optional key binding.
+pub fn init_script(shell: Shell, cmd: &str, bind: Option<KeyBinding>) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => posix_script(shell, cmd, bind),
+        Shell::Fish => fish_script(cmd, bind),
+        Shell::Nushell => nushell_script(cmd, bind),
+    }
+}
+
+fn posix_script(shell: Shell, cmd: &str, bind: Option<KeyBinding>) -> String {
+    let mut script = format!(
+        r#"# treenav {shell} integration
+# Add to your shell config: eval "$(treenav init {shell})"
+
+{cmd}() {{
+    local dir
+    if [ "$#" -eq 1 ] && [ "${{1#-}}" = "$1" ] && [ ! -d "$1" ]; then
+        dir="$(command treenav jump "$1")" || return
+    else
+        dir="$(command treenav "$@")" || return
+    fi
+    if [ -n "$dir" ] && [ -d "$dir" ]; then
+        builtin cd -- "$dir"
+    fi
+}}
+"#,
+        shell = if shell == Shell::Bash { "bash" } else { "zsh" },
+        cmd = cmd,
+    );
+
+    let Some(bind) = bind else {
+        return script;
+    };
+    if shell == Shell::Bash {
+        let _ = write!(
+            script,
+            r#"
+__treenav_widget() {{
+    local dir
+    dir="$(command treenav </dev/tty)" || return
+    if [ -n "$dir" ] && [ -d "$dir" ]; then
+        builtin cd -- "$dir"
+    fi
+}}
+
+if [[ $- == *i* ]]; then
+    bind -x '"{key}": __treenav_widget'
+fi
+"#,
+            key = bind.bash(),
+        );
+    } else {
+        let _ = write!(
+            script,
+            r#"
+__treenav_widget() {{
+    local dir
+    dir="$(command treenav </dev/tty)"
+    if [[ -n "$dir" && -d "$dir" ]]; then
+        builtin cd -- "$dir"
+        local hook
+        for hook in $chpwd_functions $precmd_functions; do
+            "$hook"
+        done
+    fi
+    zle reset-prompt
+}}
+
+if [[ -o interactive ]]; then
+    zle -N __treenav_widget
+    bindkey '{key}' __treenav_widget
+fi
+"#,
+            key = bind.zsh(),
+        );
+    }
+    script
+}
+
+fn fish_script(cmd: &str, bind: Option<KeyBinding>) -> String {
+    let mut script = format!(
+        r#"# treenav fish integration
+# Add to config.fish: treenav init fish | source
+
+function {cmd} --description 'Navigate with treenav and cd to the selection'
+    set -l dir
+    if test (count $argv) -eq 1; and not string match -q -- '-*' $argv[1]; and not test -d $argv[1]
+        set dir (command treenav jump $argv[1])
+        or return
+    else
+        set dir (command treenav $argv)
+        or return
+    end
+    if test -n "$dir"; and test -d "$dir"
+        builtin cd -- $dir
+    end
+end
+"#,
+        cmd = cmd,
+    );
+
+    if let Some(bind) = bind {
+        let _ = write!(
+            script,
+            r#"
+function __treenav_widget
+    set -l dir (command treenav </dev/tty)
+    if test -n "$dir"; and test -d "$dir"
+        builtin cd -- $dir
+    end
+    commandline -f repaint
+end
+
+if status is-interactive
+    bind {key} __treenav_widget
+end
+"#,
+            key = bind.fish(),
+        );
+    }
+    script
+}
+
+fn nushell_script(cmd: &str, bind: Option<KeyBinding>) -> String {
+    let mut script = format!(
+        r#"# treenav nushell integration
+# Save the output and source it from config.nu:
+#   treenav init nushell | save -f ~/.config/nushell/treenav.nu
+#   source ~/.config/nushell/treenav.nu
+
+def --env --wrapped {cmd} [...args] {{
+    let jump = ($args | length) == 1 and not ($args.0 | str starts-with "-") and ($args.0 | path type) != "dir"
+    let dir = if $jump {{ ^treenav jump $args.0 | str trim }} else {{ ^treenav ...$args | str trim }}
+    if ($dir | is-not-empty) and ($dir | path type) == "dir" {{
+        cd $dir
+    }}
+}}
+"#,
+        cmd = cmd,
+    );
+
+    if let Some(bind) = bind {
+        // executehostcommand runs the command without touching the line buffer
+        let _ = write!(
+            script,
+            r#"
+$env.config = ($env.config | upsert keybindings (
+    ($env.config.keybindings? | default []) | append {{
+        name: treenav
+        modifier: {modifier}
+        keycode: char_{key}
+        mode: [emacs vi_normal vi_insert]
+        event: {{ send: executehostcommand, cmd: "{cmd}" }}
+    }}
+))
+"#,
+            modifier = bind.nushell_modifier(),
+            key = bind.key,
+            cmd = cmd,
+        );
+    }
+    script
+}
@@ -1,65 +1,600 @@
-use crossbeam_channel::{bounded, Receiver, Sender};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use crate::config::Diagnostic;
+use crate::sizecache::{DirRecord, DirStamp, Records};
+use crate::state;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata};
+use std::io;
+use std::ops::AddAssign;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use walkdir::WalkDir;
+use toml::Spanned;
 
+/// Directories measured at the same time, at most. Walking is mostly waiting
+/// on the disk, so more threads than this rarely help.
+const MAX_WORKERS: usize = 4;
+
+/// The total size of the files below a directory, and how many files and
+/// directories there are, counting the directory itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirSize {
+    /// What the files' lengths add up to
+    pub apparent: u64,
+    /// What the files take up on disk, which is less for sparse files and
+    /// more for small ones
+    pub allocated: u64,
+    pub files: u64,
+    pub dirs: u64,
+}
+
+impl DirSize {
+    /// The size of a single file.
+    pub fn of_file(metadata: &Metadata) -> Self {
+        Self {
+            apparent: metadata.len(),
+            allocated: metadata.blocks() * 512,
+            files: 1,
+            dirs: 0,
+        }
+    }
+
+    pub fn saturating_sub(self, other: DirSize) -> Self {
+        Self {
+            apparent: self.apparent.saturating_sub(other.apparent),
+            allocated: self.allocated.saturating_sub(other.allocated),
+            files: self.files.saturating_sub(other.files),
+            dirs: self.dirs.saturating_sub(other.dirs),
+        }
+    }
+}
+
+impl AddAssign for DirSize {
+    fn add_assign(&mut self, other: DirSize) {
+        self.apparent += other.apparent;
+        self.allocated += other.allocated;
+        self.files += other.files;
+        self.dirs += other.dirs;
+    }
+}
+
+/// How far along the size of a directory is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeState {
+    Pending,
+    Measured(DirSize),
+    /// A size from the cache that may be out of date. It is being measured
+    /// again.
+    Stale(DirSize),
+    /// The directory could not be read, or is on another filesystem with
+    /// `--one-file-system`
+    Failed(io::ErrorKind),
+}
+
+impl SizeState {
+    /// The size, if there is one to show, stale or not.
+    pub fn size(self) -> Option<DirSize> {
+        match self {
+            SizeState::Measured(size) | SizeState::Stale(size) => Some(size),
+            SizeState::Pending | SizeState::Failed(_) => None,
+        }
+    }
+
+    /// Whether a result is still to come.
+    pub fn is_pending(self) -> bool {
+        matches!(self, SizeState::Pending | SizeState::Stale(_))
+    }
+}
+
+/// A finished measurement.
+pub struct SizeResult {
+    pub path: PathBuf,
+    pub state: SizeState,
+    /// The directories read or found unchanged on the way, for the cache
+    pub records: Vec<(PathBuf, DirRecord)>,
+}
+
+struct Request {
+    path: PathBuf,
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Files with several hard links, by the directory whose listing they were
+/// found in, and which entry of that listing counts them. A file linked
+/// from two sibling directories only counts toward the one measured first,
+/// so the sizes in a listing add up to what it takes on disk.
+#[derive(Debug, Default)]
+struct LinkClaims(HashMap<PathBuf, HashMap<(u64, u64), Claim>>);
+
+#[derive(Debug)]
+struct Claim {
+    owner: PathBuf,
+    /// Siblings that left the file out as `owner` counts it
+    skipped: HashSet<PathBuf>,
+}
+
+impl LinkClaims {
+    /// Records the linked files `dir` holds now, claiming those no sibling
+    /// has. Returns the ones a sibling already counts.
+    fn settle(&mut self, dir: &Path, keys: &HashSet<(u64, u64)>) -> HashSet<(u64, u64)> {
+        let Some(listing) = dir.parent() else {
+            return HashSet::new();
+        };
+        let claims = self.0.entry(listing.to_path_buf()).or_default();
+        claims.retain(|key, claim| claim.owner != dir || keys.contains(key));
+        let mut taken = HashSet::new();
+        for key in keys {
+            let claim = claims.entry(*key).or_insert_with(|| Claim {
+                owner: dir.to_path_buf(),
+                skipped: HashSet::new(),
+            });
+            if claim.owner != dir {
+                claim.skipped.insert(dir.to_path_buf());
+                taken.insert(*key);
+            }
+        }
+        taken
+    }
+
+    /// Drops the claims of `dir` and everything below it. Returns the
+    /// siblings of `dir` that left out a file it claimed.
+    fn release(&mut self, dir: &Path) -> HashSet<PathBuf> {
+        let mut skipped = HashSet::new();
+        if let Some(claims) = dir.parent().and_then(|listing| self.0.get_mut(listing)) {
+            claims.retain(|_, claim| {
+                if claim.owner != dir {
+                    return true;
+                }
+                skipped.extend(claim.skipped.drain());
+                false
+            });
+        }
+        self.0
+            .retain(|listing, claims| !claims.is_empty() && !listing.starts_with(dir));
+        skipped
+    }
+}
+
+/// Measures directories on a pool of threads. Every request ends in exactly
+/// one result from `poll_results`, unless it is cancelled first.
 pub struct SizeWorker {
-    request_tx: Sender<PathBuf>,
-    result_rx: Receiver<(PathBuf, u64)>,
+    request_tx: Sender<Request>,
+    result_rx: Receiver<(u64, SizeResult)>,
+    /// Requests not finished yet, by directory
+    in_flight: HashMap<PathBuf, (u64, Arc<AtomicBool>)>,
+    next_id: u64,
+    claims: Arc<Mutex<LinkClaims>>,
 }
 
 impl SizeWorker {
-    pub fn new() -> Self {
-        let (request_tx, request_rx) = bounded::<PathBuf>(100);
-        let (result_tx, result_rx) = bounded::<(PathBuf, u64)>(100);
-
-        thread::spawn(move || {
-            while let Ok(path) = request_rx.recv() {
-                let size = calculate_dir_size(&path);
-                let _ = result_tx.send((path, size));
-            }
-        });
+    /// With `device`, only files on that filesystem are counted, and
+    /// directories on any other fail with `CrossesDevices`. Directories
+    /// unchanged since their entry in `records` are not read again.
+    pub fn new(device: Option<u64>, records: Records) -> Self {
+        let (request_tx, request_rx) = unbounded::<Request>();
+        let (result_tx, result_rx) = unbounded();
+
+        let claims = Arc::new(Mutex::new(LinkClaims::default()));
+        let workers = thread::available_parallelism().map_or(1, |n| n.get().min(MAX_WORKERS));
+        for _ in 0..workers {
+            let request_rx = request_rx.clone();
+            let result_tx = result_tx.clone();
+            let claims = claims.clone();
+            let records = records.clone();
+            thread::spawn(move || {
+                while let Ok(request) = request_rx.recv() {
+                    let measured =
+                        measure(&request.path, device, &request.cancelled, &claims, &records);
+                    let Some((state, records)) = measured else {
+                        continue;
+                    };
+                    let result = SizeResult {
+                        path: request.path,
+                        state,
+                        records,
+                    };
+                    let _ = result_tx.send((request.id, result));
+                }
+            });
+        }
 
         Self {
             request_tx,
             result_rx,
+            in_flight: HashMap::new(),
+            next_id: 0,
+            claims,
+        }
+    }
+
+    /// Measures `path`, replacing any request for it still in flight.
+    pub fn request_size(&mut self, path: PathBuf) {
+        self.cancel(&path);
+        let id = self.next_id;
+        self.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.in_flight.insert(path.clone(), (id, cancelled.clone()));
+        let _ = self.request_tx.send(Request {
+            path,
+            id,
+            cancelled,
+        });
+    }
+
+    /// Stops measuring `path`. Its result, if already on the way, is dropped.
+    pub fn cancel(&mut self, path: &Path) {
+        if let Some((_, cancelled)) = self.in_flight.remove(path) {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Stops measuring `path` and what is below it for good, as it is gone.
+    /// Returns its siblings that left out files linked from `path` too, as
+    /// they are theirs to count now.
+    pub fn forget(&mut self, path: &Path) -> HashSet<PathBuf> {
+        self.in_flight.retain(|dir, (_, cancelled)| {
+            if dir.starts_with(path) {
+                cancelled.store(true, Ordering::Relaxed);
+                return false;
+            }
+            true
+        });
+        self.claims.lock().unwrap().release(path)
+    }
+
+    /// The sizes finished since the last call.
+    pub fn poll_results(&mut self) -> Vec<SizeResult> {
+        let mut results = Vec::new();
+        while let Ok((id, result)) = self.result_rx.try_recv() {
+            // Skip results of cancelled or replaced requests
+            if self
+                .in_flight
+                .get(&result.path)
+                .is_some_and(|(current, _)| *current == id)
+            {
+                self.in_flight.remove(&result.path);
+                results.push(result);
+            }
+        }
+        results
+    }
+}
+
+/// Adds up the files below `path`, or returns `None` once `cancelled` is set.
+/// Files with several hard links count once, and not at all if a sibling of
+/// `path` counts them. Only directories whose stamp changed since their
+/// entry in `known` are read; the rest are added up from there.
+fn measure(
+    path: &Path,
+    device: Option<u64>,
+    cancelled: &AtomicBool,
+    claims: &Mutex<LinkClaims>,
+    known: &RwLock<HashMap<PathBuf, DirRecord>>,
+) -> Option<(SizeState, Vec<(PathBuf, DirRecord)>)> {
+    let failed = |kind| Some((SizeState::Failed(kind), Vec::new()));
+    let metadata = match fs::metadata(path) {
+        Ok(m) if device.is_some_and(|device| m.dev() != device) => {
+            return failed(io::ErrorKind::CrossesDevices);
+        }
+        Ok(_) if is_virtual_fs(path) => return failed(io::ErrorKind::Unsupported),
+        Ok(m) => m,
+        Err(e) => return failed(e.kind()),
+    };
+    if let Err(e) = fs::read_dir(path) {
+        return failed(e.kind());
+    }
+
+    let now = state::now();
+    let mut size = DirSize::default();
+    // Files with several links, counted once the walk is done
+    let mut linked: HashMap<(u64, u64), DirSize> = HashMap::new();
+    let mut records = Vec::new();
+    let mut stack = vec![(path.to_path_buf(), metadata)];
+    while let Some((dir, metadata)) = stack.pop() {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let stamp = DirStamp::of(&metadata);
+        let cached = known
+            .read()
+            .unwrap()
+            .get(&dir)
+            .filter(|record| record.stamp == stamp)
+            .cloned();
+        let mut record = cached.unwrap_or_else(|| DirRecord::read(&dir, stamp));
+        record.checked_at = now;
+
+        size += record.files;
+        for file in &record.linked {
+            linked.entry((file.device, file.inode)).or_insert(file.size);
+        }
+        for name in &record.subdirs {
+            let subdir = dir.join(name);
+            match fs::symlink_metadata(&subdir) {
+                Ok(m) if m.is_dir() && device.is_none_or(|device| m.dev() == device) => {
+                    stack.push((subdir, m));
+                }
+                _ => {}
+            }
+        }
+        records.push((dir, record));
+    }
+
+    // Claiming only now leaves no claims behind when cancelled
+    let keys: HashSet<(u64, u64)> = linked.keys().copied().collect();
+    let taken = claims.lock().unwrap().settle(path, &keys);
+    for (key, file) in linked {
+        if !taken.contains(&key) {
+            size += file;
         }
     }
+    Some((SizeState::Measured(size), records))
+}
 
-    pub fn request_size(&self, path: PathBuf) {
-        let _ = self.request_tx.try_send(path);
+/// Whether `path` is on a filesystem like `/proc` or `/sys`, whose files take
+/// no space and can be slow or unsafe to read all of.
+#[cfg(target_os = "linux")]
+fn is_virtual_fs(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    const VIRTUAL: &[libc::__fsword_t] = &[
+        libc::PROC_SUPER_MAGIC,
+        libc::SYSFS_MAGIC,
+        libc::DEBUGFS_MAGIC,
+        libc::TRACEFS_MAGIC,
+        libc::SECURITYFS_MAGIC,
+        libc::CGROUP_SUPER_MAGIC,
+        libc::CGROUP2_SUPER_MAGIC,
+        libc::DEVPTS_SUPER_MAGIC,
+        libc::BPF_FS_MAGIC,
+        // Reading an automount point mounts it
+        libc::AUTOFS_SUPER_MAGIC,
+    ];
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read once statfs
+    // has filled it in
+    unsafe {
+        libc::statfs(path.as_ptr(), stat.as_mut_ptr()) == 0
+            && VIRTUAL.contains(&stat.assume_init().f_type)
     }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_virtual_fs(_path: &Path) -> bool {
+    false
+}
+
+/// Which multiples sizes are shown in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeUnits {
+    /// Powers of 1024 with short suffixes, like `du -h`: 1.5K
+    #[default]
+    Binary,
+    /// Powers of 1024 with IEC suffixes: 1.5KiB
+    Iec,
+    /// Powers of 1000 with SI suffixes, like `du --si`: 1.5kB
+    Si,
+}
 
-    pub fn poll_results(&self, sizes: &mut HashMap<PathBuf, Option<u64>>) {
-        while let Ok((path, size)) = self.result_rx.try_recv() {
-            sizes.insert(path, Some(size));
+impl SizeUnits {
+    pub fn name(self) -> &'static str {
+        match self {
+            SizeUnits::Binary => "binary",
+            SizeUnits::Iec => "iec",
+            SizeUnits::Si => "si",
         }
     }
 }
 
-fn calculate_dir_size(path: &PathBuf) -> u64 {
-    WalkDir::new(path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter_map(|e| e.metadata().ok())
-        .filter(|m| m.is_file())
-        .map(|m| m.len())
-        .sum()
+impl FromStr for SizeUnits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(SizeUnits::Binary),
+            "iec" => Ok(SizeUnits::Iec),
+            "si" => Ok(SizeUnits::Si),
+            _ => Err(format!(
+                "unknown size units '{}', expected binary, iec or si",
+                s
+            )),
+        }
+    }
 }
 
-pub fn format_size(bytes: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
+pub fn format_size(bytes: u64, units: SizeUnits) -> String {
+    let (base, suffixes) = match units {
+        SizeUnits::Binary => (1024.0, ["K", "M", "G", "T"]),
+        SizeUnits::Iec => (1024.0, ["KiB", "MiB", "GiB", "TiB"]),
+        SizeUnits::Si => (1000.0, ["kB", "MB", "GB", "TB"]),
+    };
+
+    if (bytes as f64) < base {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / base;
+    let mut suffix = suffixes[0];
+    for next in &suffixes[1..] {
+        if value < base {
+            break;
+        }
+        value /= base;
+        suffix = next;
+    }
+    format!("{:.1}{}", value, suffix)
+}
 
-    if bytes >= GB {
-        format!("{:.1}G", bytes as f64 / GB as f64)
-    } else if bytes >= MB {
-        format!("{:.1}M", bytes as f64 / MB as f64)
-    } else if bytes >= KB {
-        format!("{:.1}K", bytes as f64 / KB as f64)
+/// A count with a K or M suffix once it stops fitting in a few digits.
+pub fn format_count(count: u64) -> String {
+    if count >= 1_000_000 {
+        format!("{:.1}M", count as f64 / 1_000_000.0)
+    } else if count >= 10_000 {
+        format!("{:.1}K", count as f64 / 1_000.0)
     } else {
-        format!("{}B", bytes)
+        count.to_string()
+    }
+}
+
+/// How directory sizes are shown: which size, in which units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeDisplay {
+    pub units: SizeUnits,
+    /// Show apparent sizes instead of what files take up on disk
+    pub apparent: bool,
+}
+
+impl SizeDisplay {
+    /// The bytes of `size` this display shows.
+    pub fn bytes(self, size: DirSize) -> u64 {
+        if self.apparent {
+            size.apparent
+        } else {
+            size.allocated
+        }
+    }
+
+    pub fn format(self, size: DirSize) -> String {
+        format_size(self.bytes(size), self.units)
+    }
+
+    /// The display in effect, as a `[sizes]` table.
+    pub fn to_toml(self) -> toml::Table {
+        let mut table = toml::Table::new();
+        table.insert("units".to_string(), self.units.name().into());
+        table.insert("apparent".to_string(), self.apparent.into());
+        table
+    }
+}
+
+/// The `[sizes]` table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SizesTable {
+    units: Option<Spanned<String>>,
+    apparent: Option<bool>,
+}
+
+/// Builds the size display from the `[sizes]` table.
+pub fn resolve(table: &SizesTable, contents: &str, errors: &mut Vec<Diagnostic>) -> SizeDisplay {
+    let units = match table.units.as_ref() {
+        Some(units) => units.get_ref().parse().unwrap_or_else(|message| {
+            errors.push(Diagnostic::at(contents, units.span().start, message));
+            SizeUnits::default()
+        }),
+        None => SizeUnits::default(),
+    };
+    SizeDisplay {
+        units,
+        apparent: table.apparent.unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Polls until `count` results are in, or a few seconds have passed.
+    fn wait_for(worker: &mut SizeWorker, count: usize) -> HashMap<PathBuf, SizeState> {
+        let mut results = HashMap::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while results.len() < count && Instant::now() < deadline {
+            for result in worker.poll_results() {
+                assert!(
+                    results.insert(result.path.clone(), result.state).is_none(),
+                    "second result for {}",
+                    result.path.display()
+                );
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        results
+    }
+
+    #[test]
+    fn every_request_resolves_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs: Vec<PathBuf> = (0..8).map(|i| dir.path().join(i.to_string())).collect();
+        for (i, path) in dirs.iter().enumerate() {
+            fs::create_dir(path).unwrap();
+            fs::write(path.join("file"), vec![0; i]).unwrap();
+        }
+        let gone = dir.path().join("gone");
+
+        let mut worker = SizeWorker::new(None, Records::default());
+        for path in dirs.iter().chain([&gone]) {
+            worker.request_size(path.clone());
+        }
+        // Asking again replaces the first request rather than adding one
+        worker.request_size(dirs[0].clone());
+
+        let results = wait_for(&mut worker, dirs.len() + 1);
+        assert_eq!(results.len(), dirs.len() + 1);
+        for (i, path) in dirs.iter().enumerate() {
+            let size = results[path].size().unwrap();
+            assert_eq!((size.apparent, size.files, size.dirs), (i as u64, 1, 1));
+        }
+        assert_eq!(results[&gone], SizeState::Failed(io::ErrorKind::NotFound));
+        thread::sleep(Duration::from_millis(100));
+        assert!(worker.poll_results().is_empty());
+    }
+
+    #[test]
+    fn cancelled_requests_have_no_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let (kept, cancelled) = (dir.path().join("kept"), dir.path().join("cancelled"));
+        fs::create_dir(&kept).unwrap();
+        fs::create_dir(&cancelled).unwrap();
+
+        let mut worker = SizeWorker::new(None, Records::default());
+        worker.request_size(cancelled.clone());
+        worker.request_size(kept.clone());
+        worker.cancel(&cancelled);
+
+        let results = wait_for(&mut worker, 1);
+        thread::sleep(Duration::from_millis(100));
+        assert!(worker.poll_results().is_empty());
+        assert_eq!(results.keys().collect::<Vec<_>>(), [&kept]);
+    }
+
+    #[test]
+    fn files_linked_from_siblings_count_once_per_listing() {
+        let dir = tempfile::tempdir().unwrap();
+        let parent = dir.path().join("parent");
+        let (a, b) = (parent.join("a"), parent.join("b"));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("file"), vec![0; 1000]).unwrap();
+        fs::hard_link(a.join("file"), b.join("link")).unwrap();
+        fs::hard_link(a.join("file"), b.join("second link")).unwrap();
+
+        let mut worker = SizeWorker::new(None, Records::default());
+        worker.request_size(a.clone());
+        worker.request_size(b.clone());
+        worker.request_size(parent.clone());
+        let results = wait_for(&mut worker, 3);
+        let apparent = |path: &Path| results[path].size().unwrap().apparent;
+        assert_eq!(apparent(&a) + apparent(&b), 1000);
+        assert_eq!(apparent(&parent), 1000);
+
+        // Measuring again keeps the file where it was counted
+        let counted_by_a = apparent(&a) == 1000;
+        worker.request_size(a.clone());
+        worker.request_size(b.clone());
+        let again = wait_for(&mut worker, 2);
+        assert_eq!(again[&a].size().unwrap().apparent, apparent(&a));
+        assert_eq!(again[&b].size().unwrap().apparent, apparent(&b));
+
+        // Once the counting side is gone, the other one should count it
+        let (owner, other) = if counted_by_a { (&a, &b) } else { (&b, &a) };
+        assert_eq!(worker.forget(owner), HashSet::from([other.clone()]));
     }
 }
@@ -0,0 +1,442 @@
+use crate::size::{DirSize, SizeState};
+use crate::state;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+const CACHE_VERSION: u32 = 3;
+
+/// Directories kept in the cache file. Past this, the ones checked longest
+/// ago are dropped first, and are read again when next measured.
+const MAX_CACHED_DIRS: usize = 100_000;
+
+/// Directories `get` checks at most. Larger trees are left for the size
+/// worker to check, so they don't hold up the UI.
+const MAX_CHECKED_DIRS: usize = 1_000;
+
+/// What a directory looked like when it was read. Creating, deleting or
+/// renaming an entry in it changes its mtime; replacing it changes its inode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirStamp {
+    mtime: i64,
+    mtime_nsec: i64,
+    inode: u64,
+    device: u64,
+}
+
+impl DirStamp {
+    pub fn of(metadata: &Metadata) -> Self {
+        Self {
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            inode: metadata.ino(),
+            device: metadata.dev(),
+        }
+    }
+}
+
+/// What one directory holds by itself, as read when it looked like `stamp`.
+/// The size of a directory adds up its own record and those of every
+/// subdirectory below it, so a change deep down only means reading the
+/// directory it happened in again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirRecord {
+    pub stamp: DirStamp,
+    /// The directory itself and its files with a single link
+    pub files: DirSize,
+    /// Files with several links, which count once per measured directory
+    pub linked: Vec<LinkedFile>,
+    /// Names of the subdirectories
+    pub subdirs: Vec<PathBuf>,
+    /// When the directory was read or found unchanged, in seconds since the
+    /// epoch
+    pub checked_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkedFile {
+    pub device: u64,
+    pub inode: u64,
+    pub size: DirSize,
+}
+
+impl DirRecord {
+    /// Reads what `dir` holds now. An unreadable directory holds nothing.
+    pub fn read(dir: &Path, stamp: DirStamp) -> Self {
+        let mut record = Self {
+            stamp,
+            files: DirSize {
+                dirs: 1,
+                ..DirSize::default()
+            },
+            linked: Vec::new(),
+            subdirs: Vec::new(),
+            checked_at: state::now(),
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return record;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                record.subdirs.push(PathBuf::from(entry.file_name()));
+            } else if metadata.is_file() && metadata.nlink() > 1 {
+                record.linked.push(LinkedFile {
+                    device: metadata.dev(),
+                    inode: metadata.ino(),
+                    size: DirSize::of_file(&metadata),
+                });
+            } else if metadata.is_file() {
+                record.files += DirSize::of_file(&metadata);
+            }
+        }
+        record
+    }
+}
+
+/// The records of every directory known, shared with the size worker.
+pub type Records = Arc<RwLock<HashMap<PathBuf, DirRecord>>>;
+
+#[derive(Default, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    dirs: HashMap<PathBuf, DirRecord>,
+}
+
+/// Directory sizes from earlier runs, in `sizes.json` next to `state.json`.
+pub struct SizeCache {
+    /// None if there is no data directory to keep the cache in
+    path: Option<PathBuf>,
+    records: Records,
+    /// Directories read or checked in this run, written back on save
+    checked: HashSet<PathBuf>,
+    /// Directories to drop from the cache file, as they could not be measured
+    forgotten: HashSet<PathBuf>,
+    /// Sizes are limited to this filesystem with `--one-file-system`
+    device: Option<u64>,
+    /// `MAX_CACHED_DIRS`, but for tests
+    max_dirs: usize,
+}
+
+impl SizeCache {
+    fn cache_file_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|p| p.join("treenav").join("sizes.json"))
+    }
+
+    /// Reads the cache. A missing, corrupt or newer file is an empty cache.
+    pub fn load(device: Option<u64>) -> Self {
+        Self::open(Self::cache_file_path(), device)
+    }
+
+    /// Reads the cache kept in `path`.
+    pub fn open(path: Option<PathBuf>, device: Option<u64>) -> Self {
+        let dirs = path
+            .as_deref()
+            .map(|path| read_cache_file(path).dirs)
+            .unwrap_or_default();
+        Self {
+            path,
+            records: Arc::new(RwLock::new(dirs)),
+            checked: HashSet::new(),
+            forgotten: HashSet::new(),
+            device,
+            max_dirs: MAX_CACHED_DIRS,
+        }
+    }
+
+    /// The records, for the size worker to skip directories that are
+    /// unchanged.
+    pub fn records(&self) -> Records {
+        self.records.clone()
+    }
+
+    /// The cached size of `dir`: `Measured` if nothing below it changed
+    /// since, and `Stale` if something did or it is too large to check here.
+    /// Sizes with files linked from elsewhere are stale too, as which
+    /// directory counts those is up to the size worker.
+    pub fn get(&self, dir: &Path) -> Option<SizeState> {
+        let records = self.records.read().unwrap();
+        let record = records.get(dir)?;
+        if self
+            .device
+            .is_some_and(|device| record.stamp.device != device)
+        {
+            return None;
+        }
+
+        let mut size = DirSize::default();
+        let mut linked = HashMap::new();
+        let mut unchanged = true;
+        let mut checked = 0;
+        let mut stack = vec![(dir.to_path_buf(), record)];
+        while let Some((dir, record)) = stack.pop() {
+            if unchanged {
+                checked += 1;
+                // The directory asked about may be a link to one
+                let metadata = match checked {
+                    1 => fs::metadata(&dir),
+                    _ => fs::symlink_metadata(&dir),
+                };
+                unchanged = checked <= MAX_CHECKED_DIRS
+                    && metadata.is_ok_and(|m| DirStamp::of(&m) == record.stamp);
+            }
+            size += record.files;
+            for file in &record.linked {
+                linked.entry((file.device, file.inode)).or_insert(file.size);
+            }
+            for name in &record.subdirs {
+                let subdir = dir.join(name);
+                match records.get(&subdir) {
+                    Some(record)
+                        if self
+                            .device
+                            .is_some_and(|device| record.stamp.device != device) => {}
+                    Some(record) => stack.push((subdir, record)),
+                    None => unchanged = false,
+                }
+            }
+        }
+
+        if unchanged && linked.is_empty() {
+            return Some(SizeState::Measured(size));
+        }
+        for file in linked.into_values() {
+            size += file;
+        }
+        Some(SizeState::Stale(size))
+    }
+
+    /// Records the directories read or found unchanged while measuring.
+    pub fn insert(&mut self, records: Vec<(PathBuf, DirRecord)>) {
+        let mut known = self.records.write().unwrap();
+        for (dir, record) in records {
+            self.forgotten.remove(&dir);
+            self.checked.insert(dir.clone());
+            known.insert(dir, record);
+        }
+    }
+
+    /// Drops the record of `dir`, so it is read again next time.
+    pub fn forget(&mut self, dir: &Path) {
+        self.records.write().unwrap().remove(dir);
+        self.checked.remove(dir);
+        self.forgotten.insert(dir.to_path_buf());
+    }
+
+    /// Adds the directories checked in this run to the cache file, keeping
+    /// what other sessions saved meanwhile.
+    pub fn save(&self) -> io::Result<()> {
+        if self.checked.is_empty() && self.forgotten.is_empty() {
+            return Ok(());
+        }
+        let records = self.records.read().unwrap();
+        self.update(|cache| {
+            for dir in &self.forgotten {
+                cache.dirs.remove(dir);
+            }
+            for dir in &self.checked {
+                if let Some(record) = records.get(dir) {
+                    cache.dirs.insert(dir.clone(), record.clone());
+                }
+            }
+        })
+    }
+
+    /// Drops the records of directories `keep` returns false for, and
+    /// returns them. With `dry_run`, the file is left as it is.
+    pub fn prune(
+        &self,
+        mut keep: impl FnMut(&Path) -> bool,
+        dry_run: bool,
+    ) -> io::Result<Vec<PathBuf>> {
+        let Some(path) = &self.path else {
+            return Ok(Vec::new());
+        };
+        let mut pruned: Vec<PathBuf> = read_cache_file(path)
+            .dirs
+            .into_keys()
+            .filter(|dir| !keep(dir))
+            .collect();
+        pruned.sort();
+        if !dry_run && !pruned.is_empty() {
+            self.update(|cache| {
+                for dir in &pruned {
+                    cache.dirs.remove(dir);
+                }
+            })?;
+        }
+        Ok(pruned)
+    }
+
+    /// Rewrites the cache file atomically under an advisory lock, as
+    /// `change` makes it.
+    fn update(&self, change: impl FnOnce(&mut CacheFile)) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let lock = fs::File::create(path.with_extension("json.lock"))?;
+        lock.lock()?;
+
+        let mut cache = read_cache_file(path);
+        change(&mut cache);
+        // JSON strings must be valid UTF-8
+        cache.dirs.retain(|dir, record| {
+            dir.to_str().is_some() && record.subdirs.iter().all(|s| s.to_str().is_some())
+        });
+        if cache.dirs.len() > self.max_dirs {
+            let mut dirs: Vec<(PathBuf, DirRecord)> = cache.dirs.drain().collect();
+            dirs.sort_by_key(|(_, record)| std::cmp::Reverse(record.checked_at));
+            dirs.truncate(self.max_dirs);
+            cache.dirs = dirs.into_iter().collect();
+        }
+        cache.version = CACHE_VERSION;
+
+        let json = serde_json::to_string(&cache)?;
+        let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        let written = fs::File::create(&tmp).and_then(|mut file| file.write_all(json.as_bytes()));
+        if let Err(e) = written.and_then(|_| fs::rename(&tmp, path)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+fn read_cache_file(path: &Path) -> CacheFile {
+    fs::read(path)
+        .ok()
+        .and_then(|contents| serde_json::from_slice::<CacheFile>(&contents).ok())
+        .filter(|cache| cache.version == CACHE_VERSION)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::size::SizeWorker;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Measures `dir` with the records of `cache`, and records what was read.
+    fn measure(cache: &mut SizeCache, dir: &Path) -> SizeState {
+        let mut worker = SizeWorker::new(None, cache.records());
+        worker.request_size(dir.to_path_buf());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let result = loop {
+            if let Some(result) = worker.poll_results().pop() {
+                break result;
+            }
+            assert!(Instant::now() < deadline, "no size for {}", dir.display());
+            thread::sleep(Duration::from_millis(10));
+        };
+        cache.insert(result.records);
+        result.state
+    }
+
+    fn apparent(state: Option<SizeState>) -> Option<u64> {
+        state.and_then(SizeState::size).map(|size| size.apparent)
+    }
+
+    #[test]
+    fn unchanged_dirs_come_back_measured_without_a_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("sizes.json");
+        let measured = dir.path().join("measured");
+        fs::create_dir_all(measured.join("deep/deeper")).unwrap();
+        fs::write(measured.join("deep/deeper/file"), vec![0; 100]).unwrap();
+
+        let mut cache = SizeCache::open(Some(file.clone()), None);
+        let SizeState::Measured(size) = measure(&mut cache, &measured) else {
+            panic!("not measured");
+        };
+        assert_eq!((size.apparent, size.files, size.dirs), (100, 1, 3));
+        cache.save().unwrap();
+
+        let cache = SizeCache::open(Some(file.clone()), None);
+        assert_eq!(cache.get(&measured), Some(SizeState::Measured(size)));
+        assert_eq!(cache.get(&dir.path().join("other")), None);
+        // Sizes measured across filesystems don't stand in for limited ones
+        assert_eq!(
+            SizeCache::open(Some(file), Some(u64::MAX)).get(&measured),
+            None
+        );
+    }
+
+    #[test]
+    fn changes_deep_down_are_read_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("sizes.json");
+        let measured = dir.path().join("measured");
+        let deeper = measured.join("deep/deeper");
+        fs::create_dir_all(&deeper).unwrap();
+        fs::create_dir_all(measured.join("other")).unwrap();
+        fs::write(deeper.join("file"), vec![0; 100]).unwrap();
+
+        let mut cache = SizeCache::open(Some(file.clone()), None);
+        measure(&mut cache, &measured);
+        cache.save().unwrap();
+
+        fs::write(deeper.join("new"), vec![0; 200]).unwrap();
+        let mut cache = SizeCache::open(Some(file), None);
+        // The old size shows until it is measured again
+        assert!(matches!(cache.get(&measured), Some(SizeState::Stale(_))));
+        assert_eq!(apparent(cache.get(&measured)), Some(100));
+
+        // A directory whose stamp still matches is taken from its record,
+        // not read again
+        let other = measured.join("other");
+        cache
+            .records
+            .write()
+            .unwrap()
+            .get_mut(&other)
+            .unwrap()
+            .files
+            .apparent = 1000;
+        assert_eq!(apparent(Some(measure(&mut cache, &measured))), Some(1300));
+        assert!(matches!(cache.get(&measured), Some(SizeState::Measured(_))));
+    }
+
+    #[test]
+    fn saving_keeps_other_sessions_dirs_and_caps_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("sizes.json");
+        let stamp = DirStamp::of(&fs::metadata(dir.path()).unwrap());
+        let record = |checked_at| DirRecord {
+            stamp,
+            files: DirSize::default(),
+            linked: Vec::new(),
+            subdirs: Vec::new(),
+            checked_at,
+        };
+
+        let mut first = SizeCache::open(Some(file.clone()), None);
+        let mut second = SizeCache::open(Some(file.clone()), None);
+        first.max_dirs = 10;
+        second.max_dirs = 10;
+        first.insert(
+            (0..10)
+                .map(|i| (dir.path().join(format!("old{}", i)), record(i)))
+                .collect(),
+        );
+        first.save().unwrap();
+        second.insert(vec![(dir.path().join("new"), record(u64::MAX))]);
+        second.save().unwrap();
+
+        let cache = SizeCache::open(Some(file), None);
+        let records = cache.records.read().unwrap();
+        assert_eq!(records.len(), 10);
+        assert!(records.contains_key(&dir.path().join("new")));
+        assert!(!records.contains_key(&dir.path().join("old0")));
+        assert!(records.contains_key(&dir.path().join("old1")));
+    }
+}
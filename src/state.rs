@@ -1,10 +1,35 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{HashSet, VecDeque};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Once the ranks of all recent directories add up to more than this, they are
+/// scaled down and directories whose rank drops below 1 are forgotten.
+const MAX_TOTAL_RANK: f64 = 1000.0;
+
+/// The share of `MAX_TOTAL_RANK` one import may add up to at most.
+const IMPORT_RANK_SHARE: f64 = 0.25;
+
+/// The rank a single imported directory may have at most, in visits.
+const MAX_IMPORTED_RANK: f64 = 10.0;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// Current Unix time in seconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Bookmark {
     pub path: PathBuf,
     #[serde(default)]
@@ -13,9 +38,222 @@ pub struct Bookmark {
     pub created_at: u64,
 }
 
+/// A visited directory with its frecency data.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecentDir {
+    pub path: PathBuf,
+    /// Visit count, scaled down as the history ages
+    pub rank: f64,
+    /// Unix time of the last visit
+    pub last_access: u64,
+}
+
+impl RecentDir {
+    /// Combines visit count and recency the way zoxide does: recent visits
+    /// multiply the rank, old ones discount it.
+    pub fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_access);
+        let weight = if age < HOUR {
+            4.0
+        } else if age < DAY {
+            2.0
+        } else if age < WEEK {
+            0.5
+        } else {
+            0.25
+        };
+        self.rank * weight
+    }
+}
+
+/// The schema version this build reads and writes. Bump it and append a step
+/// to `MIGRATIONS` whenever `state.json` changes shape.
+const STATE_VERSION: u64 = 3;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` document to version `n + 2`.
+const MIGRATIONS: &[fn(&mut Value)] = &[migrate_v1_to_v2, migrate_v2_to_v3];
+
+enum MigrationError {
+    Invalid(String),
+    /// Written by a newer treenav, which this build cannot read or safely replace
+    Newer(u64),
+}
+
+/// Upgrades a `state.json` document to `STATE_VERSION`, one step at a time.
+/// Documents without a `version` key predate versioning and count as version 1.
+fn migrate(mut document: Value) -> Result<Value, MigrationError> {
+    let version = match document.get("version") {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .filter(|&v| v >= 1)
+            .ok_or_else(|| MigrationError::Invalid(format!("invalid version {}", v)))?,
+    };
+    if version > STATE_VERSION {
+        return Err(MigrationError::Newer(version));
+    }
+
+    for step in &MIGRATIONS[version as usize - 1..] {
+        step(&mut document);
+    }
+    if let Some(object) = document.as_object_mut() {
+        object.insert("version".to_string(), STATE_VERSION.into());
+    }
+    Ok(document)
+}
+
+/// Version 2 replaced the most-recent-first `recent_dirs` path list with
+/// frecency entries. The old order is kept as access times a second apart.
+fn migrate_v1_to_v2(document: &mut Value) {
+    let now = now();
+    let Some(recent_dirs) = document
+        .get_mut("recent_dirs")
+        .and_then(Value::as_array_mut)
+    else {
+        return;
+    };
+    for (i, entry) in recent_dirs.iter_mut().enumerate() {
+        // Unversioned builds with frecency already wrote objects
+        if let Value::String(path) = entry {
+            *entry = json!({
+                "path": path,
+                "rank": 1.0,
+                "last_access": now.saturating_sub(i as u64),
+            });
+        }
+    }
+}
+
+/// Version 3 moved the global `expanded_dirs` set into per-root state. Each
+/// directory goes to the root it was most likely expanded under: the parent
+/// of its topmost expanded ancestor.
+fn migrate_v2_to_v3(document: &mut Value) {
+    let Some(object) = document.as_object_mut() else {
+        return;
+    };
+    let expanded: HashSet<PathBuf> = object
+        .remove("expanded_dirs")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+
+    let mut roots: HashMap<PathBuf, RootState> = HashMap::new();
+    for dir in &expanded {
+        let top = dir
+            .ancestors()
+            .take_while(|a| expanded.contains(*a))
+            .last()
+            .unwrap_or(dir);
+        let root = top.parent().unwrap_or(top).to_path_buf();
+        roots
+            .entry(root)
+            .or_default()
+            .expanded_dirs
+            .insert(dir.clone());
+    }
+    object.insert("roots".to_string(), json!(roots));
+}
+
+static NO_DIRS: LazyLock<HashSet<PathBuf>> = LazyLock::new(HashSet::new);
+
+/// How a tree root was left: what was expanded, selected and scrolled to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RootState {
+    #[serde(default)]
+    pub expanded_dirs: HashSet<PathBuf>,
+    #[serde(default)]
+    pub selected: Option<PathBuf>,
+    #[serde(default)]
+    pub offset: usize,
+    /// When the root was last left, so gc can drop the least used
+    #[serde(default)]
+    pub used_at: u64,
+}
+
+impl RootState {
+    fn rename_path(&mut self, remap: impl Fn(&PathBuf) -> PathBuf) {
+        self.expanded_dirs = self.expanded_dirs.iter().map(&remap).collect();
+        self.selected = self.selected.as_ref().map(remap);
+    }
+
+    /// Drops the paths at or below `path`, returning them.
+    fn forget_path(&mut self, path: &Path) -> RootState {
+        let (forgotten, kept) = std::mem::take(&mut self.expanded_dirs)
+            .into_iter()
+            .partition(|p| p.starts_with(path));
+        self.expanded_dirs = kept;
+        RootState {
+            expanded_dirs: forgotten,
+            selected: self.selected.take_if(|p| p.starts_with(path)),
+            ..RootState::default()
+        }
+    }
+
+    /// Puts back what `forget_path` returned, unless another entry has been
+    /// selected since.
+    fn restore(&mut self, forgotten: RootState) {
+        self.expanded_dirs.extend(forgotten.expanded_dirs);
+        if self.selected.is_none() {
+            self.selected = forgotten.selected;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.expanded_dirs.is_empty() && self.selected.is_none()
+    }
+
+    /// Whether opening the root would look the same without this entry.
+    pub fn is_default(&self) -> bool {
+        self.is_empty() && self.offset == 0
+    }
+}
+
+/// What `forget_path` dropped, so restoring a trashed path can bring back
+/// its stars, bookmarks and the rest.
+#[derive(Debug, Clone, Default)]
+pub struct ForgottenPaths {
+    roots: Vec<(PathBuf, RootState)>,
+    /// Expanded directories and selections dropped from roots that are kept
+    root_trees: Vec<(PathBuf, RootState)>,
+    session_trees: Vec<(String, RootState)>,
+    starred_dirs: Vec<PathBuf>,
+    /// With their positions in the list
+    bookmarks: Vec<(usize, Bookmark)>,
+    recent_dirs: Vec<RecentDir>,
+}
+
+/// A named snapshot of a whole session, restored with `--session <name>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub root: PathBuf,
+    #[serde(default)]
+    pub tree: RootState,
+    #[serde(default)]
+    pub show_preview: bool,
+    #[serde(default)]
+    pub view_mode: SavedView,
+    #[serde(default)]
+    pub saved_at: u64,
+}
+
+/// The view a session was left in, as written to `state.json`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SavedView {
+    #[default]
+    Tree,
+    Starred,
+    Bookmarks,
+    Recent,
+    Trash,
+    DiskUsage,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PersistentState {
-    pub expanded_dirs: HashSet<PathBuf>,
+    #[serde(default)]
+    pub roots: HashMap<PathBuf, RootState>,
+    #[serde(default)]
+    pub sessions: BTreeMap<String, Session>,
     #[serde(default)]
     pub starred_dirs: HashSet<PathBuf>,
     #[serde(default)]
@@ -23,7 +261,70 @@ pub struct PersistentState {
     #[serde(default)]
     pub bookmarks: Vec<Bookmark>,
     #[serde(default)]
-    pub recent_dirs: VecDeque<PathBuf>,
+    pub recent_dirs: Vec<RecentDir>,
+    /// The state as last read from or written to disk. `save` merges the
+    /// difference between this and the current state into the file, so
+    /// concurrent sessions only overwrite what they changed themselves.
+    #[serde(skip)]
+    base: Option<Box<PersistentState>>,
+    /// Set when `state.json` could not be used: corrupt and moved aside, or
+    /// written by a newer treenav
+    #[serde(skip)]
+    pub load_error: Option<String>,
+}
+
+/// Applies the additions and removals between `base` and `ours` to `target`.
+fn merge_set(target: &mut HashSet<PathBuf>, base: &HashSet<PathBuf>, ours: &HashSet<PathBuf>) {
+    for removed in base.difference(ours) {
+        target.remove(removed);
+    }
+    for added in ours.difference(base) {
+        target.insert(added.clone());
+    }
+}
+
+/// Reads and migrates `state.json`. A missing file is an empty state; a corrupt
+/// one is renamed to `state.json.corrupt-<time>` so it can be recovered. Files
+/// from a newer treenav are left alone.
+fn read_state_file(path: &Path) -> Result<PersistentState, String> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(PersistentState::default()),
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+    };
+    let document = serde_json::from_slice(&contents).map_err(|e| move_aside(path, e))?;
+    let document = match migrate(document) {
+        Ok(document) => document,
+        Err(MigrationError::Invalid(e)) => return Err(move_aside(path, e)),
+        Err(MigrationError::Newer(version)) => {
+            return Err(format!(
+                "{} is from a newer treenav (state version {}, this build supports {}); \
+                 changes will not be saved",
+                path.display(),
+                version,
+                STATE_VERSION
+            ))
+        }
+    };
+    serde_json::from_value(document).map_err(|e| move_aside(path, e))
+}
+
+fn move_aside(path: &Path, e: impl std::fmt::Display) -> String {
+    let backup = path.with_extension(format!("json.corrupt-{}", now()));
+    match fs::rename(path, &backup) {
+        Ok(()) => format!(
+            "{} is corrupt ({}); moved it to {}",
+            path.display(),
+            e,
+            backup.display()
+        ),
+        Err(rename_error) => format!(
+            "{} is corrupt ({}) and could not be moved aside: {}",
+            path.display(),
+            e,
+            rename_error
+        ),
+    }
 }
 
 impl PersistentState {
@@ -32,21 +333,181 @@ impl PersistentState {
     }
 
     pub fn load() -> Self {
-        Self::state_file_path()
-            .and_then(|path| fs::read_to_string(&path).ok())
-            .and_then(|contents| serde_json::from_str(&contents).ok())
-            .unwrap_or_default()
+        let Some(path) = Self::state_file_path() else {
+            return Self::default();
+        };
+        let (mut state, load_error) = match read_state_file(&path) {
+            Ok(state) => (state, None),
+            Err(e) => (Self::default(), Some(e)),
+        };
+        state.base = Some(Box::new(state.clone()));
+        state.load_error = load_error;
+        state
     }
 
-    pub fn save(&self) -> io::Result<()> {
-        if let Some(path) = Self::state_file_path() {
-            if let Some(parent) = path.parent() {
-                fs::create_dir_all(parent)?;
+    /// Writes the state atomically under an advisory lock, after merging in
+    /// whatever other sessions saved since this one loaded. Returns what was
+    /// wrong with the file on disk if it turned out corrupt and was moved
+    /// aside, or could not be read and was left as it is, like `load_error`.
+    pub fn save(&mut self) -> io::Result<Option<String>> {
+        let Some(path) = Self::state_file_path() else {
+            return Ok(None);
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let lock = fs::File::create(path.with_extension("json.lock"))?;
+        lock.lock()?;
+
+        let mut moved_aside = None;
+        let on_disk = match read_state_file(&path) {
+            Ok(state) => state,
+            // A corrupt file has been moved aside, so there is nothing to merge
+            Err(e) if !path.exists() => {
+                moved_aside = Some(e);
+                Self::default()
             }
-            let json = serde_json::to_string_pretty(self)?;
-            fs::write(&path, json)?;
+            // Like one from a newer treenav, which is not ours to overwrite
+            Err(e) => return Ok(Some(e)),
+        };
+        let base = self.base.take().map(|b| *b).unwrap_or_default();
+        let mut merged = on_disk.merged_with(&base, self);
+        merged.age_recent();
+
+        let mut document = serde_json::to_value(&merged)?;
+        if let Some(object) = document.as_object_mut() {
+            object.insert("version".to_string(), STATE_VERSION.into());
+        }
+        let json = serde_json::to_string_pretty(&document)?;
+        let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        let written = fs::File::create(&tmp).and_then(|mut file| {
+            file.write_all(json.as_bytes())?;
+            file.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| fs::rename(&tmp, &path)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+
+        merged.base = Some(Box::new(merged.clone()));
+        merged.load_error = self.load_error.take();
+        *self = merged;
+        Ok(moved_aside)
+    }
+
+    /// Applies the changes from `base` to `ours` on top of `self`, field by
+    /// field: set members and list entries added or removed in `ours` are
+    /// added or removed here, and recent ranks gained since `base` are added.
+    fn merged_with(mut self, base: &Self, ours: &Self) -> Self {
+        let roots: HashSet<&PathBuf> = base.roots.keys().chain(ours.roots.keys()).collect();
+        for root in roots {
+            let (base_root, our_root) = (base.roots.get(root), ours.roots.get(root));
+            if base_root == our_root {
+                continue;
+            }
+            let Some(our_root) = our_root else {
+                self.roots.remove(root);
+                continue;
+            };
+            let base_root = base_root.cloned().unwrap_or_default();
+            let merged = self.roots.entry(root.clone()).or_default();
+            merge_set(
+                &mut merged.expanded_dirs,
+                &base_root.expanded_dirs,
+                &our_root.expanded_dirs,
+            );
+            if our_root.selected != base_root.selected || our_root.offset != base_root.offset {
+                merged.selected = our_root.selected.clone();
+                merged.offset = our_root.offset;
+            }
+            merged.used_at = merged.used_at.max(our_root.used_at);
+        }
+
+        let names: HashSet<&String> = base.sessions.keys().chain(ours.sessions.keys()).collect();
+        for name in names {
+            match ours.sessions.get(name) {
+                ours_session if ours_session == base.sessions.get(name) => {}
+                Some(session) => {
+                    self.sessions.insert(name.clone(), session.clone());
+                }
+                None => {
+                    self.sessions.remove(name);
+                }
+            }
+        }
+
+        merge_set(
+            &mut self.starred_dirs,
+            &base.starred_dirs,
+            &ours.starred_dirs,
+        );
+        if ours.show_hidden != base.show_hidden {
+            self.show_hidden = ours.show_hidden;
+        }
+
+        self.bookmarks.retain(|b| {
+            let removed =
+                base.get_bookmark(&b.path).is_some() && ours.get_bookmark(&b.path).is_none();
+            !removed
+        });
+        for bookmark in &ours.bookmarks {
+            if base.get_bookmark(&bookmark.path) == Some(bookmark) {
+                continue;
+            }
+            match self.bookmarks.iter_mut().find(|b| b.path == bookmark.path) {
+                Some(existing) => *existing = bookmark.clone(),
+                None => self.bookmarks.push(bookmark.clone()),
+            }
+        }
+
+        let find = |list: &[RecentDir], path: &Path| list.iter().position(|r| r.path == path);
+        self.recent_dirs.retain(|r| {
+            let removed = find(&base.recent_dirs, &r.path).is_some()
+                && find(&ours.recent_dirs, &r.path).is_none();
+            !removed
+        });
+        for recent in &ours.recent_dirs {
+            let base_rank =
+                find(&base.recent_dirs, &recent.path).map_or(0.0, |i| base.recent_dirs[i].rank);
+            match find(&self.recent_dirs, &recent.path) {
+                Some(i) => {
+                    let existing = &mut self.recent_dirs[i];
+                    existing.rank += (recent.rank - base_rank).max(0.0);
+                    existing.last_access = existing.last_access.max(recent.last_access);
+                }
+                // Gone from disk: only bring it back if visited again since
+                None if recent.rank > base_rank => {
+                    self.recent_dirs.push(recent.clone());
+                }
+                None => {}
+            }
+        }
+        self
+    }
+
+    /// Directories expanded in the tree rooted at `root`.
+    pub fn expanded_dirs(&self, root: &Path) -> &HashSet<PathBuf> {
+        self.roots.get(root).map_or(&NO_DIRS, |r| &r.expanded_dirs)
+    }
+
+    pub fn expanded_dirs_mut(&mut self, root: &Path) -> &mut HashSet<PathBuf> {
+        &mut self.root_mut(root).expanded_dirs
+    }
+
+    pub fn root_mut(&mut self, root: &Path) -> &mut RootState {
+        self.roots.entry(root.to_path_buf()).or_default()
+    }
+
+    /// Records how `root` was left, or drops its entry if there is nothing
+    /// worth keeping, so merely opening a directory leaves no trace.
+    pub fn set_root(&mut self, root: &Path, mut root_state: RootState) {
+        if root_state.is_default() {
+            self.roots.remove(root);
+        } else {
+            root_state.used_at = now();
+            self.roots.insert(root.to_path_buf(), root_state);
         }
-        Ok(())
     }
 
     pub fn add_bookmark(&mut self, path: PathBuf, label: String) {
@@ -54,10 +515,7 @@ impl PersistentState {
         self.bookmarks.push(Bookmark {
             path,
             label,
-            created_at: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
+            created_at: now(),
         });
     }
 
@@ -65,11 +523,201 @@ impl PersistentState {
         self.bookmarks.iter().find(|b| &b.path == path)
     }
 
+    /// Points every remembered path at or below `from` to the same place below `to`.
+    pub fn rename_path(&mut self, from: &Path, to: &Path) {
+        let remap = |p: &PathBuf| -> PathBuf {
+            p.strip_prefix(from)
+                .map(|rest| to.join(rest))
+                .unwrap_or_else(|_| p.clone())
+        };
+
+        self.roots = std::mem::take(&mut self.roots)
+            .into_iter()
+            .map(|(root, mut state)| {
+                state.rename_path(remap);
+                (remap(&root), state)
+            })
+            .collect();
+        for session in self.sessions.values_mut() {
+            session.root = remap(&session.root);
+            session.tree.rename_path(remap);
+        }
+        self.starred_dirs = self.starred_dirs.iter().map(remap).collect();
+        for bookmark in &mut self.bookmarks {
+            bookmark.path = remap(&bookmark.path);
+        }
+        for recent in &mut self.recent_dirs {
+            recent.path = remap(&recent.path);
+        }
+    }
+
+    /// Drops every remembered path at or below `path`, returning what was
+    /// dropped for `restore_forgotten`.
+    pub fn forget_path(&mut self, path: &Path) -> ForgottenPaths {
+        let roots = self
+            .roots
+            .extract_if(|root, _| root.starts_with(path))
+            .collect();
+        let root_trees = self
+            .roots
+            .iter_mut()
+            .map(|(root, state)| (root.clone(), state.forget_path(path)))
+            .filter(|(_, tree)| !tree.is_empty())
+            .collect();
+        let session_trees = self
+            .sessions
+            .iter_mut()
+            .map(|(name, session)| (name.clone(), session.tree.forget_path(path)))
+            .filter(|(_, tree)| !tree.is_empty())
+            .collect();
+        let starred_dirs = self
+            .starred_dirs
+            .extract_if(|p| p.starts_with(path))
+            .collect();
+        let bookmarks = self
+            .bookmarks
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.path.starts_with(path))
+            .map(|(i, b)| (i, b.clone()))
+            .collect();
+        self.bookmarks.retain(|b| !b.path.starts_with(path));
+        let recent_dirs = self
+            .recent_dirs
+            .extract_if(.., |r| r.path.starts_with(path))
+            .collect();
+        ForgottenPaths {
+            roots,
+            root_trees,
+            session_trees,
+            starred_dirs,
+            bookmarks,
+            recent_dirs,
+        }
+    }
+
+    /// Brings back what `forget_path` dropped, keeping whatever was set for
+    /// the same paths since.
+    pub fn restore_forgotten(&mut self, forgotten: ForgottenPaths) {
+        for (root, state) in forgotten.roots {
+            self.roots.entry(root).or_insert(state);
+        }
+        for (root, tree) in forgotten.root_trees {
+            if let Some(state) = self.roots.get_mut(&root) {
+                state.restore(tree);
+            }
+        }
+        for (name, tree) in forgotten.session_trees {
+            if let Some(session) = self.sessions.get_mut(&name) {
+                session.tree.restore(tree);
+            }
+        }
+        self.starred_dirs.extend(forgotten.starred_dirs);
+        for (i, bookmark) in forgotten.bookmarks {
+            if self.get_bookmark(&bookmark.path).is_none() {
+                self.bookmarks.insert(i.min(self.bookmarks.len()), bookmark);
+            }
+        }
+        for recent in forgotten.recent_dirs {
+            if !self.recent_dirs.iter().any(|r| r.path == recent.path) {
+                self.recent_dirs.push(recent);
+            }
+        }
+    }
+
+    /// Records a visit to `path`, raising its frecency.
     pub fn add_recent(&mut self, path: PathBuf) {
-        self.recent_dirs.retain(|p| p != &path);
-        self.recent_dirs.push_front(path);
-        while self.recent_dirs.len() > 50 {
-            self.recent_dirs.pop_back();
+        let now = now();
+        match self.recent_dirs.iter_mut().find(|r| r.path == path) {
+            Some(recent) => {
+                recent.rank += 1.0;
+                recent.last_access = now;
+            }
+            None => self.recent_dirs.push(RecentDir {
+                path,
+                rank: 1.0,
+                last_access: now,
+            }),
         }
+        self.age_recent();
+    }
+
+    /// Merges visits recorded elsewhere: ranks add up and the later access wins.
+    /// Returns whether `imported` was a directory not seen before.
+    pub fn merge_recent(&mut self, imported: RecentDir) -> bool {
+        match self
+            .recent_dirs
+            .iter_mut()
+            .find(|r| r.path == imported.path)
+        {
+            Some(recent) => {
+                recent.rank += imported.rank;
+                recent.last_access = recent.last_access.max(imported.last_access);
+                false
+            }
+            None => {
+                self.recent_dirs.push(imported);
+                true
+            }
+        }
+    }
+
+    /// Scales ranks from another jumper down into treenav's range, keeping
+    /// their order. Other jumpers let ranks grow far past ours before aging
+    /// them, or start every entry high, so merged as they are, imported
+    /// directories would outrank the ones actually visited here for long.
+    /// Entries whose rank is not a positive number would break the order, so
+    /// they are dropped and returned.
+    pub fn scale_imported(entries: &mut Vec<RecentDir>) -> Vec<RecentDir> {
+        let (usable, unusable) = std::mem::take(entries)
+            .into_iter()
+            .partition(|e| e.rank.is_finite() && e.rank > 0.0);
+        *entries = usable;
+
+        let total: f64 = entries.iter().map(|e| e.rank).sum();
+        let highest = entries.iter().map(|e| e.rank).fold(0.0, f64::max);
+        if highest <= 0.0 {
+            return unusable;
+        }
+        let factor = (IMPORT_RANK_SHARE * MAX_TOTAL_RANK / total)
+            .min(MAX_IMPORTED_RANK / highest)
+            .min(1.0);
+        for entry in entries {
+            entry.rank *= factor;
+        }
+        unusable
+    }
+
+    /// Scales ranks down once they add up past `MAX_TOTAL_RANK`.
+    pub fn age_recent(&mut self) {
+        let total: f64 = self.recent_dirs.iter().map(|r| r.rank).sum();
+        if total <= MAX_TOTAL_RANK {
+            return;
+        }
+        let factor = 0.9 * MAX_TOTAL_RANK / total;
+        for recent in &mut self.recent_dirs {
+            recent.rank *= factor;
+        }
+        self.recent_dirs.retain(|r| r.rank >= 1.0);
+    }
+
+    /// Recent directories, highest frecency first.
+    pub fn ranked_recent(&self) -> Vec<&RecentDir> {
+        let now = now();
+        let mut ranked: Vec<&RecentDir> = self.recent_dirs.iter().collect();
+        ranked.sort_by(|a, b| {
+            b.frecency(now)
+                .total_cmp(&a.frecency(now))
+                .then(b.last_access.cmp(&a.last_access))
+        });
+        ranked
+    }
+
+    /// The frecency of `path`, or 0 if it was never visited.
+    pub fn frecency(&self, path: &Path) -> f64 {
+        self.recent_dirs
+            .iter()
+            .find(|r| r.path == path)
+            .map_or(0.0, |r| r.frecency(now()))
     }
 }
@@ -0,0 +1,671 @@
+use crate::config::Diagnostic;
+use ratatui::style::{Color, Modifier, Style};
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use toml::Spanned;
+
+/// Themes that ship with treenav, written like the files in `themes/`, each
+/// with the highlight theme file previews use by default.
+const PRESETS: &[(&str, &str, &str)] = &[
+    (
+        "gruvbox",
+        include_str!("themes/gruvbox.toml"),
+        "base16-eighties.dark",
+    ),
+    (
+        "solarized",
+        include_str!("themes/solarized.toml"),
+        "Solarized (dark)",
+    ),
+    (
+        "nord",
+        include_str!("themes/nord.toml"),
+        "base16-ocean.dark",
+    ),
+    ("light", include_str!("themes/light.toml"), "InspiredGitHub"),
+];
+
+/// The highlight theme of the default theme and themes not based on a preset.
+const DEFAULT_HIGHLIGHT: &str = "base16-ocean.dark";
+
+/// Colors that set a whole group of elements at once. These were the only
+/// theme settings before elements could be styled one by one.
+const PALETTE: &[&str] = &["border", "starred", "text", "dim", "highlight_bg", "marked"];
+
+macro_rules! theme_elements {
+    ($($(#[$doc:meta])* $name:ident,)*) => {
+        /// The style of every element treenav draws.
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct Theme {
+            $($(#[$doc])* pub $name: Style,)*
+            /// Highlight theme for file previews, unless `[preview]` names one
+            pub highlight: &'static str,
+        }
+
+        impl Theme {
+            /// Element names as written in `[theme]`.
+            pub const ELEMENTS: &'static [&'static str] = &[$(stringify!($name)),*];
+
+            pub fn element(&self, name: &str) -> Option<Style> {
+                match name {
+                    $(stringify!($name) => Some(self.$name),)*
+                    _ => None,
+                }
+            }
+
+            fn element_mut(&mut self, name: &str) -> Option<&mut Style> {
+                match name {
+                    $(stringify!($name) => Some(&mut self.$name),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+theme_elements! {
+    /// Border of the tree
+    tree_border,
+    /// Root path above the tree
+    tree_title,
+    /// Border of the starred, bookmarks, recent and trash lists
+    list_border,
+    list_title,
+    /// Tree rows
+    entry,
+    /// The selected row
+    selection,
+    marked_entry,
+    /// Footer bar, under the keys
+    footer,
+    footer_key,
+    footer_text,
+    footer_separator,
+    /// Messages shown in place of the footer
+    status,
+    /// Warning about config problems at startup
+    banner,
+    /// Background of the help and of dialogs
+    popup,
+    popup_border,
+    popup_title,
+    help_heading,
+    help_key,
+    help_text,
+    /// Secondary text in popups, like "Press any key to close"
+    hint,
+    /// Preview pane contents
+    preview,
+    preview_border,
+    preview_title,
+    /// Line numbers beside file previews
+    preview_line_number,
+    /// Search bar at the bottom
+    search,
+    search_text,
+    search_count,
+    /// Labels above dialog inputs
+    prompt,
+    /// Text typed into dialogs
+    input,
+    /// Keys listed in dialogs
+    dialog_key,
+    /// The trash confirmation dialog
+    confirm_border,
+    confirm_title,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let border = Color::Rgb(80, 200, 220);
+        let starred = Color::Rgb(250, 200, 50);
+        let dim = Color::Rgb(100, 100, 100);
+        let text = Color::White;
+        let bold = Modifier::BOLD;
+        Self {
+            tree_border: Style::new().fg(border),
+            tree_title: Style::new().fg(border).add_modifier(bold),
+            list_border: Style::new().fg(starred),
+            list_title: Style::new().fg(starred).add_modifier(bold),
+            entry: Style::new(),
+            selection: Style::new()
+                .fg(text)
+                .bg(Color::Rgb(40, 80, 100))
+                .add_modifier(bold),
+            marked_entry: Style::new()
+                .fg(Color::Rgb(220, 120, 220))
+                .add_modifier(bold),
+            footer: Style::new().bg(Color::Rgb(20, 20, 30)),
+            footer_key: Style::new().fg(border).add_modifier(bold),
+            footer_text: Style::new().fg(dim),
+            footer_separator: Style::new().fg(Color::DarkGray),
+            status: Style::new().fg(text).bg(Color::Rgb(20, 20, 30)),
+            banner: Style::new().fg(Color::Black).bg(Color::Yellow),
+            popup: Style::new().bg(Color::Rgb(15, 15, 25)),
+            popup_border: Style::new().fg(border),
+            popup_title: Style::new().fg(border).add_modifier(bold),
+            help_heading: Style::new().fg(starred).add_modifier(bold),
+            help_key: Style::new().fg(border),
+            help_text: Style::new().fg(text),
+            hint: Style::new().fg(dim).add_modifier(Modifier::ITALIC),
+            preview: Style::new().fg(dim),
+            preview_border: Style::new().fg(border),
+            preview_title: Style::new().fg(border).add_modifier(bold),
+            preview_line_number: Style::new().fg(dim),
+            search: Style::new().bg(Color::Rgb(30, 30, 40)),
+            search_text: Style::new().fg(text),
+            search_count: Style::new().fg(dim),
+            prompt: Style::new().fg(dim),
+            input: Style::new().fg(text),
+            dialog_key: Style::new().fg(border).add_modifier(bold),
+            confirm_border: Style::new().fg(starred),
+            confirm_title: Style::new().fg(starred).add_modifier(bold),
+            highlight: DEFAULT_HIGHLIGHT,
+        }
+    }
+}
+
+impl Theme {
+    /// Sets one of the `PALETTE` colors, which each cover a group of elements.
+    fn set_palette(&mut self, name: &str, color: Color) {
+        match name {
+            "border" => {
+                for style in [
+                    &mut self.tree_border,
+                    &mut self.tree_title,
+                    &mut self.footer_key,
+                    &mut self.popup_border,
+                    &mut self.popup_title,
+                    &mut self.help_key,
+                    &mut self.preview_border,
+                    &mut self.preview_title,
+                    &mut self.dialog_key,
+                ] {
+                    style.fg = Some(color);
+                }
+            }
+            "starred" => {
+                for style in [
+                    &mut self.list_border,
+                    &mut self.list_title,
+                    &mut self.help_heading,
+                    &mut self.confirm_border,
+                    &mut self.confirm_title,
+                ] {
+                    style.fg = Some(color);
+                }
+            }
+            "text" => {
+                for style in [
+                    &mut self.selection,
+                    &mut self.status,
+                    &mut self.help_text,
+                    &mut self.search_text,
+                    &mut self.input,
+                ] {
+                    style.fg = Some(color);
+                }
+            }
+            "dim" => {
+                for style in [
+                    &mut self.footer_text,
+                    &mut self.hint,
+                    &mut self.preview,
+                    &mut self.preview_line_number,
+                    &mut self.search_count,
+                    &mut self.prompt,
+                ] {
+                    style.fg = Some(color);
+                }
+            }
+            "highlight_bg" => self.selection.bg = Some(color),
+            "marked" => self.marked_entry.fg = Some(color),
+            _ => unreachable!("not a palette color: {}", name),
+        }
+    }
+
+    /// Applies a theme table: `name` first, then palette colors, then
+    /// elements, so the more specific setting wins.
+    fn apply(&mut self, table: &ThemeTable, contents: &str, errors: &mut Vec<Diagnostic>) {
+        let mut entries: Vec<&(Spanned<String>, Spanned<StyleValue>)> = table.0.iter().collect();
+        entries.sort_by_key(|(key, _)| match key.get_ref().as_str() {
+            "name" => 0,
+            name if self.element(name).is_none() => 1,
+            _ => 2,
+        });
+
+        for (key, value) in entries {
+            let error = |message: String| Diagnostic::at(contents, value.span().start, message);
+            let name = key.get_ref().as_str();
+            if name == "name" {
+                // Handled by the caller, which knows where themes live
+                continue;
+            }
+            if let Some(style) = self.element_mut(name) {
+                if let Err(message) = value.get_ref().apply_to(style) {
+                    errors.push(error(message));
+                }
+                continue;
+            }
+            if !PALETTE.contains(&name) {
+                errors.push(Diagnostic::at(
+                    contents,
+                    key.span().start,
+                    format!("unknown theme element '{}'", name),
+                ));
+                continue;
+            }
+            match value.get_ref() {
+                StyleValue::Color(color) => match color.resolve() {
+                    Ok(color) => self.set_palette(name, color),
+                    Err(message) => errors.push(error(message)),
+                },
+                StyleValue::Table(_) => {
+                    errors.push(error(format!("'{}' takes a color, not a table", name)))
+                }
+            }
+        }
+    }
+
+    /// The theme as a `[theme]` table that sets every element.
+    pub fn to_toml(&self) -> toml::Table {
+        let mut table = toml::Table::new();
+        for name in Self::ELEMENTS {
+            let style = self.element(name).expect("element exists");
+            table.insert(name.to_string(), style_value(style));
+        }
+        table
+    }
+}
+
+/// `theme = "name"`, or a `[theme]` table that may name a theme to start from.
+#[derive(Debug)]
+pub enum ThemeSetting {
+    Name(Spanned<String>),
+    Table(ThemeTable),
+}
+
+impl<'de> Deserialize<'de> for ThemeSetting {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Keeps the spans of the table's entries, unlike an untagged enum
+        let value = Spanned::<NameOrTable>::deserialize(deserializer)?;
+        let span = value.span();
+        Ok(match value.into_inner() {
+            NameOrTable::Name(name) => ThemeSetting::Name(Spanned::new(span, name)),
+            NameOrTable::Table(table) => ThemeSetting::Table(table),
+        })
+    }
+}
+
+enum NameOrTable {
+    Name(String),
+    Table(ThemeTable),
+}
+
+impl<'de> Deserialize<'de> for NameOrTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NameOrTableVisitor;
+
+        impl<'de> Visitor<'de> for NameOrTableVisitor {
+            type Value = NameOrTable;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a theme name or a table")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                Ok(NameOrTable::Name(name.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                ThemeTableVisitor.visit_map(map).map(NameOrTable::Table)
+            }
+        }
+
+        deserializer.deserialize_any(NameOrTableVisitor)
+    }
+}
+
+/// Entries of a theme table, in file order, with their spans.
+#[derive(Debug, Default)]
+pub struct ThemeTable(Vec<(Spanned<String>, Spanned<StyleValue>)>);
+
+impl ThemeTable {
+    pub fn entries(&self) -> &[(Spanned<String>, Spanned<StyleValue>)] {
+        &self.0
+    }
+}
+
+struct ThemeTableVisitor;
+
+impl<'de> Visitor<'de> for ThemeTableVisitor {
+    type Value = ThemeTable;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a table of theme elements")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key::<Spanned<String>>()? {
+            entries.push((key, map.next_value()?));
+        }
+        Ok(ThemeTable(entries))
+    }
+}
+
+impl<'de> Deserialize<'de> for ThemeTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ThemeTableVisitor)
+    }
+}
+
+/// A color, which sets the foreground alone, or a table of `fg`, `bg` and
+/// `modifiers`.
+#[derive(Debug)]
+pub enum StyleValue {
+    Color(ColorValue),
+    Table(StyleTable),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StyleTable {
+    fg: Option<ColorValue>,
+    bg: Option<ColorValue>,
+    modifiers: Option<Vec<String>>,
+}
+
+/// A color name, `#rrggbb`, `default`, or a 256-color index.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ColorValue {
+    Name(String),
+    Index(i64),
+}
+
+impl<'de> Deserialize<'de> for StyleValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StyleVisitor;
+
+        impl<'de> Visitor<'de> for StyleVisitor {
+            type Value = StyleValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a color or a table of fg, bg and modifiers")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                Ok(StyleValue::Color(ColorValue::Name(name.to_string())))
+            }
+
+            fn visit_i64<E: de::Error>(self, index: i64) -> Result<Self::Value, E> {
+                Ok(StyleValue::Color(ColorValue::Index(index)))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                StyleTable::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(StyleValue::Table)
+            }
+        }
+
+        deserializer.deserialize_any(StyleVisitor)
+    }
+}
+
+impl ColorValue {
+    fn resolve(&self) -> Result<Color, String> {
+        match self {
+            ColorValue::Name(name) => parse_color(name).ok_or_else(|| {
+                format!(
+                    "invalid color '{}', expected #rrggbb, a color name, default or 0-255",
+                    name
+                )
+            }),
+            ColorValue::Index(index) => u8::try_from(*index)
+                .map(Color::Indexed)
+                .map_err(|_| format!("color index {} is not in 0-255", index)),
+        }
+    }
+}
+
+impl StyleValue {
+    pub fn apply_to(&self, style: &mut Style) -> Result<(), String> {
+        match self {
+            StyleValue::Color(color) => style.fg = Some(color.resolve()?),
+            StyleValue::Table(table) => {
+                if let Some(fg) = &table.fg {
+                    style.fg = Some(fg.resolve()?);
+                }
+                if let Some(bg) = &table.bg {
+                    style.bg = Some(bg.resolve()?);
+                }
+                if let Some(names) = &table.modifiers {
+                    let mut modifiers = Modifier::empty();
+                    for name in names {
+                        modifiers |= parse_modifier(name)?;
+                    }
+                    style.add_modifier = modifiers;
+                    style.sub_modifier = Modifier::empty();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_modifier(name: &str) -> Result<Modifier, String> {
+    Ok(match name.to_lowercase().as_str() {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underlined" | "underline" => Modifier::UNDERLINED,
+        "slow_blink" | "blink" => Modifier::SLOW_BLINK,
+        "rapid_blink" => Modifier::RAPID_BLINK,
+        "reversed" | "reverse" => Modifier::REVERSED,
+        "hidden" => Modifier::HIDDEN,
+        "crossed_out" | "strikethrough" => Modifier::CROSSED_OUT,
+        _ => return Err(format!("unknown modifier '{}'", name)),
+    })
+}
+
+/// Builds the theme from the `theme` setting. Named themes are looked up in
+/// `themes_dir` first, then among the presets.
+pub fn resolve(
+    setting: Option<&ThemeSetting>,
+    contents: &str,
+    themes_dir: Option<&Path>,
+) -> (Theme, Vec<Diagnostic>) {
+    let mut theme = Theme::default();
+    let mut errors = Vec::new();
+    let (name, table) = match setting {
+        None => return (theme, errors),
+        Some(ThemeSetting::Name(name)) => (Some(name.clone()), None),
+        Some(ThemeSetting::Table(table)) => (table_name(table, contents, &mut errors), Some(table)),
+    };
+
+    if let Some(name) = name {
+        match load_named(name.get_ref(), themes_dir, &mut errors) {
+            Some(named) => theme = named,
+            None => errors.push(Diagnostic::at(
+                contents,
+                name.span().start,
+                format!("no theme named '{}'", name.get_ref()),
+            )),
+        }
+    }
+    if let Some(table) = table {
+        theme.apply(table, contents, &mut errors);
+    }
+    (theme, errors)
+}
+
+/// The `name` entry of a theme table, if it has one.
+fn table_name(
+    table: &ThemeTable,
+    contents: &str,
+    errors: &mut Vec<Diagnostic>,
+) -> Option<Spanned<String>> {
+    let (_, value) = table.0.iter().find(|(key, _)| key.get_ref() == "name")?;
+    match value.get_ref() {
+        StyleValue::Color(ColorValue::Name(name)) => Some(Spanned::new(value.span(), name.clone())),
+        _ => {
+            errors.push(Diagnostic::at(
+                contents,
+                value.span().start,
+                "theme name must be a string",
+            ));
+            None
+        }
+    }
+}
+
+/// Loads `themes/<name>.toml` or a preset. A theme file may itself name a
+/// preset to start from.
+fn load_named(
+    name: &str,
+    themes_dir: Option<&Path>,
+    errors: &mut Vec<Diagnostic>,
+) -> Option<Theme> {
+    if name == "default" {
+        return Some(Theme::default());
+    }
+    let file = themes_dir.map(|dir| dir.join(format!("{}.toml", name)));
+    if let Some(contents) = file.as_ref().and_then(|f| fs::read_to_string(f).ok()) {
+        let file = file.expect("file was read");
+        let mut file_errors = Vec::new();
+        let theme = parse_theme_file(&contents, true, &mut file_errors);
+        errors.extend(file_errors.into_iter().map(|e| e.in_theme_file(&file)));
+        return Some(theme);
+    }
+    load_preset(name, errors)
+}
+
+fn load_preset(name: &str, errors: &mut Vec<Diagnostic>) -> Option<Theme> {
+    let (_, preset, highlight) = PRESETS.iter().find(|(preset, ..)| *preset == name)?;
+    let mut theme = parse_theme_file(preset, false, errors);
+    theme.highlight = highlight;
+    Some(theme)
+}
+
+fn parse_theme_file(contents: &str, allow_name: bool, errors: &mut Vec<Diagnostic>) -> Theme {
+    let table = match toml::from_str::<ThemeTable>(contents) {
+        Ok(table) => table,
+        Err(e) => {
+            errors.push(Diagnostic::at(
+                contents,
+                e.span().map_or(0, |span| span.start),
+                e.message().to_string(),
+            ));
+            return Theme::default();
+        }
+    };
+
+    let mut theme = Theme::default();
+    if let Some(name) = table_name(&table, contents, errors) {
+        let base = allow_name
+            .then(|| load_preset(name.get_ref(), errors))
+            .flatten();
+        match base {
+            Some(base) => theme = base,
+            None => errors.push(Diagnostic::at(
+                contents,
+                name.span().start,
+                format!("no built-in theme named '{}'", name.get_ref()),
+            )),
+        }
+    }
+    theme.apply(&table, contents, errors);
+    theme
+}
+
+pub fn parse_color(s: &str) -> Option<Color> {
+    let s = s.trim();
+
+    // Handle hex colors like "#50C8DC" or "50C8DC"
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    // Slicing by byte below would split a multi-byte character
+    if hex.len() == 6 && hex.is_ascii() {
+        if let (Ok(r), Ok(g), Ok(b)) = (
+            u8::from_str_radix(&hex[0..2], 16),
+            u8::from_str_radix(&hex[2..4], 16),
+            u8::from_str_radix(&hex[4..6], 16),
+        ) {
+            return Some(Color::Rgb(r, g, b));
+        }
+    }
+
+    // Handle named colors
+    match s.to_lowercase().as_str() {
+        "default" | "reset" => Some(Color::Reset),
+        "black" => Some(Color::Black),
+        "red" => Some(Color::Red),
+        "green" => Some(Color::Green),
+        "yellow" => Some(Color::Yellow),
+        "blue" => Some(Color::Blue),
+        "magenta" => Some(Color::Magenta),
+        "cyan" => Some(Color::Cyan),
+        "gray" | "grey" => Some(Color::Gray),
+        "darkgray" | "darkgrey" => Some(Color::DarkGray),
+        "lightred" => Some(Color::LightRed),
+        "lightgreen" => Some(Color::LightGreen),
+        "lightyellow" => Some(Color::LightYellow),
+        "lightblue" => Some(Color::LightBlue),
+        "lightmagenta" => Some(Color::LightMagenta),
+        "lightcyan" => Some(Color::LightCyan),
+        "white" => Some(Color::White),
+        _ => None,
+    }
+}
+
+/// Writes a style as the `{ fg, bg, modifiers }` table `StyleValue` reads.
+pub fn style_value(style: Style) -> toml::Value {
+    let mut entry = toml::Table::new();
+    if let Some(fg) = style.fg {
+        entry.insert("fg".to_string(), color_value(fg));
+    }
+    if let Some(bg) = style.bg {
+        entry.insert("bg".to_string(), color_value(bg));
+    }
+    let modifiers: Vec<toml::Value> = style
+        .add_modifier
+        .iter_names()
+        .map(|(name, _)| name.to_lowercase().into())
+        .collect();
+    entry.insert("modifiers".to_string(), modifiers.into());
+    entry.into()
+}
+
+/// Writes a color the way `parse_color` reads it back.
+fn color_value(color: Color) -> toml::Value {
+    match color {
+        Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b).into(),
+        Color::Indexed(index) => i64::from(index).into(),
+        Color::Reset => "default".into(),
+        color => format!("{:?}", color).to_lowercase().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preview::HIGHLIGHT_THEMES;
+
+    #[test]
+    fn presets_name_known_highlight_themes() {
+        let highlights = PRESETS.iter().map(|(_, _, highlight)| highlight);
+        for highlight in highlights.chain([&DEFAULT_HIGHLIGHT]) {
+            assert!(HIGHLIGHT_THEMES.contains(highlight), "{}", highlight);
+        }
+    }
+
+    #[test]
+    fn colors_that_look_hex_but_are_not_ascii_are_rejected() {
+        assert_eq!(parse_color("#50C8DC"), Some(Color::Rgb(80, 200, 220)));
+        assert_eq!(parse_color("#aé€"), None);
+        assert_eq!(parse_color("ééé"), None);
+    }
+}
@@ -0,0 +1,484 @@
+use crate::fileops;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An item in a freedesktop.org trash.
+#[derive(Debug, Clone)]
+pub struct TrashEntry {
+    /// Location of the trashed item inside the trash's `files`
+    pub file: PathBuf,
+    /// The matching `.trashinfo` file inside the trash's `info`
+    pub info: PathBuf,
+    pub original_path: PathBuf,
+    pub deletion_date: String,
+}
+
+/// Topdirs trashed into by this process, listed even where the mounted
+/// filesystems can't be found.
+static USED_TOPDIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A trash directory, holding `files` and `info`.
+struct TrashDir {
+    path: PathBuf,
+    /// The top of the filesystem a trash other than the home trash is on.
+    /// Paths in its `.trashinfo` files are relative to it.
+    topdir: Option<PathBuf>,
+}
+
+impl TrashDir {
+    /// The home trash, which need not exist.
+    fn home() -> io::Result<Self> {
+        let path = dirs::data_dir().map(|p| p.join("Trash")).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no data directory for trash")
+        })?;
+        Ok(Self { path, topdir: None })
+    }
+
+    /// The trash whose `files` directory holds `file`.
+    fn containing(file: &Path) -> Option<Self> {
+        let path = file.parent()?.parent()?;
+        let home = Self::home().ok()?;
+        if path == home.path {
+            return Some(home);
+        }
+        // `$topdir/.Trash-$uid` or `$topdir/.Trash/$uid`
+        let topdir = match path.file_name()?.to_str()?.starts_with(".Trash-") {
+            true => path.parent()?,
+            false => path.parent()?.parent()?,
+        };
+        Some(Self {
+            path: path.to_path_buf(),
+            topdir: Some(topdir.to_path_buf()),
+        })
+    }
+
+    fn files(&self) -> PathBuf {
+        self.path.join("files")
+    }
+
+    fn info(&self) -> PathBuf {
+        self.path.join("info")
+    }
+
+    /// `original_path` as the `.trashinfo` file records it.
+    fn info_path<'a>(&self, original_path: &'a Path) -> &'a Path {
+        self.topdir
+            .as_deref()
+            .and_then(|topdir| original_path.strip_prefix(topdir).ok())
+            .unwrap_or(original_path)
+    }
+}
+
+/// The trash for `path`: the home trash if it is on the same filesystem, or
+/// else one at the top of the filesystem `path` is on, so trashing never
+/// copies. Falls back to the home trash if no such trash can be made.
+fn trash_dir_for(path: &Path) -> io::Result<TrashDir> {
+    let home = TrashDir::home()?;
+    let device = path.symlink_metadata()?.dev();
+    let home_device = home
+        .path
+        .ancestors()
+        .find_map(|dir| dir.metadata().ok())
+        .map(|m| m.dev());
+    let trash = (home_device != Some(device))
+        .then(|| topdir_trash(path, device))
+        .flatten()
+        .unwrap_or(home);
+    fs::create_dir_all(trash.files())?;
+    fs::create_dir_all(trash.info())?;
+    if let Some(topdir) = &trash.topdir {
+        let mut used = USED_TOPDIRS.lock().unwrap();
+        if !used.contains(topdir) {
+            used.push(topdir.clone());
+        }
+    }
+    Ok(trash)
+}
+
+/// `$topdir/.Trash/$uid` if the administrator set up a shared `.Trash`, or
+/// else `$topdir/.Trash-$uid`, where `$topdir` is the mount point of the
+/// filesystem on `device` that `path` is in.
+fn topdir_trash(path: &Path, device: u64) -> Option<TrashDir> {
+    let topdir = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.metadata().is_ok_and(|m| m.dev() == device))
+        .last()?;
+    let (shared, own) = topdir_trash_paths(topdir);
+    let trash = shared
+        .filter(|trash| private_dir(trash).is_ok())
+        .or_else(|| private_dir(&own).ok().map(|()| own))?;
+    Some(TrashDir {
+        path: trash,
+        topdir: Some(topdir.to_path_buf()),
+    })
+}
+
+/// The trashes `topdir` may have: `.Trash/$uid` if `.Trash` can be trusted,
+/// and `.Trash-$uid`.
+fn topdir_trash_paths(topdir: &Path) -> (Option<PathBuf>, PathBuf) {
+    // SAFETY: getuid has no preconditions and cannot fail
+    let uid = unsafe { libc::getuid() };
+    // The spec only trusts a shared .Trash that is a real directory with the sticky bit
+    let shared = topdir.join(".Trash");
+    let shared = shared
+        .symlink_metadata()
+        .is_ok_and(|m| m.is_dir() && m.mode() & 0o1000 != 0)
+        .then(|| shared.join(uid.to_string()));
+    (shared, topdir.join(format!(".Trash-{}", uid)))
+}
+
+/// The home trash and those at the top of every mounted filesystem, as far
+/// as they exist.
+fn all_trash_dirs() -> Vec<TrashDir> {
+    let mut topdirs = mount_points();
+    topdirs.extend(USED_TOPDIRS.lock().unwrap().iter().cloned());
+    topdirs.sort();
+    topdirs.dedup();
+
+    let home = TrashDir::home().ok();
+    let mut trashes = Vec::new();
+    for topdir in topdirs {
+        let (shared, own) = topdir_trash_paths(&topdir);
+        for path in shared.into_iter().chain([own]) {
+            // The home trash may be right at the top of a filesystem too
+            let is_home = home.as_ref().is_some_and(|home| home.path == path);
+            if !is_home && path.join("info").is_dir() {
+                trashes.push(TrashDir {
+                    path,
+                    topdir: Some(topdir.clone()),
+                });
+            }
+        }
+    }
+    trashes.extend(home);
+    trashes
+}
+
+/// Where filesystems are mounted, leaving out automounts, which looking
+/// into would mount.
+#[cfg(target_os = "linux")]
+fn mount_points() -> Vec<PathBuf> {
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+        return Vec::new();
+    };
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let mount_point = fields.nth(1)?;
+            (fields.next()? != "autofs").then(|| unescape_mount_point(mount_point))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_points() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// Undoes the octal escapes `/proc/self/mounts` uses for spaces and the like.
+#[cfg(target_os = "linux")]
+fn unescape_mount_point(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'\\')
+            .then(|| bytes.get(i + 1..i + 4))
+            .flatten()
+            .and_then(|octal| std::str::from_utf8(octal).ok())
+            .and_then(|octal| u8::from_str_radix(octal, 8).ok());
+        match escaped {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(unescaped))
+}
+
+/// Creates `dir` readable by its owner only, or checks that it already is a
+/// directory and not a link to one.
+fn private_dir(dir: &Path) -> io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            if dir.symlink_metadata()?.is_dir() {
+                Ok(())
+            } else {
+                Err(e)
+            }
+        }
+        result => result,
+    }
+}
+
+/// Moves `path` into the trash, following the XDG trash specification.
+pub fn move_to_trash(path: &Path) -> io::Result<TrashEntry> {
+    let original_path = std::path::absolute(path)?;
+    let trash = trash_dir_for(&original_path)?;
+    trash_into(&original_path, &trash)
+}
+
+fn trash_into(original_path: &Path, trash: &TrashDir) -> io::Result<TrashEntry> {
+    let (files_dir, info_dir) = (trash.files(), trash.info());
+    let name = original_path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "cannot trash this path"))?
+        .to_os_string();
+    let deletion_date = format_deletion_date(SystemTime::now());
+
+    // Reserve a name by creating its .trashinfo exclusively, as the spec requires
+    for n in 1.. {
+        let mut trash_name = name.clone();
+        if n > 1 {
+            trash_name.push(format!(".{}", n));
+        }
+        let mut info_name = trash_name.clone();
+        info_name.push(".trashinfo");
+        let info = info_dir.join(&info_name);
+        let file = files_dir.join(&trash_name);
+
+        let mut info_file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info)
+        {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        if file.symlink_metadata().is_ok() {
+            let _ = fs::remove_file(&info);
+            continue;
+        }
+
+        let write_result = write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(trash.info_path(original_path)),
+            deletion_date
+        );
+        if let Err(e) = write_result.and_then(|_| fileops::move_path(original_path, &file)) {
+            let _ = fs::remove_file(&info);
+            return Err(e);
+        }
+
+        return Ok(TrashEntry {
+            file,
+            info,
+            original_path: original_path.to_path_buf(),
+            deletion_date,
+        });
+    }
+    unreachable!("unbounded range always yields a free name")
+}
+
+/// Moves a trashed item back to where it came from.
+pub fn restore(entry: &TrashEntry) -> io::Result<PathBuf> {
+    if let Some(parent) = entry.original_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fileops::move_path(&entry.file, &entry.original_path)?;
+    fs::remove_file(&entry.info)?;
+    Ok(entry.original_path.clone())
+}
+
+/// Lists the home trash and the trashes at the top of mounted filesystems,
+/// most recently deleted first.
+pub fn list() -> Vec<TrashEntry> {
+    list_in(&all_trash_dirs())
+}
+
+fn list_in(trashes: &[TrashDir]) -> Vec<TrashEntry> {
+    let mut entries: Vec<TrashEntry> = trashes
+        .iter()
+        .filter_map(|trash| Some((trash, fs::read_dir(trash.info()).ok()?)))
+        .flat_map(|(trash, read_dir)| {
+            read_dir.filter_map(|e| e.ok()).filter_map(move |e| {
+                let info = e.path();
+                let name = info.file_stem()?;
+                parse_info(trash, &info, trash.files().join(name))
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| b.deletion_date.cmp(&a.deletion_date));
+    entries
+}
+
+/// Finds the entry for an item inside a trash's `files` directory.
+pub fn entry_for(file: &Path) -> Option<TrashEntry> {
+    let trash = TrashDir::containing(file)?;
+    let mut info_name = file.file_name()?.to_os_string();
+    info_name.push(".trashinfo");
+    parse_info(&trash, &trash.info().join(info_name), file.to_path_buf())
+}
+
+fn parse_info(trash: &TrashDir, info: &Path, file: PathBuf) -> Option<TrashEntry> {
+    if info.extension()? != "trashinfo" || file.symlink_metadata().is_err() {
+        return None;
+    }
+    let contents = fs::read_to_string(info).ok()?;
+
+    let mut original_path = None;
+    let mut deletion_date = String::new();
+    for line in contents.lines() {
+        if let Some(value) = line.strip_prefix("Path=") {
+            let path = decode_path(value);
+            original_path = Some(match &trash.topdir {
+                Some(topdir) => topdir.join(path),
+                None => path,
+            });
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deletion_date = value.to_string();
+        }
+    }
+
+    Some(TrashEntry {
+        file,
+        info: info.to_path_buf(),
+        original_path: original_path?,
+        deletion_date,
+    })
+}
+
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn decode_path(value: &str) -> PathBuf {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(decoded))
+}
+
+/// Formats as `YYYY-MM-DDThh:mm:ss` in local time, as the spec asks.
+fn format_deletion_date(time: SystemTime) -> String {
+    let utc = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let secs = utc.saturating_add_signed(utc_offset(utc));
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// How far local time is ahead of UTC at `secs` past the epoch, in seconds.
+fn utc_offset(secs: u64) -> i64 {
+    let Ok(time) = libc::time_t::try_from(secs) else {
+        return 0;
+    };
+    let mut tm = std::mem::MaybeUninit::<libc::tm>::zeroed();
+    // SAFETY: both pointers are valid for the call, and localtime_r only
+    // writes to `tm`
+    let result = unsafe { libc::localtime_r(&time, tm.as_mut_ptr()) };
+    if result.is_null() {
+        return 0;
+    }
+    // SAFETY: localtime_r succeeded, so it filled in `tm`
+    unsafe { tm.assume_init() }.tm_gmtoff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topdir_trashes_record_paths_relative_to_the_topdir() {
+        let dir = tempfile::tempdir().unwrap();
+        let topdir = dir.path().canonicalize().unwrap();
+        fs::create_dir(topdir.join("sub")).unwrap();
+        fs::write(topdir.join("sub/file"), "contents").unwrap();
+        // SAFETY: getuid has no preconditions and cannot fail
+        let uid = unsafe { libc::getuid() };
+        let trash = TrashDir {
+            path: topdir.join(format!(".Trash-{}", uid)),
+            topdir: Some(topdir.clone()),
+        };
+        fs::create_dir_all(trash.files()).unwrap();
+        fs::create_dir_all(trash.info()).unwrap();
+
+        let entry = trash_into(&topdir.join("sub/file"), &trash).unwrap();
+        let info = fs::read_to_string(&entry.info).unwrap();
+        assert!(info.contains("\nPath=sub/file\n"), "{}", info);
+
+        let listed = list_in(&[trash]);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].original_path, topdir.join("sub/file"));
+        let found = entry_for(&entry.file).unwrap();
+        assert_eq!(found.original_path, topdir.join("sub/file"));
+        assert_eq!(restore(&found).unwrap(), topdir.join("sub/file"));
+        assert_eq!(
+            fs::read_to_string(topdir.join("sub/file")).unwrap(),
+            "contents"
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn mount_points_are_unescaped() {
+        assert_eq!(
+            unescape_mount_point("/media/My\\040Drive"),
+            Path::new("/media/My Drive")
+        );
+        assert_eq!(unescape_mount_point("/mnt/a\\b"), Path::new("/mnt/a\\b"));
+    }
+}
@@ -1,26 +1,32 @@
-use crate::icons;
-use crate::size;
-use crate::state::Bookmark;
-use std::collections::{HashMap, HashSet, VecDeque};
+use crate::icons::Icons;
+use crate::lscolors::LsColors;
+use crate::size::{self, DirSize, SizeDisplay, SizeState};
+use crate::state::{Bookmark, RecentDir};
+use crate::theme::Theme;
+use crate::trash::TrashEntry;
+use ratatui::style::Style;
+use ratatui::text::{Line, Span, Text};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use tui_tree_widget::TreeItem;
 
-fn is_hidden(path: &Path) -> bool {
+pub fn is_hidden(path: &Path) -> bool {
     path.file_name()
         .and_then(|n| n.to_str())
         .map(|n| n.starts_with('.'))
         .unwrap_or(false)
 }
 
-pub fn format_entry_name(
+/// The icon and name of a tree row in `name_style`, then its star and size.
+fn format_entry_name(
     path: &Path,
     is_expanded: bool,
     is_starred: bool,
-    dir_sizes: Option<&HashMap<PathBuf, Option<u64>>>,
-) -> String {
-    let icon = icons::get_icon(path, is_expanded);
+    options: &TreeOptions,
+    name_style: Style,
+) -> Line<'static> {
     let name = path
         .file_name()
         .map(|n| n.to_string_lossy().into_owned())
@@ -29,18 +35,26 @@ pub fn format_entry_name(
     let star = if is_starred { " ★" } else { "" };
 
     let size_str = if is_expanded && path.is_dir() {
-        dir_sizes
+        options
+            .dir_sizes
             .and_then(|sizes| sizes.get(&path.to_path_buf()))
             .map(|size| match size {
-                Some(bytes) => format!(" [{}]", size::format_size(*bytes)),
-                None => " [...]".to_string(),
+                SizeState::Measured(size) => format!(" [{}]", options.sizes.format(*size)),
+                // Still the size from the cache, while it is measured again
+                SizeState::Stale(size) => format!(" [~{}]", options.sizes.format(*size)),
+                SizeState::Pending => " [...]".to_string(),
+                SizeState::Failed(kind) => format!(" [{}]", format_error(*kind)),
             })
             .unwrap_or_default()
     } else {
         String::new()
     };
 
-    format!("{} {}{}{}", icon, name, star, size_str)
+    Line::from(vec![
+        Span::styled(options.icons.label(path, is_expanded, &name), name_style),
+        Span::raw(star),
+        Span::raw(size_str),
+    ])
 }
 
 fn sort_entries(entries: &mut [PathBuf]) {
@@ -59,89 +73,130 @@ fn sort_entries(entries: &mut [PathBuf]) {
     });
 }
 
+/// Everything besides the filesystem itself that decides how tree rows look.
+pub struct TreeOptions<'a> {
+    pub expanded_dirs: &'a HashSet<PathBuf>,
+    pub starred_dirs: &'a HashSet<PathBuf>,
+    pub marked: &'a HashSet<PathBuf>,
+    pub show_hidden: bool,
+    pub dir_sizes: Option<&'a HashMap<PathBuf, SizeState>>,
+    pub theme: &'a Theme,
+    pub colors: &'a LsColors,
+    pub icons: &'a Icons,
+    pub sizes: SizeDisplay,
+}
+
 pub fn build_tree_item(
     path: &Path,
-    expanded_dirs: &HashSet<PathBuf>,
-    starred_dirs: &HashSet<PathBuf>,
-    show_hidden: bool,
-    dir_sizes: Option<&HashMap<PathBuf, Option<u64>>>,
+    options: &TreeOptions,
 ) -> io::Result<TreeItem<'static, PathBuf>> {
-    let is_expanded = expanded_dirs.contains(path);
-    let is_starred = starred_dirs.contains(path);
-    let name = format_entry_name(path, is_expanded, is_starred, dir_sizes);
+    let is_expanded = options.expanded_dirs.contains(path);
+    let is_starred = options.starred_dirs.contains(path);
+    // Marks stand out over the file's own color
+    let name_style = if options.marked.contains(path) {
+        options.theme.marked_entry
+    } else {
+        options.colors.style(path)
+    };
+    let mut name = format_entry_name(path, is_expanded, is_starred, options, name_style);
 
     if path.is_dir() && is_expanded {
-        match load_children(path, expanded_dirs, starred_dirs, show_hidden, dir_sizes) {
-            Ok(children) => TreeItem::new(path.to_path_buf(), name, children)
+        match load_children(path, options) {
+            Ok(children) => TreeItem::new(path.to_path_buf(), Text::from(name), children)
                 .map_err(|e| io::Error::other(format!("Tree item error: {}", e))),
             Err(e) => {
-                let error_name = format!("{} [{}]", name, format_error(&e));
-                Ok(TreeItem::new_leaf(path.to_path_buf(), error_name))
+                name.push_span(format!(" [{}]", format_error(e.kind())));
+                Ok(TreeItem::new_leaf(path.to_path_buf(), Text::from(name)))
             }
         }
     } else {
-        Ok(TreeItem::new_leaf(path.to_path_buf(), name))
+        Ok(TreeItem::new_leaf(path.to_path_buf(), Text::from(name)))
     }
 }
 
-fn load_children(
-    dir: &Path,
-    expanded_dirs: &HashSet<PathBuf>,
-    starred_dirs: &HashSet<PathBuf>,
-    show_hidden: bool,
-    dir_sizes: Option<&HashMap<PathBuf, Option<u64>>>,
-) -> io::Result<Vec<TreeItem<'static, PathBuf>>> {
+fn load_children(dir: &Path, options: &TreeOptions) -> io::Result<Vec<TreeItem<'static, PathBuf>>> {
     let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
         .filter_map(|e| e.ok())
         .map(|e| e.path())
-        .filter(|p| show_hidden || !is_hidden(p))
+        .filter(|p| options.show_hidden || !is_hidden(p))
         .collect();
 
     sort_entries(&mut entries);
 
     let children: Vec<TreeItem<'static, PathBuf>> = entries
         .iter()
-        .filter_map(|p| {
-            build_tree_item(p, expanded_dirs, starred_dirs, show_hidden, dir_sizes).ok()
-        })
+        .filter_map(|p| build_tree_item(p, options).ok())
         .collect();
 
     Ok(children)
 }
 
-fn format_error(e: &io::Error) -> &'static str {
-    match e.kind() {
+fn format_error(kind: io::ErrorKind) -> &'static str {
+    match kind {
         io::ErrorKind::PermissionDenied => "Permission denied",
         io::ErrorKind::NotFound => "Not found",
+        io::ErrorKind::CrossesDevices => "Other filesystem",
+        io::ErrorKind::Unsupported => "Virtual filesystem",
         _ => "Error",
     }
 }
 
 pub fn build_tree(
     root: &Path,
-    expanded_dirs: &HashSet<PathBuf>,
-    starred_dirs: &HashSet<PathBuf>,
-    show_hidden: bool,
-    dir_sizes: Option<&HashMap<PathBuf, Option<u64>>>,
+    options: &TreeOptions,
 ) -> io::Result<Vec<TreeItem<'static, PathBuf>>> {
     let mut entries: Vec<PathBuf> = fs::read_dir(root)?
         .filter_map(|e| e.ok())
         .map(|e| e.path())
-        .filter(|p| show_hidden || !is_hidden(p))
+        .filter(|p| options.show_hidden || !is_hidden(p))
         .collect();
 
     sort_entries(&mut entries);
 
     let items: Vec<TreeItem<'static, PathBuf>> = entries
         .iter()
-        .filter_map(|p| {
-            build_tree_item(p, expanded_dirs, starred_dirs, show_hidden, dir_sizes).ok()
-        })
+        .filter_map(|p| build_tree_item(p, options).ok())
         .collect();
 
     Ok(items)
 }
 
+/// Rebuilds the already-loaded item for `dir` in place, leaving the rest of the tree untouched.
+/// Returns false if `dir` is not part of `items`.
+pub fn rebuild_subtree(
+    items: &mut [TreeItem<'static, PathBuf>],
+    dir: &Path,
+    options: &TreeOptions,
+) -> bool {
+    let Some(mut current) = items
+        .iter_mut()
+        .find(|item| dir.starts_with(item.identifier()))
+    else {
+        return false;
+    };
+    while current.identifier() != dir {
+        let Some(index) = current
+            .children()
+            .iter()
+            .position(|child| dir.starts_with(child.identifier()))
+        else {
+            return false;
+        };
+        current = match current.child_mut(index) {
+            Some(child) => child,
+            None => return false,
+        };
+    }
+
+    match build_tree_item(dir, options) {
+        Ok(item) => {
+            *current = item;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
 pub fn build_starred_list(
     starred_dirs: &HashSet<PathBuf>,
 ) -> io::Result<Vec<TreeItem<'static, PathBuf>>> {
@@ -186,16 +241,254 @@ pub fn build_bookmarks_list(bookmarks: &[Bookmark]) -> io::Result<Vec<TreeItem<'
 }
 
 pub fn build_recent_list(
-    recent_dirs: &VecDeque<PathBuf>,
+    recent_dirs: &[&RecentDir],
 ) -> io::Result<Vec<TreeItem<'static, PathBuf>>> {
     let items: Vec<TreeItem<'static, PathBuf>> = recent_dirs
         .iter()
-        .filter(|p| p.exists())
-        .map(|p| {
-            let name = format!("⏱ {}", p.display());
-            TreeItem::new_leaf(p.clone(), name)
+        .filter(|r| r.path.exists())
+        .map(|r| {
+            let name = format!("⏱ {}", r.path.display());
+            TreeItem::new_leaf(r.path.clone(), name)
+        })
+        .collect();
+
+    Ok(items)
+}
+
+pub fn build_trash_list(entries: &[TrashEntry]) -> io::Result<Vec<TreeItem<'static, PathBuf>>> {
+    let items: Vec<TreeItem<'static, PathBuf>> = entries
+        .iter()
+        .map(|e| {
+            let name = format!(
+                "🗑 {} ({})",
+                e.original_path.display(),
+                e.deletion_date.replace('T', " ")
+            );
+            TreeItem::new_leaf(e.file.clone(), name)
         })
         .collect();
 
     Ok(items)
 }
+
+/// The size of a directory's entries so far, while some are still being
+/// measured.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiskUsage {
+    pub total: DirSize,
+    /// Subdirectories whose size is not known yet
+    pub pending: usize,
+}
+
+const USAGE_BAR_WIDTH: usize = 10;
+
+/// An entry of a directory listed by size. A subdirectory takes its size
+/// from `TreeOptions::dir_sizes` each time the list is built, so the list can
+/// be rebuilt as sizes come in without reading the directory again.
+#[derive(Debug, Clone)]
+pub struct UsageEntry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// The size of a file, empty for anything but regular files
+    file_size: DirSize,
+}
+
+/// Reads the entries of `dir` for `build_disk_usage_list`. Symlinks are not
+/// followed.
+pub fn read_usage_entries(dir: &Path) -> io::Result<Vec<UsageEntry>> {
+    Ok(fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let metadata = e.metadata().ok();
+            let is_dir = metadata.as_ref().is_some_and(|m| m.is_dir());
+            // Only regular files count, as in the sizes `SizeWorker` measures
+            let file_size = match metadata {
+                Some(m) if m.is_file() => DirSize::of_file(&m),
+                _ => DirSize::default(),
+            };
+            UsageEntry {
+                path: e.path(),
+                is_dir,
+                file_size,
+            }
+        })
+        .collect())
+}
+
+/// The entries, largest first, each with its size, share of the directory,
+/// a bar for that share and its file count. Hidden entries always count, as
+/// they take up space all the same.
+pub fn build_disk_usage_list(
+    entries: &[UsageEntry],
+    options: &TreeOptions,
+) -> (Vec<TreeItem<'static, PathBuf>>, DiskUsage) {
+    let mut entries: Vec<(PathBuf, bool, SizeState)> = entries
+        .iter()
+        .map(|entry| {
+            let size = if entry.is_dir {
+                options
+                    .dir_sizes
+                    .and_then(|sizes| sizes.get(&entry.path).copied())
+                    .unwrap_or(SizeState::Pending)
+            } else {
+                SizeState::Measured(entry.file_size)
+            };
+            (entry.path.clone(), entry.is_dir, size)
+        })
+        .collect();
+
+    // Unmeasured directories go last until their size comes in, and ones
+    // that can't be measured count as empty
+    let bytes = |size: &SizeState| match size {
+        SizeState::Measured(size) | SizeState::Stale(size) => Some(options.sizes.bytes(*size)),
+        SizeState::Failed(_) => Some(0),
+        SizeState::Pending => None,
+    };
+    entries.sort_by(|(a_path, _, a), (b_path, _, b)| {
+        bytes(b)
+            .cmp(&bytes(a))
+            .then_with(|| a_path.file_name().cmp(&b_path.file_name()))
+    });
+
+    let mut usage = DiskUsage::default();
+    for (_, _, size) in &entries {
+        if let Some(size) = size.size() {
+            usage.total += size;
+        }
+        if size.is_pending() {
+            usage.pending += 1;
+        }
+    }
+
+    let items = entries
+        .into_iter()
+        .map(|(path, is_dir, state)| {
+            let columns = match state {
+                SizeState::Measured(size) | SizeState::Stale(size) => {
+                    let share = match options.sizes.bytes(usage.total) {
+                        0 => 0.0,
+                        total => options.sizes.bytes(size) as f64 / total as f64,
+                    };
+                    let filled = (share * USAGE_BAR_WIDTH as f64).round() as usize;
+                    format!(
+                        "{:>9} {:>5.1}% [{:<width$}] {:>8} ",
+                        match state {
+                            SizeState::Stale(_) => format!("~{}", options.sizes.format(size)),
+                            _ => options.sizes.format(size),
+                        },
+                        share * 100.0,
+                        "#".repeat(filled),
+                        if is_dir {
+                            size::format_count(size.files)
+                        } else {
+                            String::new()
+                        },
+                        width = USAGE_BAR_WIDTH
+                    )
+                }
+                SizeState::Pending | SizeState::Failed(_) => format!(
+                    "{:>9} {:>6} [{:<width$}] {:>8} ",
+                    if state == SizeState::Pending {
+                        "…"
+                    } else {
+                        "!"
+                    },
+                    "",
+                    "",
+                    "",
+                    width = USAGE_BAR_WIDTH
+                ),
+            };
+            let name_style = if options.marked.contains(&path) {
+                options.theme.marked_entry
+            } else {
+                options.colors.style(&path)
+            };
+            let mut name = format_entry_name(&path, false, false, options, name_style);
+            name.spans.insert(0, Span::raw(columns));
+            if let SizeState::Failed(kind) = state {
+                name.push_span(format!(" [{}]", format_error(kind)));
+            }
+            TreeItem::new_leaf(path, Text::from(name))
+        })
+        .collect();
+
+    (items, usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icons::IconMode;
+    use ratatui::buffer::Buffer;
+    use ratatui::layout::Rect;
+    use ratatui::widgets::StatefulWidget;
+    use tui_tree_widget::{Tree, TreeState};
+
+    /// The rows as the tree widget draws them.
+    fn render(items: &[TreeItem<'static, PathBuf>]) -> Vec<String> {
+        let area = Rect::new(0, 0, 60, items.len() as u16);
+        let mut buffer = Buffer::empty(area);
+        let tree = Tree::new(items).unwrap();
+        StatefulWidget::render(tree, area, &mut buffer, &mut TreeState::default());
+        (0..area.height)
+            .map(|y| {
+                let row: String = (0..area.width).map(|x| buffer[(x, y)].symbol()).collect();
+                row.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn disk_usage_list_sorts_by_size_with_shares_and_pending_last() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("small"), vec![0; 100]).unwrap();
+        fs::write(dir.path().join("big"), vec![0; 3000]).unwrap();
+        fs::create_dir(dir.path().join("measured")).unwrap();
+        fs::create_dir(dir.path().join("pending")).unwrap();
+        let measured = DirSize {
+            apparent: 1000,
+            allocated: 4096,
+            files: 2,
+            dirs: 1,
+        };
+        let dir_sizes =
+            HashMap::from([(dir.path().join("measured"), SizeState::Measured(measured))]);
+        let none = HashSet::new();
+        let options = TreeOptions {
+            expanded_dirs: &none,
+            starred_dirs: &none,
+            marked: &none,
+            show_hidden: false,
+            dir_sizes: Some(&dir_sizes),
+            theme: &Theme::default(),
+            colors: &LsColors::parse(""),
+            icons: &Icons::new(IconMode::None),
+            sizes: SizeDisplay {
+                apparent: true,
+                ..SizeDisplay::default()
+            },
+        };
+
+        let entries = read_usage_entries(dir.path()).unwrap();
+        let (items, usage) = build_disk_usage_list(&entries, &options);
+
+        let order: Vec<&PathBuf> = items.iter().map(|item| item.identifier()).collect();
+        let names = ["big", "measured", "small", "pending"].map(|n| dir.path().join(n));
+        assert_eq!(order, names.iter().collect::<Vec<_>>());
+        assert_eq!(usage.pending, 1);
+        assert_eq!(usage.total.apparent, 4100);
+        assert_eq!(usage.total.files, 4);
+
+        let rows = render(&items);
+        assert_eq!(
+            rows,
+            [
+                "       2.9K  73.2% [#######   ]          big",
+                "      1000B  24.4% [##        ]        2 measured",
+                "       100B   2.4% [          ]          small",
+                "          …        [          ]          pending",
+            ],
+        );
+    }
+}
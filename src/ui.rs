@@ -1,21 +1,33 @@
 use crate::app::{App, InputMode, ViewMode};
-use crate::config::Theme;
+use crate::keymap::{self, Action, Keymap};
+use crate::preview::PreviewContents;
+use crate::size;
+use crate::theme::Theme;
 use ratatui::{
     layout::{Constraint, Layout, Rect},
-    style::{Color, Modifier, Style},
-    text::{Line, Span},
+    text::{Line, Span, Text},
     widgets::{Block, Borders, Clear, Paragraph, Wrap},
     Frame,
 };
 use std::fs;
-use std::io::{BufRead, BufReader};
 use tui_tree_widget::Tree;
 
 pub fn render(frame: &mut Frame, app: &mut App) {
-    let chunks = Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).split(frame.area());
+    let banner_height = u16::from(app.config_warning.is_some());
+    let chunks = Layout::vertical([
+        Constraint::Length(banner_height),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .split(frame.area());
+
+    let main_area = chunks[1];
+    let footer_area = chunks[2];
 
-    let main_area = chunks[0];
-    let footer_area = chunks[1];
+    if let Some(warning) = &app.config_warning {
+        let banner = Paragraph::new(format!(" ⚠ {}", warning)).style(app.config.theme.banner);
+        frame.render_widget(banner, chunks[0]);
+    }
 
     if app.show_preview {
         let split = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
@@ -43,11 +55,16 @@ pub fn render(frame: &mut Frame, app: &mut App) {
     }
 
     if app.show_help {
-        render_help(frame, &app.config.theme);
+        render_help(frame, &app.config.theme, &app.config.keys);
     }
 
-    if app.input_mode == InputMode::BookmarkLabel {
-        render_bookmark_input(frame, app);
+    match app.input_mode {
+        InputMode::BookmarkLabel => render_bookmark_input(frame, app),
+        InputMode::CreateFile | InputMode::CreateDir | InputMode::Rename => {
+            render_file_op_input(frame, app)
+        }
+        InputMode::ConfirmDelete => render_confirm_delete(frame, app),
+        InputMode::Normal | InputMode::Search | InputMode::Visual => {}
     }
 }
 
@@ -59,206 +76,370 @@ fn render_tree(frame: &mut Frame, app: &mut App, area: Rect) {
         ViewMode::Starred => " ★ Starred ".to_string(),
         ViewMode::Bookmarks => " 📌 Bookmarks ".to_string(),
         ViewMode::Recent => " ⏱ Recent ".to_string(),
+        ViewMode::Trash => " 🗑 Trash ".to_string(),
+        ViewMode::DiskUsage => {
+            let usage = &app.du_usage;
+            let scanning = match usage.pending {
+                0 => String::new(),
+                n => format!(", scanning {}…", n),
+            };
+            let kind = if app.size_display.apparent {
+                "apparent"
+            } else {
+                "on disk"
+            };
+            format!(
+                " {}  {} {} in {} files, {} dirs{} ",
+                app.du_dir.display(),
+                app.size_display.format(usage.total),
+                kind,
+                size::format_count(usage.total.files),
+                size::format_count(usage.total.dirs),
+                scanning
+            )
+        }
     };
 
-    let title_style = match app.view_mode {
-        ViewMode::Tree => Style::default()
-            .fg(theme.border)
-            .add_modifier(Modifier::BOLD),
-        ViewMode::Starred | ViewMode::Bookmarks | ViewMode::Recent => Style::default()
-            .fg(theme.starred)
-            .add_modifier(Modifier::BOLD),
-    };
-
-    let border_color = match app.view_mode {
-        ViewMode::Tree => theme.border,
-        ViewMode::Starred | ViewMode::Bookmarks | ViewMode::Recent => theme.starred,
+    let (title_style, border_style) = match app.view_mode {
+        ViewMode::Tree => (theme.tree_title, theme.tree_border),
+        ViewMode::Starred
+        | ViewMode::Bookmarks
+        | ViewMode::Recent
+        | ViewMode::Trash
+        | ViewMode::DiskUsage => (theme.list_title, theme.list_border),
     };
 
     let block = tui_tree_widget::Block::bordered()
         .title(title)
         .title_style(title_style)
-        .border_style(Style::default().fg(border_color));
-
-    let highlight_style = Style::default()
-        .bg(theme.highlight_bg)
-        .fg(theme.text)
-        .add_modifier(Modifier::BOLD);
+        .border_style(border_style);
 
     let tree = Tree::new(&app.items)
         .expect("Tree items should be valid")
         .block(block)
-        .highlight_style(highlight_style)
+        .style(theme.entry)
+        .highlight_style(theme.selection)
         .highlight_symbol("▸ ");
 
     frame.render_stateful_widget(tree, area, &mut app.tree_state);
 }
 
 fn render_footer(frame: &mut Frame, app: &App, area: Rect) {
-    let mut keys = match app.view_mode {
+    let theme = &app.config.theme;
+
+    if let Some(message) = &app.status_message {
+        let footer = Paragraph::new(format!(" {}", message)).style(theme.status);
+        frame.render_widget(footer, area);
+        return;
+    }
+
+    let enter = if app.picker.is_some() { "pick" } else { "cd" };
+    let nav: &[Action] = &[Action::Down, Action::Up];
+    let bindings: Vec<(&[Action], &str)> = match app.view_mode {
         ViewMode::Tree => vec![
-            ("↑↓/jk", "nav"),
-            ("←→/hl", "tree"),
-            ("Space", "toggle"),
-            ("Enter", "cd"),
-            ("s", "star"),
-            ("b", "mark"),
-            ("/", "search"),
-            ("p", "preview"),
-            (".", "hidden"),
-            ("B", "marks"),
-            ("r", "recent"),
-            ("?", "help"),
-            ("q", "quit"),
+            (nav, "nav"),
+            (&[Action::Collapse, Action::Expand], "tree"),
+            (&[Action::Toggle], "toggle"),
+            (&[Action::Open], enter),
+            (&[Action::Star], "star"),
+            (&[Action::Bookmark], "mark"),
+            (&[Action::NewFile, Action::NewDir], "new"),
+            (&[Action::Rename], "rename"),
+            (
+                &[Action::Copy, Action::Cut, Action::Paste],
+                "copy/cut/paste",
+            ),
+            (&[Action::Trash], "trash"),
+            (&[Action::Undo], "undo"),
+            (&[Action::Mark, Action::Visual], "mark"),
+            (&[Action::Search], "search"),
+            (&[Action::TogglePreview], "preview"),
+            (&[Action::ToggleHidden], "hidden"),
+            (&[Action::BookmarksView], "marks"),
+            (&[Action::RecentView], "recent"),
+            (&[Action::Help], "help"),
+            (&[Action::Quit], "quit"),
         ],
         ViewMode::Starred => vec![
-            ("↑↓/jk", "navigate"),
-            ("Enter", "cd"),
-            ("s", "unstar"),
-            ("S", "back"),
-            ("?", "help"),
-            ("q", "quit"),
+            (nav, "navigate"),
+            (&[Action::Open], "cd"),
+            (&[Action::Star], "unstar"),
+            (&[Action::StarredView], "back"),
+            (&[Action::Help], "help"),
+            (&[Action::Quit], "quit"),
         ],
         ViewMode::Bookmarks => vec![
-            ("↑↓/jk", "navigate"),
-            ("Enter", "cd"),
-            ("B", "back"),
-            ("?", "help"),
-            ("q", "quit"),
+            (nav, "navigate"),
+            (&[Action::Open], "cd"),
+            (&[Action::BookmarksView], "back"),
+            (&[Action::Help], "help"),
+            (&[Action::Quit], "quit"),
         ],
         ViewMode::Recent => vec![
-            ("↑↓/jk", "navigate"),
-            ("Enter", "cd"),
-            ("r", "back"),
-            ("?", "help"),
-            ("q", "quit"),
+            (nav, "navigate"),
+            (&[Action::Open], "cd"),
+            (&[Action::RecentView], "back"),
+            (&[Action::Help], "help"),
+            (&[Action::Quit], "quit"),
+        ],
+        ViewMode::Trash => vec![
+            (nav, "navigate"),
+            (&[Action::Open], "restore"),
+            (&[Action::Undo], "undo"),
+            (&[Action::TrashView], "back"),
+            (&[Action::Help], "help"),
+            (&[Action::Quit], "quit"),
+        ],
+        ViewMode::DiskUsage => vec![
+            (nav, "navigate"),
+            (&[Action::Open], "open"),
+            (&[Action::Collapse], "up"),
+            (&[Action::Trash], "trash"),
+            (&[Action::Undo], "undo"),
+            (&[Action::Mark], "mark"),
+            (&[Action::ToggleApparentSize], "apparent"),
+            (&[Action::DiskUsageView], "back"),
+            (&[Action::Help], "help"),
+            (&[Action::Quit], "quit"),
         ],
     };
+    // Actions without a key are left out
+    let mut keys: Vec<(String, &str)> = bindings
+        .into_iter()
+        .filter_map(|(actions, desc)| Some((app.config.keys.short(actions)?, desc)))
+        .collect();
 
     if app.persistent_state.show_hidden && app.view_mode == ViewMode::Tree {
-        keys.insert(0, ("●", "hidden"));
+        keys.insert(0, ("●".to_string(), "hidden"));
+    }
+
+    let marked_count = format!("{} marked", app.marked.len());
+    if !app.marked.is_empty() {
+        if let Some(clear) = app.config.keys.short(&[Action::ClearMarks]) {
+            keys.insert(0, (clear, "clear"));
+        }
+        keys.insert(0, ("●".to_string(), &marked_count));
+    }
+    if app.input_mode == InputMode::Visual {
+        keys.insert(0, ("-- VISUAL --".to_string(), ""));
+    }
+    if !app.pending_keys.is_empty() {
+        keys.insert(0, (keymap::pending_display(&app.pending_keys), "…"));
     }
 
-    let theme = &app.config.theme;
     let spans: Vec<Span> = keys
         .iter()
         .enumerate()
         .flat_map(|(i, (key, desc))| {
             let mut v = vec![
-                Span::styled(
-                    *key,
-                    Style::default()
-                        .fg(theme.border)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::styled(format!(" {} ", desc), Style::default().fg(theme.dim)),
+                Span::styled(key.as_str(), theme.footer_key),
+                Span::styled(format!(" {} ", desc), theme.footer_text),
             ];
             if i < keys.len() - 1 {
-                v.push(Span::styled("│ ", Style::default().fg(Color::DarkGray)));
+                v.push(Span::styled("│ ", theme.footer_separator));
             }
             v
         })
         .collect();
 
-    let footer =
-        Paragraph::new(Line::from(spans)).style(Style::default().bg(Color::Rgb(20, 20, 30)));
+    let footer = Paragraph::new(Line::from(spans)).style(theme.footer);
 
     frame.render_widget(footer, area);
 }
 
-fn render_help(frame: &mut Frame, theme: &Theme) {
+fn render_help(frame: &mut Frame, theme: &Theme, keys: &Keymap) {
     let area = frame.area();
 
-    // Center the help popup
-    let popup_width = 60.min(area.width.saturating_sub(4));
-    let popup_height = 22.min(area.height.saturating_sub(4));
-    let popup_area = Rect {
-        x: (area.width - popup_width) / 2,
-        y: (area.height - popup_height) / 2,
-        width: popup_width,
-        height: popup_height,
-    };
-
-    frame.render_widget(Clear, popup_area);
-
     let help_text = vec![
         Line::from(vec![
-            Span::styled(
-                "  treenav",
-                Style::default()
-                    .fg(theme.border)
-                    .add_modifier(Modifier::BOLD),
-            ),
-            Span::styled(
-                " - Terminal Directory Navigator",
-                Style::default().fg(theme.dim),
-            ),
+            Span::styled("  treenav", theme.popup_title),
+            Span::styled(" - Terminal Directory Navigator", theme.hint),
         ]),
         Line::from(""),
-        Line::styled(
-            "  NAVIGATION",
-            Style::default()
-                .fg(theme.starred)
-                .add_modifier(Modifier::BOLD),
+        Line::styled("  NAVIGATION", theme.help_heading),
+        help_line(keys, &[Action::Up], "Move up", theme),
+        help_line(keys, &[Action::Down], "Move down", theme),
+        help_line(
+            keys,
+            &[Action::Collapse],
+            "Collapse directory / go to parent",
+            theme,
+        ),
+        help_line(keys, &[Action::Expand], "Expand directory", theme),
+        help_line(keys, &[Action::Toggle], "Toggle expand/collapse", theme),
+        help_line(keys, &[Action::First], "Go to first item", theme),
+        help_line(keys, &[Action::Last], "Go to last item", theme),
+        help_line(
+            keys,
+            &[Action::PageUp, Action::PageDown],
+            "Page up/down",
+            theme,
+        ),
+        help_line(
+            keys,
+            &[Action::HalfPageUp, Action::HalfPageDown],
+            "Half page up/down",
+            theme,
         ),
-        help_line("↑ / k", "Move up", theme),
-        help_line("↓ / j", "Move down", theme),
-        help_line("← / h", "Collapse directory / go to parent", theme),
-        help_line("→ / l", "Expand directory", theme),
-        help_line("Space", "Toggle expand/collapse", theme),
-        help_line("g / Home", "Go to first item", theme),
-        help_line("G / End", "Go to last item", theme),
-        help_line("PgUp/PgDn", "Page up/down", theme),
-        help_line("Ctrl+u/d", "Half page up/down", theme),
         Line::from(""),
-        Line::styled(
-            "  ACTIONS",
-            Style::default()
-                .fg(theme.starred)
-                .add_modifier(Modifier::BOLD),
+        Line::styled("  ACTIONS", theme.help_heading),
+        help_line(
+            keys,
+            &[Action::Open],
+            "cd to selected directory and exit",
+            theme,
+        ),
+        help_line(keys, &[Action::Star], "Toggle star on directory", theme),
+        help_line(
+            keys,
+            &[Action::StarredView],
+            "Switch to/from starred view",
+            theme,
+        ),
+        help_line(
+            keys,
+            &[Action::Search],
+            "Fuzzy search files and folders",
+            theme,
+        ),
+        help_line(keys, &[Action::TogglePreview], "Toggle preview pane", theme),
+        help_line(keys, &[Action::ToggleHidden], "Toggle hidden files", theme),
+        help_line(
+            keys,
+            &[Action::Bookmark],
+            "Add/edit bookmark with label",
+            theme,
+        ),
+        help_line(
+            keys,
+            &[Action::BookmarksView],
+            "Open/close bookmarks view",
+            theme,
+        ),
+        help_line(
+            keys,
+            &[Action::RecentView],
+            "Open/close recent directories",
+            theme,
+        ),
+        help_line(
+            keys,
+            &[Action::Quit],
+            "Quit without changing directory",
+            theme,
+        ),
+        help_line(keys, &[Action::Help], "Toggle this help", theme),
+        Line::from(""),
+        Line::styled("  FILES", theme.help_heading),
+        help_line(
+            keys,
+            &[Action::NewFile, Action::NewDir],
+            "Create file / directory",
+            theme,
+        ),
+        help_line(keys, &[Action::Rename], "Rename", theme),
+        help_line(keys, &[Action::Copy, Action::Cut], "Copy / cut", theme),
+        help_line(
+            keys,
+            &[Action::Paste],
+            "Paste into selected directory",
+            theme,
+        ),
+        help_line(
+            keys,
+            &[Action::Trash],
+            "Move to trash (asks for confirmation)",
+            theme,
+        ),
+        help_line(keys, &[Action::Undo], "Undo last file operation", theme),
+        help_line(
+            keys,
+            &[Action::TrashView],
+            "Open/close trash (Enter restores)",
+            theme,
+        ),
+        help_line(
+            keys,
+            &[Action::DiskUsageView],
+            "Open/close disk usage of the directory",
+            theme,
+        ),
+        help_line(
+            keys,
+            &[Action::ToggleApparentSize],
+            "Show apparent sizes or disk usage",
+            theme,
         ),
-        help_line("Enter", "cd to selected directory and exit", theme),
-        help_line("s", "Toggle star on directory", theme),
-        help_line("S", "Switch to/from starred view", theme),
-        help_line("/", "Fuzzy search files and folders", theme),
-        help_line("p", "Toggle preview pane", theme),
-        help_line(".", "Toggle hidden files", theme),
-        help_line("b", "Add/edit bookmark with label", theme),
-        help_line("B", "Open/close bookmarks view", theme),
-        help_line("r", "Open/close recent directories", theme),
-        help_line("q / Ctrl+c", "Quit without changing directory", theme),
-        help_line("?", "Toggle this help", theme),
         Line::from(""),
-        Line::styled(
-            "  Press any key to close",
-            Style::default()
-                .fg(theme.dim)
-                .add_modifier(Modifier::ITALIC),
+        Line::styled("  MARKS", theme.help_heading),
+        help_line(keys, &[Action::Mark], "Toggle mark and move down", theme),
+        help_line(
+            keys,
+            &[Action::Visual],
+            "Visual mode: mark a range of rows",
+            theme,
+        ),
+        help_line(keys, &[Action::ClearMarks], "Clear all marks", theme),
+        help_line(
+            keys,
+            &[
+                Action::Star,
+                Action::Bookmark,
+                Action::Copy,
+                Action::Cut,
+                Action::Trash,
+            ],
+            "Act on all marked entries",
+            theme,
         ),
+        help_line(
+            keys,
+            &[Action::Open],
+            "Print all marked paths and exit",
+            theme,
+        ),
+        Line::from(""),
+        Line::styled("  Press any key to close", theme.hint),
     ];
 
+    // Center the help popup
+    let popup_width = 64.min(area.width.saturating_sub(4));
+    let popup_height = (help_text.len() as u16 + 2).min(area.height.saturating_sub(4));
+    let popup_area = Rect {
+        x: (area.width - popup_width) / 2,
+        y: (area.height - popup_height) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    frame.render_widget(Clear, popup_area);
+
     let help = Paragraph::new(help_text)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(theme.border))
+                .border_style(theme.popup_border)
                 .title(" Help ")
-                .title_style(
-                    Style::default()
-                        .fg(theme.border)
-                        .add_modifier(Modifier::BOLD),
-                )
-                .style(Style::default().bg(Color::Rgb(15, 15, 25))),
+                .title_style(theme.popup_title)
+                .style(theme.popup),
         )
         .wrap(Wrap { trim: false });
 
     frame.render_widget(help, popup_area);
 }
 
-fn help_line<'a>(key: &'a str, desc: &'a str, theme: &Theme) -> Line<'a> {
+/// A help row listing the keys for `actions`: every key for a single action,
+/// or the preferred key of each when they share a row.
+fn help_line<'a>(keys: &Keymap, actions: &[Action], desc: &'a str, theme: &Theme) -> Line<'a> {
+    let key = match actions {
+        [action] => keys.describe(*action),
+        _ => keys
+            .short(actions)
+            .unwrap_or_else(|| "(unbound)".to_string()),
+    };
     Line::from(vec![
-        Span::styled(format!("  {:14}", key), Style::default().fg(theme.border)),
-        Span::styled(desc, Style::default().fg(theme.text)),
+        Span::styled(format!("  {:18}", key), theme.help_key),
+        Span::styled(desc, theme.help_text),
     ])
 }
 
@@ -301,50 +482,73 @@ fn render_preview(frame: &mut Frame, app: &App, area: Rect) {
                     items
                 })
                 .unwrap_or_default();
-            (title, entries.join("\n"))
-        }
-        Some(ref path) if path.is_file() => {
-            let title = format!(
-                " {} ",
-                path.file_name().unwrap_or_default().to_string_lossy()
-            );
-            let content = fs::File::open(path)
-                .ok()
-                .map(|f| {
-                    BufReader::new(f)
-                        .lines()
-                        .take(100)
-                        .filter_map(|l| l.ok())
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                })
-                .unwrap_or_else(|| "[Unable to read file]".to_string());
-            (title, content)
+            (title, Text::raw(entries.join("\n")))
         }
+        Some(ref path) if path.is_file() => match app.preview.as_ref() {
+            // Until the selected file is read, keep showing the last one
+            Some(preview) => (
+                format!(
+                    " {} ",
+                    preview
+                        .path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                ),
+                match &preview.contents {
+                    PreviewContents::Lines(lines) if app.config.preview.line_numbers => {
+                        Text::from(with_line_numbers(lines, theme))
+                    }
+                    PreviewContents::Lines(lines) => Text::from(lines.clone()),
+                    PreviewContents::Binary => Text::raw("[Binary file]"),
+                    PreviewContents::Unreadable => Text::raw("[Unable to read file]"),
+                },
+            ),
+            None => (
+                format!(
+                    " {} ",
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                ),
+                Text::styled("Loading…", theme.hint),
+            ),
+        },
         _ => (
             " Preview ".to_string(),
-            "Select a file or directory".to_string(),
+            Text::raw("Select a file or directory"),
         ),
     };
 
     let block = Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(theme.border))
+        .border_style(theme.preview_border)
         .title(title)
-        .title_style(
-            Style::default()
-                .fg(theme.border)
-                .add_modifier(Modifier::BOLD),
-        );
+        .title_style(theme.preview_title);
 
     let paragraph = Paragraph::new(content)
-        .style(Style::default().fg(theme.dim))
+        .style(theme.preview)
         .block(block)
         .wrap(Wrap { trim: false });
 
     frame.render_widget(paragraph, area);
 }
 
+/// Preview lines behind a gutter of line numbers.
+fn with_line_numbers(lines: &[Line<'static>], theme: &Theme) -> Vec<Line<'static>> {
+    let width = lines.len().to_string().len();
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let mut spans = vec![Span::styled(
+                format!("{:>width$} ", i + 1),
+                theme.preview_line_number,
+            )];
+            spans.extend(line.spans.iter().cloned());
+            Line::from(spans)
+        })
+        .collect()
+}
+
 fn render_search_bar(frame: &mut Frame, app: &App, area: Rect) {
     let theme = &app.config.theme;
 
@@ -360,13 +564,19 @@ fn render_search_bar(frame: &mut Frame, app: &App, area: Rect) {
     } else {
         format!(" [{}/{}]", app.search_index + 1, app.search_matches.len())
     };
+    let indexing_text = if app.is_indexing() {
+        " indexing…"
+    } else {
+        ""
+    };
 
     let line = Line::from(vec![
-        Span::styled(&search_text, Style::default().fg(theme.text)),
-        Span::styled(&count_text, Style::default().fg(theme.dim)),
+        Span::styled(&search_text, theme.search_text),
+        Span::styled(&count_text, theme.search_count),
+        Span::styled(indexing_text, theme.search_count),
     ]);
 
-    let input = Paragraph::new(line).style(Style::default().bg(Color::Rgb(30, 30, 40)));
+    let input = Paragraph::new(line).style(theme.search);
 
     frame.render_widget(input, area);
 
@@ -400,14 +610,10 @@ fn render_bookmark_input(frame: &mut Frame, app: &App) {
     let title = format!(" Bookmark: {} ", path_name);
     let block = Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(theme.starred))
+        .border_style(theme.list_border)
         .title(title)
-        .title_style(
-            Style::default()
-                .fg(theme.starred)
-                .add_modifier(Modifier::BOLD),
-        )
-        .style(Style::default().bg(Color::Rgb(15, 15, 25)));
+        .title_style(theme.list_title)
+        .style(theme.popup);
 
     let inner = block.inner(popup_area);
     frame.render_widget(block, popup_area);
@@ -416,7 +622,7 @@ fn render_bookmark_input(frame: &mut Frame, app: &App) {
 
     // Label prompt
     frame.render_widget(
-        Paragraph::new("Label (optional):").style(Style::default().fg(theme.dim)),
+        Paragraph::new("Label (optional):").style(theme.prompt),
         chunks[0],
     );
 
@@ -424,7 +630,7 @@ fn render_bookmark_input(frame: &mut Frame, app: &App) {
     let input_width = chunks[1].width.saturating_sub(1) as usize;
     let scroll = app.bookmark_input.visual_scroll(input_width);
     let input = Paragraph::new(app.bookmark_input.value())
-        .style(Style::default().fg(theme.text))
+        .style(theme.input)
         .scroll((0, scroll as u16));
 
     frame.render_widget(input, chunks[1]);
@@ -432,3 +638,99 @@ fn render_bookmark_input(frame: &mut Frame, app: &App) {
     let cursor_x = chunks[1].x + (app.bookmark_input.visual_cursor().saturating_sub(scroll)) as u16;
     frame.set_cursor_position((cursor_x, chunks[1].y));
 }
+
+fn render_file_op_input(frame: &mut Frame, app: &App) {
+    let area = frame.area();
+    let theme = &app.config.theme;
+
+    let popup_width = 50.min(area.width.saturating_sub(4));
+    let popup_height = 5;
+    let popup_area = Rect {
+        x: (area.width - popup_width) / 2,
+        y: (area.height - popup_height) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    frame.render_widget(Clear, popup_area);
+
+    let path_name = app
+        .file_op_path
+        .as_ref()
+        .and_then(|p| p.file_name())
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let (title, prompt) = match app.input_mode {
+        InputMode::CreateDir => (format!(" New directory in {} ", path_name), "Name:"),
+        InputMode::Rename => (format!(" Rename: {} ", path_name), "New name:"),
+        _ => (format!(" New file in {} ", path_name), "Name:"),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(theme.popup_border)
+        .title(title)
+        .title_style(theme.popup_title)
+        .style(theme.popup);
+
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let chunks = Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).split(inner);
+
+    frame.render_widget(Paragraph::new(prompt).style(theme.prompt), chunks[0]);
+
+    let input_width = chunks[1].width.saturating_sub(1) as usize;
+    let scroll = app.file_op_input.visual_scroll(input_width);
+    let input = Paragraph::new(app.file_op_input.value())
+        .style(theme.input)
+        .scroll((0, scroll as u16));
+
+    frame.render_widget(input, chunks[1]);
+
+    let cursor_x = chunks[1].x + (app.file_op_input.visual_cursor().saturating_sub(scroll)) as u16;
+    frame.set_cursor_position((cursor_x, chunks[1].y));
+}
+
+fn render_confirm_delete(frame: &mut Frame, app: &App) {
+    let area = frame.area();
+    let theme = &app.config.theme;
+
+    let popup_width = 50.min(area.width.saturating_sub(4));
+    let popup_height = 5;
+    let popup_area = Rect {
+        x: (area.width - popup_width) / 2,
+        y: (area.height - popup_height) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    frame.render_widget(Clear, popup_area);
+
+    let target = match app.delete_paths.as_slice() {
+        [path] => path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        paths => format!("{} items", paths.len()),
+    };
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(theme.confirm_border)
+        .title(" Delete ")
+        .title_style(theme.confirm_title)
+        .style(theme.popup);
+
+    let text = vec![
+        Line::styled(format!("Move {} to trash?", target), theme.input),
+        Line::from(vec![
+            Span::styled("y", theme.dialog_key),
+            Span::styled(" trash  ", theme.prompt),
+            Span::styled("any other key", theme.dialog_key),
+            Span::styled(" cancel", theme.prompt),
+        ]),
+    ];
+
+    frame.render_widget(Paragraph::new(text).block(block), popup_area);
+}
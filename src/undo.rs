@@ -0,0 +1,42 @@
+use crate::state::ForgottenPaths;
+use crate::trash::TrashEntry;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+const MAX_UNDO: usize = 50;
+
+/// A completed file operation, with enough information to reverse it.
+#[derive(Debug, Clone)]
+pub enum FileOp {
+    Created(PathBuf),
+    Copied(PathBuf),
+    /// A rename, or a move from cut and paste
+    Moved {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// A delete, with the state remembered for the path until then
+    Trashed {
+        entry: TrashEntry,
+        forgotten: ForgottenPaths,
+    },
+}
+
+/// In-session history of file operations, newest last.
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    ops: VecDeque<FileOp>,
+}
+
+impl UndoStack {
+    pub fn push(&mut self, op: FileOp) {
+        self.ops.push_back(op);
+        while self.ops.len() > MAX_UNDO {
+            self.ops.pop_front();
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<FileOp> {
+        self.ops.pop_back()
+    }
+}
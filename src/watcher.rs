@@ -0,0 +1,257 @@
+use crossbeam_channel::{unbounded, Receiver};
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long events have to stop for before they are handed on, so a burst
+/// like a build writing many files is handled once.
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+/// How long events are held back at most while they keep coming.
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Directories affected by filesystem events since the last poll.
+#[derive(Debug, Default)]
+pub struct FsChanges {
+    /// Directories whose listing changed (entries created, removed or renamed)
+    pub listings: HashSet<PathBuf>,
+    /// Directories containing any change, including file contents
+    pub touched: HashSet<PathBuf>,
+}
+
+impl FsChanges {
+    pub fn is_empty(&self) -> bool {
+        self.touched.is_empty()
+    }
+}
+
+pub struct FsWatcher {
+    watcher: Option<RecommendedWatcher>,
+    event_rx: Receiver<notify::Result<Event>>,
+    watched: HashSet<PathBuf>,
+    /// Changes waiting for events to settle
+    pending: FsChanges,
+    /// When the first and the latest of the pending events came in
+    first_event: Option<Instant>,
+    last_event: Option<Instant>,
+}
+
+impl FsWatcher {
+    pub fn new() -> Self {
+        let (event_tx, event_rx) = unbounded();
+
+        // Watching is best-effort: without it the tree is simply a snapshot
+        let watcher = notify::recommended_watcher(move |res| {
+            let _ = event_tx.send(res);
+        })
+        .ok();
+
+        Self {
+            watcher,
+            event_rx,
+            watched: HashSet::new(),
+            pending: FsChanges::default(),
+            first_event: None,
+            last_event: None,
+        }
+    }
+
+    /// Watches exactly `dirs` (non-recursively), adding and removing watches as needed.
+    pub fn sync(&mut self, dirs: HashSet<PathBuf>) {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return;
+        };
+
+        for stale in self.watched.difference(&dirs) {
+            let _ = watcher.unwatch(stale);
+        }
+        self.watched.retain(|p| dirs.contains(p));
+
+        for dir in dirs {
+            if !self.watched.contains(&dir)
+                && watcher.watch(&dir, RecursiveMode::NonRecursive).is_ok()
+            {
+                self.watched.insert(dir);
+            }
+        }
+    }
+
+    /// The changes since the last call, once events have settled for
+    /// `SETTLE_DELAY`, or every `MAX_DELAY` while they keep coming.
+    pub fn poll_changes(&mut self) -> FsChanges {
+        let now = Instant::now();
+        while let Ok(res) = self.event_rx.try_recv() {
+            let Ok(event) = res else {
+                continue;
+            };
+            let structural = match event.kind {
+                EventKind::Access(_) => continue,
+                EventKind::Create(_) | EventKind::Remove(_) => true,
+                EventKind::Modify(ModifyKind::Name(_)) => true,
+                EventKind::Modify(_) => false,
+                EventKind::Any | EventKind::Other => true,
+            };
+
+            for path in event.paths {
+                let Some(dir) = self.watched_parent(&path) else {
+                    continue;
+                };
+                if structural {
+                    self.pending.listings.insert(dir.clone());
+                }
+                self.pending.touched.insert(dir);
+                self.first_event.get_or_insert(now);
+                self.last_event = Some(now);
+            }
+        }
+
+        let (Some(first), Some(last)) = (self.first_event, self.last_event) else {
+            return FsChanges::default();
+        };
+        if now - last < SETTLE_DELAY && now - first < MAX_DELAY {
+            return FsChanges::default();
+        }
+        self.first_event = None;
+        self.last_event = None;
+        std::mem::take(&mut self.pending)
+    }
+
+    fn watched_parent(&self, path: &Path) -> Option<PathBuf> {
+        path.parent()
+            .filter(|parent| self.watched.contains(*parent))
+            .or_else(|| self.watched.contains(path).then_some(path))
+            .map(Path::to_path_buf)
+    }
+}
+
+/// Watches config.toml and the theme files next to it.
+pub struct ConfigWatcher {
+    watcher: RecommendedWatcher,
+    event_rx: Receiver<notify::Result<Event>>,
+    file: PathBuf,
+    themes_dir: PathBuf,
+    /// The directories watched: the config and themes directories, or their
+    /// nearest existing ancestors until they are created
+    watched: Vec<PathBuf>,
+}
+
+impl ConfigWatcher {
+    /// Watches the directory holding `file`, since editors often save by
+    /// replacing the file. Returns None if watching is not possible.
+    pub fn new(file: &Path) -> Option<Self> {
+        let themes_dir = file.parent()?.join("themes");
+        let (event_tx, event_rx) = unbounded();
+        let watcher = notify::recommended_watcher(move |res| {
+            let _ = event_tx.send(res);
+        })
+        .ok()?;
+
+        let mut config_watcher = Self {
+            watcher,
+            event_rx,
+            file: file.to_path_buf(),
+            themes_dir,
+            watched: Vec::new(),
+        };
+        config_watcher.rewatch();
+        Some(config_watcher)
+    }
+
+    /// Whether config.toml or a theme file changed since the last poll.
+    pub fn poll_changed(&mut self) -> bool {
+        let mut changed = false;
+        let mut any_event = false;
+        while let Ok(res) = self.event_rx.try_recv() {
+            let Ok(event) = res else {
+                continue;
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+            any_event = true;
+            changed |= event
+                .paths
+                .iter()
+                .any(|path| *path == self.file || path.starts_with(&self.themes_dir));
+        }
+        // A directory on the way may have come or gone. Events in one just
+        // created can be missed, so moving the watches counts as a change.
+        if any_event {
+            changed |= self.rewatch();
+        }
+        changed
+    }
+
+    /// Watches the nearest existing directory for the config file and for
+    /// the themes. Returns whether that moved anything.
+    fn rewatch(&mut self) -> bool {
+        let config_dir = self.themes_dir.parent().unwrap_or(&self.themes_dir);
+        let mut wanted: Vec<PathBuf> = [config_dir, self.themes_dir.as_path()]
+            .into_iter()
+            .filter_map(|dir| dir.ancestors().find(|d| d.is_dir()))
+            .map(Path::to_path_buf)
+            .collect();
+        wanted.dedup();
+        if wanted == self.watched {
+            return false;
+        }
+        for dir in &self.watched {
+            let _ = self.watcher.unwatch(dir);
+        }
+        for dir in &wanted {
+            // Theme files are optional, and so is watching them
+            let _ = self.watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+        self.watched = wanted;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::thread;
+
+    /// Polls for up to a few seconds, as events arrive from another thread.
+    fn changed_soon(watcher: &mut ConfigWatcher) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if watcher.poll_changed() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    /// Polls for a moment, long enough for events to have arrived.
+    fn changed_now(watcher: &mut ConfigWatcher) -> bool {
+        thread::sleep(Duration::from_millis(300));
+        watcher.poll_changed()
+    }
+
+    #[test]
+    fn config_watcher_sees_config_and_themes_created_later() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_dir = dir.path().join("config/treenav");
+        let file = config_dir.join("config.toml");
+        let mut watcher = ConfigWatcher::new(&file).unwrap();
+        assert!(!changed_now(&mut watcher));
+
+        fs::create_dir_all(&config_dir).unwrap();
+        assert!(changed_soon(&mut watcher));
+        fs::write(&file, "icons = \"ascii\"\n").unwrap();
+        assert!(changed_soon(&mut watcher));
+
+        fs::write(config_dir.join("notes.txt"), "").unwrap();
+        assert!(!changed_now(&mut watcher));
+
+        fs::create_dir(config_dir.join("themes")).unwrap();
+        assert!(changed_soon(&mut watcher));
+        fs::write(config_dir.join("themes/mine.toml"), "").unwrap();
+        assert!(changed_soon(&mut watcher));
+    }
+}
@@ -0,0 +1,215 @@
+//! Exercises the non-interactive subcommands against a throwaway data directory.
+
+mod common;
+
+use common::{arg, fixture, Env};
+use std::fs;
+
+#[test]
+fn bookmark_add_rename_and_remove() {
+    let env = Env::new();
+    let projects = env.mkdir("projects");
+    let notes = env.mkdir("notes");
+
+    env.stdout(&["bookmark", "add", arg(&projects), "--label", "proj"]);
+    env.stdout(&["bookmark", "add", arg(&notes)]);
+    assert_eq!(
+        env.stdout(&["bookmark", "ls"]),
+        format!("proj\t{}\n\t{}\n", projects.display(), notes.display())
+    );
+
+    env.stdout(&["bookmark", "rename", arg(&notes), "notes"]);
+    env.stdout(&["bookmark", "rm", "proj"]);
+    assert_eq!(
+        env.stdout(&["bookmark", "ls"]),
+        format!("notes\t{}\n", notes.display())
+    );
+
+    assert!(!env.treenav(&["bookmark", "rm", "proj"]).status.success());
+}
+
+#[test]
+fn star_add_and_remove() {
+    let env = Env::new();
+    let src = env.mkdir("src");
+
+    env.stdout(&["star", "add", arg(&src)]);
+    assert_eq!(env.stdout(&["star", "ls"]), format!("{}\n", src.display()));
+    env.stdout(&["star", "rm", arg(&src)]);
+    assert_eq!(env.stdout(&["star", "ls"]), "");
+}
+
+#[test]
+fn jump_prefers_labels_then_fuzzy_names() {
+    let env = Env::new();
+    let work = env.mkdir("a/work");
+    let projects = env.mkdir("b/projects");
+    let archive = env.mkdir("c/old-projects-archive/x");
+
+    env.stdout(&["bookmark", "add", arg(&work), "--label", "proj"]);
+    env.stdout(&["star", "add", arg(&archive)]);
+    env.stdout(&["star", "add", arg(&projects)]);
+
+    assert_eq!(
+        env.stdout(&["jump", "proj"]),
+        format!("{}\n", work.display())
+    );
+    assert_eq!(
+        env.stdout(&["jump", "projects"]),
+        format!("{}\n", projects.display())
+    );
+    assert!(env
+        .stdout(&["recent"])
+        .contains(&projects.display().to_string()));
+
+    let missing = env.treenav(&["jump", "#missing#"]);
+    assert_eq!(missing.status.code(), Some(1));
+
+    // A label on a deleted directory doesn't hide a later one on a live one
+    let gone = env.mkdir("gone");
+    let docs = env.mkdir("d/docs");
+    let notes = env.mkdir("e/notes");
+    env.stdout(&["bookmark", "add", arg(&gone), "--label", "notes"]);
+    env.stdout(&["bookmark", "add", arg(&docs), "--label", "notes"]);
+    env.stdout(&["star", "add", arg(&notes)]);
+    env.stdout(&["jump", "e/notes"]);
+    fs::remove_dir(&gone).unwrap();
+    assert_eq!(
+        env.stdout(&["jump", "notes"]),
+        format!("{}\n", docs.display())
+    );
+}
+
+#[test]
+fn recent_ranks_by_frecency() {
+    let env = Env::new();
+    let once = env.mkdir("once");
+    let often = env.mkdir("often");
+
+    env.stdout(&["star", "add", arg(&once)]);
+    env.stdout(&["star", "add", arg(&often)]);
+    env.stdout(&["jump", "often"]);
+    env.stdout(&["jump", "once"]);
+    env.stdout(&["jump", "often"]);
+    assert_eq!(
+        env.stdout(&["recent"]),
+        format!("{}\n{}\n", often.display(), once.display())
+    );
+}
+
+#[test]
+fn import_reads_every_format() {
+    let cases = [
+        ("zoxide", "db.zo", 3, 1),
+        ("autojump", "autojump.txt", 3, 1),
+        ("z", "z", 3, 1),
+        ("fasd", "fasd", 4, 2),
+    ];
+    for (from, file, total, skipped) in cases {
+        let env = Env::new();
+        let summary = env.stdout(&[
+            "import",
+            "--from",
+            from,
+            &fixture(&format!("import/{}", file)),
+        ]);
+        assert!(
+            summary.starts_with(&format!("Imported {} entries", total))
+                && summary.contains(&format!("2 new, 0 merged, {} skipped", skipped)),
+            "{}: {}",
+            from,
+            summary
+        );
+
+        let mut recent: Vec<String> = env.stdout(&["recent"]).lines().map(String::from).collect();
+        recent.sort();
+        assert_eq!(recent, ["/etc", "/usr"], "{}", from);
+    }
+}
+
+#[test]
+fn import_merges_with_existing_history() {
+    let env = Env::new();
+    env.stdout(&["star", "add", "/etc"]);
+    env.stdout(&["jump", "etc"]);
+    let summary = env.stdout(&["import", "--from", "z", &fixture("import/z")]);
+    assert!(summary.contains("1 new, 1 merged"), "{}", summary);
+
+    // Imported ranks are scaled so the highest, /usr's 14, counts as 10 visits.
+    // /etc: (1 visit + 2.5 x 10/14 imported) x4 for the visit just now.
+    // /usr: 10 imported, x0.25 as its last access is long past.
+    let scores = env.stdout(&["recent", "--scores"]);
+    assert_eq!(scores, "   11.14 /etc\n    2.50 /usr\n");
+}
+
+#[test]
+fn import_skips_ranks_that_are_not_positive_numbers() {
+    let env = Env::new();
+    let file = env.dir.path().join("z");
+    fs::write(
+        &file,
+        "/usr|NaN|1700000000\n/etc|inf|1700000000\n/tmp|-3|1700000000\n/|4|1700000000\n",
+    )
+    .unwrap();
+    let summary = env.stdout(&["import", "--from", "z", "--dry-run", arg(&file)]);
+    assert!(summary.contains("skip   /usr\n"), "{}", summary);
+    assert!(
+        summary.contains("1 new, 0 merged, 3 skipped"),
+        "{}",
+        summary
+    );
+
+    env.stdout(&["import", "--from", "z", arg(&file)]);
+    assert_eq!(env.stdout(&["recent"]), "/\n");
+}
+
+#[test]
+fn import_dry_run_writes_nothing() {
+    let env = Env::new();
+    let summary = env.stdout(&[
+        "import",
+        "--from",
+        "fasd",
+        "--dry-run",
+        &fixture("import/fasd"),
+    ]);
+    assert!(summary.contains("new    /usr"), "{}", summary);
+    assert!(summary.contains("skip   /etc/passwd"), "{}", summary);
+    assert!(summary.contains("Would import 4 entries"), "{}", summary);
+    assert_eq!(env.stdout(&["recent"]), "");
+}
+
+#[test]
+fn import_rejects_malformed_files() {
+    let env = Env::new();
+    let output = env.treenav(&["import", "--from", "zoxide", &fixture("import/z")]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn session_list_and_remove() {
+    let env = Env::new();
+    let work = env.mkdir("work");
+    let play = env.mkdir("play");
+    env.write_state(
+        &serde_json::json!({
+            "version": 3,
+            "sessions": {
+                "work": { "root": work, "tree": { "selected": work.join("src") } },
+                "play": { "root": play, "view_mode": "starred" },
+            },
+        })
+        .to_string(),
+    );
+
+    assert_eq!(
+        env.stdout(&["session", "ls"]),
+        format!("play\t{}\nwork\t{}\n", play.display(), work.display())
+    );
+    env.stdout(&["session", "rm", "play"]);
+    assert_eq!(
+        env.stdout(&["session", "ls"]),
+        format!("work\t{}\n", work.display())
+    );
+    assert!(!env.treenav(&["session", "rm", "play"]).status.success());
+}
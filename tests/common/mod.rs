@@ -0,0 +1,78 @@
+//! Runs the treenav binary against a throwaway data and config directory.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+pub struct Env {
+    pub dir: TempDir,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Self {
+            dir: TempDir::new().unwrap(),
+        }
+    }
+
+    pub fn mkdir(&self, name: &str) -> PathBuf {
+        let path = self.dir.path().join(name);
+        fs::create_dir_all(&path).unwrap();
+        path.canonicalize().unwrap()
+    }
+
+    pub fn treenav(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// The treenav command, for tests that need to adjust its environment.
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_treenav"));
+        command
+            .args(args)
+            .current_dir(self.dir.path())
+            .env("XDG_DATA_HOME", self.dir.path().join("data"))
+            .env("XDG_CONFIG_HOME", self.dir.path().join("config"))
+            .env_remove("LS_COLORS");
+        command
+    }
+
+    pub fn write_state(&self, json: &str) {
+        let dir = self.dir.path().join("data/treenav");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("state.json"), json).unwrap();
+    }
+
+    pub fn write_config(&self, toml: &str) {
+        let dir = self.dir.path().join("config/treenav");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.toml"), toml).unwrap();
+    }
+
+    pub fn read_state(&self) -> String {
+        fs::read_to_string(self.dir.path().join("data/treenav/state.json")).unwrap()
+    }
+
+    pub fn stdout(&self, args: &[&str]) -> String {
+        let output = self.treenav(args);
+        assert!(
+            output.status.success(),
+            "treenav {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+pub fn arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+/// Path of a file under `tests/fixtures`.
+pub fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
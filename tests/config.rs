@@ -0,0 +1,373 @@
+//! Checks how config.toml problems are reported and what `config dump` prints.
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+#[test]
+fn check_reports_each_problem_by_line_and_column() {
+    let env = Env::new();
+    env.write_config(
+        "[theme]\n\
+         border = \"#50C8DC\"\n\
+         text = \"whte\"\n\
+         \n\
+         [keys]\n\
+         n = \"down\"\n\
+         g = \"first\"\n\
+         gg = \"last\"\n\
+         \"ctrl-n\" = \"bogus\"\n\
+         \"hyper-x\" = \"up\"\n",
+    );
+
+    let output = env.treenav(&["config", "check"]);
+    assert_eq!(output.status.code(), Some(1));
+    let file = env.dir.path().join("config/treenav/config.toml");
+    let expected: Vec<String> = [
+        "3:8: invalid color 'whte', expected #rrggbb, a color name, default or 0-255",
+        "8:1: 'gg' conflicts with 'g'",
+        "9:12: unknown action 'bogus'",
+        "10:1: unknown modifier 'hyper' in 'hyper-x'",
+    ]
+    .iter()
+    .map(|line| format!("{}:{}", file.display(), line))
+    .collect();
+    assert_eq!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .collect::<Vec<_>>(),
+        expected
+    );
+}
+
+#[test]
+fn check_reports_syntax_errors_and_unknown_settings() {
+    let env = Env::new();
+    env.write_config("[theme]\nborder = #50C8DC\n");
+    let report = String::from_utf8(env.treenav(&["config", "check"]).stdout).unwrap();
+    assert!(report.contains("config.toml:2:10: "), "{}", report);
+
+    env.write_config("[theme]\nboarder = \"red\"\n");
+    let report = String::from_utf8(env.treenav(&["config", "check"]).stdout).unwrap();
+    assert!(
+        report.contains("config.toml:2:1: unknown theme element 'boarder'"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn unknown_settings_are_reported_and_the_rest_still_applies() {
+    let env = Env::new();
+    env.write_config("colour = \"red\"\n\n[keys]\nctrl-n = \"down\"\n");
+    let report = String::from_utf8(env.treenav(&["config", "check"]).stdout).unwrap();
+    assert!(
+        report.contains("config.toml:1:1: unknown setting 'colour', expected theme, "),
+        "{}",
+        report
+    );
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(dump.contains("ctrl-n = \"down\""), "{}", dump);
+}
+
+#[test]
+fn check_accepts_a_missing_or_valid_file() {
+    let env = Env::new();
+    assert!(env
+        .stdout(&["config", "check"])
+        .ends_with("not found, using defaults\n"));
+
+    env.write_config("[theme]\nborder = \"cyan\"\n\n[keys]\nzc = \"collapse\"\n");
+    assert!(env.stdout(&["config", "check"]).ends_with(": OK\n"));
+}
+
+#[test]
+fn dump_prints_the_effective_config() {
+    let env = Env::new();
+    env.write_config(
+        "[theme]\nborder = \"cyan\"\n\n[keys]\ngg = \"first\"\n\"ctrl-n\" = \"down\"\nq = \"none\"\n",
+    );
+    let dump = env.stdout(&["config", "dump"]);
+
+    assert!(
+        dump.contains("[theme.tree_border]\nfg = \"cyan\""),
+        "{}",
+        dump
+    );
+    assert!(
+        dump.contains("[theme.list_title]\nfg = \"#fac832\"\nmodifiers = [\"bold\"]"),
+        "{}",
+        dump
+    );
+    assert!(dump.contains("gg = \"first\""), "{}", dump);
+    assert!(dump.contains("ctrl-n = \"down\""), "{}", dump);
+    assert!(dump.contains("pgdn = \"page-down\""), "{}", dump);
+    assert!(dump.contains("\"?\" = \"help\""), "{}", dump);
+    assert!(dump.contains("q = \"none\""), "{}", dump);
+    // The default g made way for gg
+    assert!(!dump.contains("\ng = "), "{}", dump);
+
+    // The dump is itself a valid config that changes nothing
+    env.write_config(&dump);
+    assert!(env.stdout(&["config", "check"]).ends_with(": OK\n"));
+    assert_eq!(env.stdout(&["config", "dump"]), dump);
+}
+
+#[test]
+fn theme_can_name_a_preset_and_override_elements() {
+    let env = Env::new();
+    env.write_config(
+        "[theme]\n\
+         name = \"nord\"\n\
+         footer = { fg = 244, bg = \"default\", modifiers = [\"italic\", \"bold\"] }\n",
+    );
+    assert!(env.stdout(&["config", "check"]).ends_with(": OK\n"));
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains(
+            "[theme.footer]\nbg = \"default\"\nfg = 244\nmodifiers = [\"bold\", \"italic\"]"
+        ),
+        "{}",
+        dump
+    );
+    // Elements not overridden come from nord
+    assert!(
+        dump.contains("[theme.tree_border]\nfg = \"#88c0d0\""),
+        "{}",
+        dump
+    );
+
+    env.write_config("theme = \"solarized\"\n");
+    assert!(env.stdout(&["config", "check"]).ends_with(": OK\n"));
+
+    env.write_config("theme = \"nope\"\n");
+    let report = String::from_utf8(env.treenav(&["config", "check"]).stdout).unwrap();
+    assert!(
+        report.ends_with("config.toml:1:9: no theme named 'nope'\n"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn theme_files_are_read_from_the_themes_directory() {
+    let env = Env::new();
+    let themes = env.dir.path().join("config/treenav/themes");
+    fs::create_dir_all(&themes).unwrap();
+    fs::write(
+        themes.join("mine.toml"),
+        "name = \"gruvbox\"\nborder = \"red\"\nselection = { modifiers = [\"sparkly\"] }\n",
+    )
+    .unwrap();
+    env.write_config("theme = \"mine\"\n");
+
+    let output = env.treenav(&["config", "check"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{}:3:13: unknown modifier 'sparkly'\n",
+            themes.join("mine.toml").display()
+        )
+    );
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[theme.tree_border]\nfg = \"red\""),
+        "{}",
+        dump
+    );
+}
+
+#[test]
+fn colors_come_from_ls_colors_and_the_colors_table() {
+    let env = Env::new();
+    env.write_config(
+        "[colors]\n\
+         \"*.rs\" = \"red\"\n\
+         ex = \"01;38;5;208\"\n\
+         socket = { fg = \"magenta\", modifiers = [\"underlined\"] }\n\
+         sparkly = \"blue\"\n\
+         \"*.md\" = \"38;5\"\n",
+    );
+
+    let output = env
+        .command(&["config", "check"])
+        .env("LS_COLORS", "di=01;34:*.rs=32:*.tar.gz=01;31:ln=target")
+        .output()
+        .unwrap();
+    let report = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = report
+        .lines()
+        .map(|l| l.split_once(".toml:").unwrap().1)
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "5:1: unknown file kind 'sparkly', expected an LS_COLORS code, a kind name or a *pattern",
+            "6:10: invalid color code '38;5'",
+        ]
+    );
+
+    let output = env
+        .command(&["config", "dump"])
+        .env("LS_COLORS", "di=01;34:*.rs=32:*.tar.gz=01;31:ln=target")
+        .output()
+        .unwrap();
+    let dump = String::from_utf8(output.stdout).unwrap();
+    for expected in [
+        "[colors]\nsymlink = \"target\"\n",
+        "[colors.\"*.rs\"]\nfg = \"red\"\nmodifiers = []\n",
+        "[colors.\"*.tar.gz\"]\nfg = \"red\"\nmodifiers = [\"bold\"]\n",
+        "[colors.directory]\nfg = \"blue\"\nmodifiers = [\"bold\"]\n",
+        "[colors.executable]\nfg = 208\nmodifiers = [\"bold\"]\n",
+        "[colors.socket]\nfg = \"magenta\"\nmodifiers = [\"underlined\"]\n",
+    ] {
+        assert!(dump.contains(expected), "{}\n{}", expected, dump);
+    }
+    // LS_COLORS replaces the defaults rather than adding to them
+    assert!(!dump.contains("[colors.pipe]"), "{}", dump);
+
+    // Without LS_COLORS, the colors ls itself uses
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[colors.pipe]\nbg = \"black\"\nfg = \"yellow\""),
+        "{}",
+        dump
+    );
+}
+
+#[test]
+fn icons_take_a_mode_and_glyph_mappings() {
+    let env = Env::new();
+    env.write_config("icons = \"ascii\"\n");
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[icons]\nmode = \"ascii\"\n\n[icons.directories]\n\n[icons.extensions]\n"),
+        "{}",
+        dump
+    );
+
+    env.write_config(
+        "[icons]\n\
+         mode = \"unicode\"\n\
+         extensions = { \".RS\" = \"R\", \"tar.gz\" = \"T\" }\n\
+         files = { Justfile = \"J\" }\n\
+         directories = { vendor = \"V\" }\n",
+    );
+    assert!(env.stdout(&["config", "check"]).ends_with(": OK\n"));
+    let dump = env.stdout(&["config", "dump"]);
+    for expected in [
+        "mode = \"unicode\"",
+        "rs = \"R\"",
+        "\"tar.gz\" = \"T\"",
+        "Justfile = \"J\"",
+        "vendor = \"V\"",
+        "zip = \"📦\"",
+    ] {
+        assert!(dump.contains(expected), "{}\n{}", expected, dump);
+    }
+
+    env.write_config("icons = \"fancy\"\n");
+    let report = String::from_utf8(env.treenav(&["config", "check"]).stdout).unwrap();
+    assert!(
+        report.ends_with(
+            "config.toml:1:9: unknown icon mode 'fancy', expected nerd, unicode, ascii or none\n"
+        ),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn sizes_take_units_and_apparent() {
+    let env = Env::new();
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[sizes]\napparent = false\nunits = \"binary\"\n"),
+        "{}",
+        dump
+    );
+
+    env.write_config("[sizes]\nunits = \"si\"\napparent = true\n");
+    assert!(env.stdout(&["config", "check"]).ends_with(": OK\n"));
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[sizes]\napparent = true\nunits = \"si\"\n"),
+        "{}",
+        dump
+    );
+
+    env.write_config("[sizes]\nunits = \"metric\"\n");
+    let report = String::from_utf8(env.treenav(&["config", "check"]).stdout).unwrap();
+    assert!(
+        report.ends_with(
+            "config.toml:2:9: unknown size units 'metric', expected binary, iec or si\n"
+        ),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn preview_takes_highlight_theme_and_line_numbers() {
+    let env = Env::new();
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[preview]\nhighlight = \"base16-ocean.dark\"\nline_numbers = true\n"),
+        "{}",
+        dump
+    );
+
+    env.write_config("[preview]\nhighlight = \"none\"\nline_numbers = false\n");
+    assert!(env.stdout(&["config", "check"]).ends_with(": OK\n"));
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[preview]\nhighlight = \"none\"\nline_numbers = false\n"),
+        "{}",
+        dump
+    );
+
+    env.write_config("[preview]\nhighlight = \"monokai\"\n");
+    let report = String::from_utf8(env.treenav(&["config", "check"]).stdout).unwrap();
+    assert!(
+        report.contains(
+            "config.toml:2:13: unknown highlight theme 'monokai', expected base16-ocean.dark, "
+        ),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn highlight_theme_defaults_to_one_that_suits_the_theme() {
+    let env = Env::new();
+    env.write_config("theme = \"light\"\n");
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[preview]\nhighlight = \"InspiredGitHub\""),
+        "{}",
+        dump
+    );
+
+    // Theme files get the highlight theme of the preset they start from
+    let themes = env.dir.path().join("config/treenav/themes");
+    fs::create_dir_all(&themes).unwrap();
+    fs::write(themes.join("mine.toml"), "name = \"solarized\"\n").unwrap();
+    env.write_config("theme = \"mine\"\n");
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[preview]\nhighlight = \"Solarized (dark)\""),
+        "{}",
+        dump
+    );
+
+    env.write_config("theme = \"light\"\n[preview]\nhighlight = \"base16-mocha.dark\"\n");
+    let dump = env.stdout(&["config", "dump"]);
+    assert!(
+        dump.contains("[preview]\nhighlight = \"base16-mocha.dark\""),
+        "{}",
+        dump
+    );
+}
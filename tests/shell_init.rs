@@ -0,0 +1,191 @@
+//! Runs the scripts from `treenav init` in every shell that is installed,
+//! with a stub `treenav` on PATH standing in for the interactive UI.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+const SHELLS: &[(&str, &str)] = &[
+    ("bash", "bash"),
+    ("zsh", "zsh"),
+    ("fish", "fish"),
+    ("nushell", "nu"),
+];
+
+fn installed(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .output()
+        .is_ok_and(|o| o.status.success())
+}
+
+fn init_script(shell: &str, extra: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_treenav"))
+        .args(["init", shell])
+        .args(extra)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "init {} failed", shell);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    /// A temp dir with a `bin/treenav` stub that records its arguments in
+    /// `args`, prints `$STUB_OUTPUT` and exits with `$STUB_STATUS`.
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let bin = dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        let stub = bin.join("treenav");
+        fs::write(
+            &stub,
+            "#!/bin/sh\necho \"$*\" > \"$STUB_ARGS\"\nprintf '%s\\n' \"$STUB_OUTPUT\"\nexit \"${STUB_STATUS:-0}\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        Self { dir }
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    fn write_script(&self, shell: &str, script: &str) -> PathBuf {
+        let path = self.path().join(format!("init.{}", shell));
+        fs::write(&path, script).unwrap();
+        path
+    }
+
+    /// Sources `script`, calls `cmd`, and returns the resulting working directory.
+    fn run(&self, program: &str, script: &Path, cmd: &str, stub: (&Path, i32)) -> PathBuf {
+        let script = script.display();
+        let code = match program {
+            "fish" | "nu" => format!("source '{}'; {}; pwd", script, cmd),
+            _ => format!(". '{}'; {}; pwd", script, cmd),
+        };
+        let mut command = Command::new(program);
+        if program == "nu" {
+            command.arg("--no-config-file");
+        }
+        let path = format!(
+            "{}:{}",
+            self.path().join("bin").display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        let output = command
+            .arg("-c")
+            .arg(code)
+            .current_dir(self.path())
+            .env("PATH", path)
+            .env("STUB_ARGS", self.path().join("args"))
+            .env("STUB_OUTPUT", stub.0)
+            .env("STUB_STATUS", stub.1.to_string())
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        PathBuf::from(stdout.lines().last().unwrap_or_default())
+    }
+}
+
+#[test]
+fn wrapper_changes_into_printed_directory() {
+    for &(shell, program) in SHELLS {
+        if !installed(program) {
+            continue;
+        }
+        let sandbox = Sandbox::new();
+        let target = sandbox.path().join("some dir");
+        fs::create_dir(&target).unwrap();
+        let target = target.canonicalize().unwrap();
+        let script = sandbox.write_script(shell, &init_script(shell, &["--cmd", "j"]));
+
+        let cwd = sandbox.run(program, &script, "j", (&target, 0));
+        assert_eq!(cwd, target, "{}", shell);
+    }
+}
+
+#[test]
+fn wrapper_jumps_when_argument_is_not_a_directory() {
+    for &(shell, program) in SHELLS {
+        if !installed(program) {
+            continue;
+        }
+        let sandbox = Sandbox::new();
+        let target = sandbox.path().join("projects");
+        fs::create_dir(&target).unwrap();
+        let target = target.canonicalize().unwrap();
+        let script = sandbox.write_script(shell, &init_script(shell, &[]));
+
+        assert_eq!(
+            sandbox.run(program, &script, "tn proj", (&target, 0)),
+            target
+        );
+        let args = fs::read_to_string(sandbox.path().join("args")).unwrap();
+        assert_eq!(args.trim(), "jump proj", "{}", shell);
+
+        // An existing directory is opened in the UI instead
+        sandbox.run(program, &script, "tn projects", (Path::new(""), 0));
+        let args = fs::read_to_string(sandbox.path().join("args")).unwrap();
+        assert_eq!(args.trim(), "projects", "{}", shell);
+    }
+}
+
+#[test]
+fn wrapper_stays_put_without_a_directory() {
+    for &(shell, program) in SHELLS {
+        if !installed(program) {
+            continue;
+        }
+        let sandbox = Sandbox::new();
+        let file = sandbox.path().join("picked.txt");
+        fs::write(&file, "").unwrap();
+        let start = sandbox.path().canonicalize().unwrap();
+        let script = sandbox.write_script(shell, &init_script(shell, &[]));
+
+        assert_eq!(sandbox.run(program, &script, "tn", (&file, 0)), start);
+        assert_eq!(
+            sandbox.run(program, &script, "tn", (Path::new(""), 1)),
+            start
+        );
+    }
+}
+
+#[test]
+fn key_binding_loads_in_non_interactive_shells() {
+    for &(shell, program) in SHELLS {
+        if !installed(program) {
+            continue;
+        }
+        let sandbox = Sandbox::new();
+        let script = init_script(shell, &["--bind", "ctrl-o"]);
+        assert!(script.contains("__treenav_widget") || shell == "nushell");
+        let script = sandbox.write_script(shell, &script);
+
+        let start = sandbox.path().canonicalize().unwrap();
+        assert_eq!(
+            sandbox.run(program, &script, "tn", (Path::new(""), 0)),
+            start
+        );
+    }
+}
+
+#[test]
+fn rejects_invalid_command_names() {
+    let output = Command::new(env!("CARGO_BIN_EXE_treenav"))
+        .args(["init", "bash", "--cmd", "tn; rm -rf"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
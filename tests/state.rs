@@ -0,0 +1,268 @@
+//! Loading, saving and migrating `state.json`.
+
+mod common;
+
+use common::{arg, fixture, Env};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn migrates_plain_recent_list() {
+    let env = Env::new();
+    let first = env.mkdir("first");
+    let second = env.mkdir("second");
+    env.write_state(&format!(
+        r#"{{"expanded_dirs": [], "recent_dirs": [{:?}, {:?}]}}"#,
+        first, second
+    ));
+
+    assert_eq!(
+        env.stdout(&["recent"]),
+        format!("{}\n{}\n", first.display(), second.display())
+    );
+}
+
+#[test]
+fn concurrent_saves_keep_every_change() {
+    let env = Env::new();
+    let dirs: Vec<PathBuf> = (0..8).map(|i| env.mkdir(&format!("d{}", i))).collect();
+
+    let children: Vec<_> = dirs
+        .iter()
+        .map(|dir| {
+            Command::new(env!("CARGO_BIN_EXE_treenav"))
+                .args(["star", "add", arg(dir)])
+                .env("XDG_DATA_HOME", env.dir.path().join("data"))
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+
+    let starred = env.stdout(&["star", "ls"]);
+    for dir in &dirs {
+        assert!(starred.contains(&dir.display().to_string()), "{}", starred);
+    }
+}
+
+#[test]
+fn corrupt_state_is_backed_up_and_reported() {
+    let env = Env::new();
+    let src = env.mkdir("src");
+    env.write_state("{\"expanded_dirs\": [");
+
+    let output = env.treenav(&["star", "add", arg(&src)]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("corrupt"));
+
+    let state_dir = env.dir.path().join("data/treenav");
+    let backup = fs::read_dir(&state_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_name().to_string_lossy().contains("corrupt"))
+        .expect("corrupt state was backed up");
+    assert_eq!(
+        fs::read_to_string(backup.path()).unwrap(),
+        "{\"expanded_dirs\": ["
+    );
+    assert_eq!(env.stdout(&["star", "ls"]), format!("{}\n", src.display()));
+}
+
+#[test]
+fn every_past_schema_loads_and_upgrades() {
+    for (name, first_recent) in [
+        ("v1.json", "/home/user/projects/treenav"),
+        ("v1-frecency.json", "/home/user/projects/treenav"),
+        ("v2.json", "/home/user/projects/treenav"),
+    ] {
+        let env = Env::new();
+        env.write_state(&fs::read_to_string(fixture(&format!("state/{}", name))).unwrap());
+
+        let output = env.treenav(&["bookmark", "ls"]);
+        assert!(output.stderr.is_empty(), "{}: {:?}", name, output);
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "proj\t/home/user/projects\n",
+            "{}",
+            name
+        );
+        assert_eq!(
+            env.stdout(&["star", "ls"]),
+            "/home/user/projects/treenav\n",
+            "{}",
+            name
+        );
+        let recent = env.stdout(&["recent"]);
+        assert_eq!(recent.lines().count(), 2, "{}", name);
+        assert_eq!(recent.lines().next(), Some(first_recent), "{}", name);
+
+        // Saving writes the current schema
+        env.stdout(&["bookmark", "rename", "proj", "p"]);
+        let saved: serde_json::Value = serde_json::from_str(&env.read_state()).unwrap();
+        assert_eq!(saved["version"], 3, "{}", name);
+        assert!(saved["recent_dirs"][0]["rank"].is_number(), "{}", name);
+        assert_eq!(saved["show_hidden"], true, "{}", name);
+        // Expanded directories now belong to the root they were opened under
+        assert_eq!(
+            saved["roots"]["/home/user"]["expanded_dirs"],
+            serde_json::json!(["/home/user/projects"]),
+            "{}",
+            name
+        );
+        assert!(saved.get("expanded_dirs").is_none(), "{}", name);
+    }
+}
+
+#[test]
+fn future_schema_is_never_overwritten() {
+    let env = Env::new();
+    let src = env.mkdir("src");
+    let future = fs::read_to_string(fixture("state/future.json")).unwrap();
+    env.write_state(&future);
+
+    // The change is dropped with a warning rather than failing the command
+    let output = env.treenav(&["star", "add", arg(&src)]);
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("newer treenav"), "{}", stderr);
+    assert!(stderr.contains("changes will not be saved"), "{}", stderr);
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+    assert_eq!(env.read_state(), future);
+}
+
+#[test]
+fn gc_removes_entries_for_deleted_paths() {
+    let env = Env::new();
+    let kept = env.mkdir("kept");
+    let gone = env.mkdir("gone");
+    env.stdout(&["star", "add", arg(&kept)]);
+    env.stdout(&["star", "add", arg(&gone)]);
+    env.stdout(&["bookmark", "add", arg(&gone), "--label", "g"]);
+    env.stdout(&["jump", "g"]);
+    fs::remove_dir(&gone).unwrap();
+
+    let before = env.read_state();
+    let report = env.stdout(&["gc", "--dry-run"]);
+    assert!(
+        report.contains(&format!("bookmark  {}", gone.display())),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains(&format!("star      {}", gone.display())),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains(&format!("recent    {}", gone.display())),
+        "{}",
+        report
+    );
+    assert!(report.contains("Would remove 3 entries"), "{}", report);
+    assert_eq!(env.read_state(), before);
+
+    env.stdout(&["gc"]);
+    assert_eq!(env.stdout(&["star", "ls"]), format!("{}\n", kept.display()));
+    assert_eq!(env.stdout(&["bookmark", "ls"]), "");
+    assert_eq!(env.stdout(&["recent"]), "");
+}
+
+#[test]
+fn gc_caps_expanded_dirs_per_root_deepest_first() {
+    let env = Env::new();
+    let mut expanded: Vec<PathBuf> = (0..255)
+        .map(|i| env.mkdir(&format!("root/d{:03}", i)))
+        .collect();
+    expanded.push(env.mkdir("root/a"));
+    expanded.push(env.mkdir("root/a/b"));
+    expanded.push(env.mkdir("root/a/b/c"));
+    env.write_state(&serde_json::json!({ "version": 2, "expanded_dirs": expanded }).to_string());
+
+    let report = env.stdout(&["gc"]);
+    assert!(report.contains("2 expanded over the limit"), "{}", report);
+
+    let saved: serde_json::Value = serde_json::from_str(&env.read_state()).unwrap();
+    let root = env.dir.path().join("root");
+    let remaining = saved["roots"][arg(&root)]["expanded_dirs"]
+        .as_array()
+        .unwrap();
+    assert_eq!(remaining.len(), 256);
+    let has = |name: &str| {
+        let path = env.dir.path().canonicalize().unwrap().join(name);
+        remaining.iter().any(|p| p.as_str() == path.to_str())
+    };
+    assert!(has("root/a"));
+    assert!(!has("root/a/b"));
+    assert!(!has("root/a/b/c"));
+}
+
+#[test]
+fn gc_drops_least_recently_used_roots_over_the_limit() {
+    let env = Env::new();
+    let roots: serde_json::Map<String, serde_json::Value> = (0..102)
+        .map(|i| {
+            let root = env.mkdir(&format!("r{:03}", i));
+            let state = serde_json::json!({ "selected": root.join("x"), "used_at": 1000 + i });
+            (arg(&root).to_string(), state)
+        })
+        .collect();
+    env.write_state(&serde_json::json!({ "version": 3, "roots": roots }).to_string());
+
+    let report = env.stdout(&["gc"]);
+    assert!(report.contains("2 roots over the limit"), "{}", report);
+
+    let saved: serde_json::Value = serde_json::from_str(&env.read_state()).unwrap();
+    let saved = saved["roots"].as_object().unwrap();
+    assert_eq!(saved.len(), 100);
+    let kept = |name: &str| saved.contains_key(arg(&env.mkdir(name)));
+    assert!(!kept("r000"));
+    assert!(!kept("r001"));
+    assert!(kept("r002"));
+    assert!(kept("r101"));
+}
+
+#[test]
+fn gc_drops_cached_sizes_of_deleted_dirs() {
+    let env = Env::new();
+    let kept = env.mkdir("kept");
+    let gone = env.dir.path().join("gone");
+    let below = gone.join("below");
+    let record = serde_json::json!({
+        "stamp": { "mtime": 0, "mtime_nsec": 0, "inode": 0, "device": 0 },
+        "files": { "apparent": 1, "allocated": 1, "files": 1, "dirs": 1 },
+        "linked": [],
+        "subdirs": [],
+        "checked_at": 0
+    });
+    let cache = serde_json::json!({
+        "version": 3,
+        "dirs": { arg(&kept): record, arg(&gone): record, arg(&below): record }
+    });
+    let file = env.dir.path().join("data/treenav/sizes.json");
+    fs::create_dir_all(file.parent().unwrap()).unwrap();
+    fs::write(&file, cache.to_string()).unwrap();
+
+    let report = env.stdout(&["gc", "--dry-run"]);
+    assert!(
+        report.contains(&format!("size      {}\n", gone.display())),
+        "{}",
+        report
+    );
+    // Only the top of what is gone is listed
+    assert!(!report.contains(arg(&below)), "{}", report);
+    assert!(
+        report.contains("Would remove 2 cached directories"),
+        "{}",
+        report
+    );
+
+    env.stdout(&["gc"]);
+    let saved: serde_json::Value = serde_json::from_slice(&fs::read(&file).unwrap()).unwrap();
+    let dirs = saved["dirs"].as_object().unwrap();
+    assert!(dirs.contains_key(arg(&kept)));
+    assert!(!dirs.contains_key(arg(&gone)));
+    assert!(!dirs.contains_key(arg(&below)));
+}